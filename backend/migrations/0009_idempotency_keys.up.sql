@@ -0,0 +1,12 @@
+CREATE TABLE "idempotency_keys" (
+  "user_id" INTEGER NOT NULL,
+  "key" VARCHAR(255) NOT NULL,
+  "route" VARCHAR(255) NOT NULL,
+  "request_body" JSONB NOT NULL,
+  "response_body" JSONB,
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW()),
+  "completed_at" TIMESTAMP,
+  PRIMARY KEY ("user_id", "key", "route")
+);
+
+ALTER TABLE "idempotency_keys" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id");
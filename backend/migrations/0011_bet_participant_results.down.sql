@@ -0,0 +1 @@
+ALTER TABLE "bet_participants" DROP COLUMN "won";
@@ -0,0 +1 @@
+ALTER TABLE "bet_participants" ADD COLUMN "won" BOOLEAN DEFAULT NULL;
@@ -0,0 +1 @@
+ALTER TABLE "bet_participants" DROP CONSTRAINT "bet_amount_positive";
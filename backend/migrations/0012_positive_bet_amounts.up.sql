@@ -0,0 +1,6 @@
+-- Stakes weren't checked before this, so the constraint only applies to new
+-- rows until the ones with a non-positive stake are removed. Scores they
+-- counted towards can be rebuilt with `bwf-admin recompute-scores`.
+ALTER TABLE "bet_participants" ADD CONSTRAINT "bet_amount_positive" CHECK ("bet_amount" > 0) NOT VALID;
+DELETE FROM "bet_participants" WHERE "bet_amount" <= 0;
+ALTER TABLE "bet_participants" VALIDATE CONSTRAINT "bet_amount_positive";
@@ -0,0 +1,11 @@
+ALTER TABLE "bets" DROP COLUMN "winning_option_id";
+
+-- Options other than Yes count as No
+ALTER TABLE "bet_participants" ADD COLUMN "for_bet" BOOLEAN;
+UPDATE "bet_participants" SET "for_bet" = ("bet_options"."label" = 'Yes')
+FROM "bet_options"
+WHERE "bet_options"."id" = "bet_participants"."option_id";
+ALTER TABLE "bet_participants" ALTER COLUMN "for_bet" SET NOT NULL;
+ALTER TABLE "bet_participants" DROP COLUMN "option_id";
+
+DROP TABLE "bet_options";
@@ -0,0 +1,36 @@
+CREATE TABLE "bet_options" (
+  "id" SERIAL PRIMARY KEY,
+  "bet_id" INTEGER NOT NULL,
+  "label" TEXT NOT NULL,
+  UNIQUE ("bet_id", "label")
+);
+
+ALTER TABLE "bet_options" ADD FOREIGN KEY ("bet_id") REFERENCES "bets" ("id");
+
+-- Every existing bet was a yes/no bet
+INSERT INTO "bet_options" ("bet_id", "label")
+SELECT "id", "label" FROM "bets", (VALUES ('Yes'), ('No')) AS "labels" ("label")
+ORDER BY "id";
+
+ALTER TABLE "bet_participants" ADD COLUMN "option_id" INTEGER;
+ALTER TABLE "bet_participants" ADD FOREIGN KEY ("option_id") REFERENCES "bet_options" ("id");
+
+UPDATE "bet_participants" SET "option_id" = "bet_options"."id"
+FROM "bet_options"
+WHERE "bet_options"."bet_id" = "bet_participants"."bet_id"
+AND "bet_options"."label" = CASE WHEN "for_bet" THEN 'Yes' ELSE 'No' END;
+
+ALTER TABLE "bet_participants" ALTER COLUMN "option_id" SET NOT NULL;
+ALTER TABLE "bet_participants" DROP COLUMN "for_bet";
+
+ALTER TABLE "bets" ADD COLUMN "winning_option_id" INTEGER;
+ALTER TABLE "bets" ADD FOREIGN KEY ("winning_option_id") REFERENCES "bet_options" ("id");
+
+-- Paid out bets: winners picked the winning option, losers picked the other one
+UPDATE "bets" SET "winning_option_id" = "winning"."id"
+FROM "bet_participants"
+JOIN "bet_options" AS "picked" ON "picked"."id" = "bet_participants"."option_id"
+JOIN "bet_options" AS "winning" ON "winning"."bet_id" = "picked"."bet_id"
+WHERE "bet_participants"."bet_id" = "bets"."id"
+AND "bet_participants"."won" IS NOT NULL
+AND ("winning"."id" = "picked"."id") = "bet_participants"."won";
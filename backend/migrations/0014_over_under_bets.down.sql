@@ -0,0 +1,7 @@
+ALTER TABLE "bets" DROP CONSTRAINT "over_under_line";
+
+ALTER TABLE "bets" DROP COLUMN "actual_value";
+ALTER TABLE "bets" DROP COLUMN "line";
+ALTER TABLE "bets" DROP COLUMN "kind";
+
+DROP TYPE "bet_kind";
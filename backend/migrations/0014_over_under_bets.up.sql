@@ -0,0 +1,10 @@
+CREATE TYPE "bet_kind" AS ENUM (
+  'options',
+  'over_under'
+);
+
+ALTER TABLE "bets" ADD COLUMN "kind" bet_kind NOT NULL DEFAULT 'options';
+ALTER TABLE "bets" ADD COLUMN "line" DOUBLE PRECISION;
+ALTER TABLE "bets" ADD COLUMN "actual_value" DOUBLE PRECISION;
+
+ALTER TABLE "bets" ADD CONSTRAINT "over_under_line" CHECK (("kind" = 'over_under') = ("line" IS NOT NULL));
@@ -0,0 +1,15 @@
+-- Postgres can't drop an enum value, so the type is rebuilt without it.
+-- Cancelled bets become finished bets that were never paid out.
+UPDATE "bets" SET "status" = 'finished' WHERE "status" = 'cancelled';
+
+ALTER TYPE "bet_status" RENAME TO "bet_status_old";
+
+CREATE TYPE "bet_status" AS ENUM (
+  'active',
+  'finished',
+  'payed_out'
+);
+
+ALTER TABLE "bets" ALTER COLUMN "status" TYPE "bet_status" USING "status"::TEXT::"bet_status";
+
+DROP TYPE "bet_status_old";
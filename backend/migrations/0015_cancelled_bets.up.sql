@@ -0,0 +1 @@
+ALTER TYPE "bet_status" ADD VALUE 'cancelled';
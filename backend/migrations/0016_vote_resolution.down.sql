@@ -0,0 +1,13 @@
+DROP TABLE "bet_votes";
+
+ALTER TABLE "bets" DROP CONSTRAINT "vote_rules";
+
+ALTER TABLE "bets" DROP COLUMN "disputed_at";
+ALTER TABLE "bets" DROP COLUMN "disputed_by";
+ALTER TABLE "bets" DROP COLUMN "proposed_at";
+ALTER TABLE "bets" DROP COLUMN "proposed_option_id";
+ALTER TABLE "bets" DROP COLUMN "dispute_window_seconds";
+ALTER TABLE "bets" DROP COLUMN "quorum";
+ALTER TABLE "bets" DROP COLUMN "resolution";
+
+DROP TYPE "bet_resolution";
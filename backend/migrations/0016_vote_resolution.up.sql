@@ -0,0 +1,32 @@
+CREATE TYPE "bet_resolution" AS ENUM (
+  'creator',
+  'vote'
+);
+
+ALTER TABLE "bets" ADD COLUMN "resolution" bet_resolution NOT NULL DEFAULT 'creator';
+ALTER TABLE "bets" ADD COLUMN "quorum" INTEGER;
+ALTER TABLE "bets" ADD COLUMN "dispute_window_seconds" INTEGER;
+ALTER TABLE "bets" ADD COLUMN "proposed_option_id" INTEGER;
+ALTER TABLE "bets" ADD COLUMN "proposed_at" TIMESTAMP;
+ALTER TABLE "bets" ADD COLUMN "disputed_by" INTEGER;
+ALTER TABLE "bets" ADD COLUMN "disputed_at" TIMESTAMP;
+
+ALTER TABLE "bets" ADD FOREIGN KEY ("proposed_option_id") REFERENCES "bet_options" ("id");
+ALTER TABLE "bets" ADD FOREIGN KEY ("disputed_by") REFERENCES "users" ("id");
+
+ALTER TABLE "bets" ADD CONSTRAINT "vote_rules" CHECK (
+  ("resolution" = 'vote') = ("quorum" IS NOT NULL AND "dispute_window_seconds" IS NOT NULL)
+  AND "quorum" > 0
+  AND "dispute_window_seconds" >= 0
+);
+
+CREATE TABLE "bet_votes" (
+  "bet_id" INTEGER,
+  "user_id" INTEGER,
+  "option_id" INTEGER NOT NULL,
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW()),
+  PRIMARY KEY ("bet_id", "user_id")
+);
+
+ALTER TABLE "bet_votes" ADD FOREIGN KEY ("bet_id", "user_id") REFERENCES "bet_participants" ("bet_id", "user_id");
+ALTER TABLE "bet_votes" ADD FOREIGN KEY ("option_id") REFERENCES "bet_options" ("id");
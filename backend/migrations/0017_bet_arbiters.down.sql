@@ -0,0 +1,6 @@
+ALTER TABLE "bets" DROP CONSTRAINT "arbiter";
+
+ALTER TABLE "bets" DROP COLUMN "arbiter_status";
+ALTER TABLE "bets" DROP COLUMN "arbiter_id";
+
+DROP TYPE "arbiter_status";
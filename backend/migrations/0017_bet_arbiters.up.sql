@@ -0,0 +1,15 @@
+CREATE TYPE "arbiter_status" AS ENUM (
+  'pending',
+  'accepted',
+  'declined'
+);
+
+ALTER TABLE "bets" ADD COLUMN "arbiter_id" INTEGER;
+ALTER TABLE "bets" ADD COLUMN "arbiter_status" arbiter_status;
+
+ALTER TABLE "bets" ADD FOREIGN KEY ("arbiter_id") REFERENCES "users" ("id");
+
+ALTER TABLE "bets" ADD CONSTRAINT "arbiter" CHECK (
+  ("arbiter_id" IS NULL) = ("arbiter_status" IS NULL)
+  AND "arbiter_id" <> "creator_id"
+);
@@ -0,0 +1,5 @@
+DROP TABLE "bet_invitations";
+
+ALTER TABLE "bets" DROP COLUMN "invite_only";
+
+DROP TYPE "invitation_status";
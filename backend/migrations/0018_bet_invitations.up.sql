@@ -0,0 +1,19 @@
+CREATE TYPE "invitation_status" AS ENUM (
+  'invited',
+  'accepted',
+  'declined'
+);
+
+ALTER TABLE "bets" ADD COLUMN "invite_only" BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE "bet_invitations" (
+  "bet_id" INTEGER,
+  "user_id" INTEGER,
+  "status" invitation_status NOT NULL DEFAULT 'invited',
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW()),
+  "responded_at" TIMESTAMP DEFAULT NULL,
+  PRIMARY KEY ("bet_id", "user_id")
+);
+
+ALTER TABLE "bet_invitations" ADD FOREIGN KEY ("bet_id") REFERENCES "bets" ("id");
+ALTER TABLE "bet_invitations" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id");
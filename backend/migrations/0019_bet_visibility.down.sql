@@ -0,0 +1,8 @@
+DROP FUNCTION "bet_visible_to";
+
+-- Friends-only bets become invite-only, the closest level that hides them
+ALTER TABLE "bets" ADD COLUMN "invite_only" BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE "bets" SET "invite_only" = TRUE WHERE "visibility" <> 'public';
+ALTER TABLE "bets" DROP COLUMN "visibility";
+
+DROP TYPE "bet_visibility";
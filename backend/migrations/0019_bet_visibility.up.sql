@@ -0,0 +1,35 @@
+CREATE TYPE "bet_visibility" AS ENUM (
+  'public',
+  'friends',
+  'private'
+);
+
+ALTER TABLE "bets" ADD COLUMN "visibility" bet_visibility NOT NULL DEFAULT 'public';
+UPDATE "bets" SET "visibility" = 'private' WHERE "invite_only";
+ALTER TABLE "bets" DROP COLUMN "invite_only";
+
+-- Whether the user with id viewer_id can see the bet, NULL viewers only see
+-- public bets
+CREATE FUNCTION "bet_visible_to"("bet" bets, "viewer_id" INTEGER)
+RETURNS BOOLEAN AS $$
+  SELECT COALESCE(
+    bet.visibility = 'public'
+    OR bet.creator_id = viewer_id
+    OR bet.arbiter_id = viewer_id
+    OR EXISTS (
+      SELECT 1 FROM bet_participants
+      WHERE bet_participants.bet_id = bet.id AND bet_participants.user_id = viewer_id
+    )
+    OR EXISTS (
+      SELECT 1 FROM bet_invitations
+      WHERE bet_invitations.bet_id = bet.id AND bet_invitations.user_id = viewer_id
+      AND bet_invitations.status <> 'declined'
+    )
+    OR (bet.visibility = 'friends' AND EXISTS (
+      SELECT 1 FROM friendships
+      WHERE friendships.user_id = bet.creator_id AND friendships.friend_id = viewer_id
+      AND friendships.status = 'accepted'
+    )),
+    FALSE
+  )
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,7 @@
+DROP TABLE "bet_versions";
+
+ALTER TABLE "bet_options" DROP CONSTRAINT "bet_options_bet_id_label_key";
+ALTER TABLE "bet_options" ADD CONSTRAINT "bet_options_bet_id_label_key" UNIQUE ("bet_id", "label");
+
+ALTER TABLE "bet_participants" DROP COLUMN "confirmed_version";
+ALTER TABLE "bets" DROP COLUMN "version";
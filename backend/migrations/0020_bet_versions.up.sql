@@ -0,0 +1,26 @@
+ALTER TABLE "bets" ADD COLUMN "version" INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE "bet_participants" ADD COLUMN "confirmed_version" INTEGER NOT NULL DEFAULT 1;
+
+-- Edits relabel options in place, so two labels may swap within a transaction
+ALTER TABLE "bet_options" DROP CONSTRAINT "bet_options_bet_id_label_key";
+ALTER TABLE "bet_options" ADD CONSTRAINT "bet_options_bet_id_label_key"
+  UNIQUE ("bet_id", "label") DEFERRABLE INITIALLY DEFERRED;
+
+CREATE TABLE "bet_versions" (
+  "bet_id" INTEGER,
+  "version" INTEGER,
+  "description" TEXT NOT NULL,
+  "stop_bets_at" TIMESTAMP,
+  "options" TEXT[] NOT NULL,
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW()),
+  PRIMARY KEY ("bet_id", "version")
+);
+
+ALTER TABLE "bet_versions" ADD FOREIGN KEY ("bet_id") REFERENCES "bets" ("id");
+
+-- Every existing bet is at its first version
+INSERT INTO "bet_versions" ("bet_id", "version", "description", "stop_bets_at", "options", "created_at")
+SELECT "id", 1, "description", "stop_bets_at",
+  ARRAY(SELECT "label" FROM "bet_options" WHERE "bet_id" = "bets"."id" ORDER BY "id"),
+  "created_at"
+FROM "bets";
@@ -0,0 +1,3 @@
+DROP TABLE "bet_participant_changes";
+
+DROP TYPE "participant_action";
@@ -0,0 +1,25 @@
+CREATE TYPE "participant_action" AS ENUM (
+  'joined',
+  'switched',
+  'restaked',
+  'withdrew'
+);
+
+CREATE TABLE "bet_participant_changes" (
+  "id" SERIAL PRIMARY KEY,
+  "bet_id" INTEGER NOT NULL,
+  "user_id" INTEGER NOT NULL,
+  "action" participant_action NOT NULL,
+  "option_id" INTEGER NOT NULL,
+  "bet_amount" INTEGER NOT NULL,
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW())
+);
+
+ALTER TABLE "bet_participant_changes" ADD FOREIGN KEY ("bet_id") REFERENCES "bets" ("id");
+ALTER TABLE "bet_participant_changes" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id");
+ALTER TABLE "bet_participant_changes" ADD FOREIGN KEY ("option_id") REFERENCES "bet_options" ("id");
+
+-- Existing participants never changed their position, so joining is all there is to record
+INSERT INTO "bet_participant_changes" ("bet_id", "user_id", "action", "option_id", "bet_amount")
+SELECT "bet_id", "user_id", 'joined', "option_id", "bet_amount" FROM "bet_participants"
+ORDER BY "bet_id", "user_id";
@@ -0,0 +1,5 @@
+DROP TABLE "bet_template_runs";
+DROP TABLE "bet_template_invitees";
+DROP TABLE "bet_templates";
+
+DROP TYPE "recurrence";
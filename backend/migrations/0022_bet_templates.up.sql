@@ -0,0 +1,47 @@
+CREATE TYPE "recurrence" AS ENUM (
+  'daily',
+  'weekly',
+  'cron'
+);
+
+CREATE TABLE "bet_templates" (
+  "id" SERIAL PRIMARY KEY,
+  "creator_id" INTEGER NOT NULL,
+  "description" TEXT NOT NULL,
+  "options" TEXT[] NOT NULL,
+  "visibility" bet_visibility NOT NULL DEFAULT 'public',
+  "recurrence" recurrence NOT NULL,
+  "cron" TEXT,
+  "starts_at" TIMESTAMP NOT NULL,
+  "cutoff_seconds" INTEGER,
+  "next_run_at" TIMESTAMP,
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW()),
+  CONSTRAINT "schedule" CHECK (
+    ("recurrence" = 'cron') = ("cron" IS NOT NULL)
+    AND "cutoff_seconds" > 0
+  )
+);
+
+ALTER TABLE "bet_templates" ADD FOREIGN KEY ("creator_id") REFERENCES "users" ("id");
+
+CREATE INDEX ON "bet_templates" ("next_run_at");
+
+CREATE TABLE "bet_template_invitees" (
+  "template_id" INTEGER,
+  "user_id" INTEGER,
+  PRIMARY KEY ("template_id", "user_id")
+);
+
+ALTER TABLE "bet_template_invitees" ADD FOREIGN KEY ("template_id") REFERENCES "bet_templates" ("id");
+ALTER TABLE "bet_template_invitees" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id");
+
+-- One bet per occurrence, so catching up on missed runs never creates duplicates
+CREATE TABLE "bet_template_runs" (
+  "template_id" INTEGER,
+  "occurrence_at" TIMESTAMP,
+  "bet_id" INTEGER NOT NULL UNIQUE,
+  PRIMARY KEY ("template_id", "occurrence_at")
+);
+
+ALTER TABLE "bet_template_runs" ADD FOREIGN KEY ("template_id") REFERENCES "bet_templates" ("id");
+ALTER TABLE "bet_template_runs" ADD FOREIGN KEY ("bet_id") REFERENCES "bets" ("id");
@@ -0,0 +1,6 @@
+DROP FUNCTION "bet_matches";
+
+ALTER TABLE "bets" DROP COLUMN "tags";
+ALTER TABLE "bets" DROP COLUMN "category_id";
+
+DROP TABLE "categories";
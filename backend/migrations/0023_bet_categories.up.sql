@@ -0,0 +1,34 @@
+-- Curated categories, new ones are added by migrations
+CREATE TABLE "categories" (
+  "id" SERIAL PRIMARY KEY,
+  "name" TEXT NOT NULL UNIQUE,
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW())
+);
+
+INSERT INTO "categories" ("name") VALUES
+  ('Sports'),
+  ('Esports'),
+  ('Politics'),
+  ('Entertainment'),
+  ('Weather'),
+  ('Personal'),
+  ('Other');
+
+ALTER TABLE "bets" ADD COLUMN "category_id" INTEGER;
+ALTER TABLE "bets" ADD FOREIGN KEY ("category_id") REFERENCES "categories" ("id");
+ALTER TABLE "bets" ADD COLUMN "tags" TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX ON "bets" ("category_id");
+CREATE INDEX ON "bets" USING GIN ("tags");
+
+-- Whether the bet is filed under the category named category_name and has the
+-- tag, NULL filters match every bet
+CREATE FUNCTION "bet_matches"("bet" bets, "category_name" TEXT, "tag" TEXT)
+RETURNS BOOLEAN AS $$
+  SELECT (
+    category_name IS NULL
+    OR bet.category_id = (SELECT id FROM categories WHERE name = category_name)
+  ) AND (
+    tag IS NULL OR bet.tags @> ARRAY[tag]
+  )
+$$ LANGUAGE SQL STABLE;
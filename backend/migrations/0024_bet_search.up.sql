@@ -0,0 +1,5 @@
+-- Bets have no separate title, their description is what people remember
+ALTER TABLE "bets" ADD COLUMN "search" tsvector
+  GENERATED ALWAYS AS (to_tsvector('english', "description")) STORED;
+
+CREATE INDEX ON "bets" USING GIN ("search");
@@ -0,0 +1,43 @@
+CREATE OR REPLACE FUNCTION "bet_visible_to"("bet" bets, "viewer_id" INTEGER)
+RETURNS BOOLEAN AS $$
+  SELECT COALESCE(
+    bet.visibility = 'public'
+    OR bet.creator_id = viewer_id
+    OR bet.arbiter_id = viewer_id
+    OR EXISTS (
+      SELECT 1 FROM bet_participants
+      WHERE bet_participants.bet_id = bet.id AND bet_participants.user_id = viewer_id
+    )
+    OR EXISTS (
+      SELECT 1 FROM bet_invitations
+      WHERE bet_invitations.bet_id = bet.id AND bet_invitations.user_id = viewer_id
+      AND bet_invitations.status <> 'declined'
+    )
+    OR (bet.visibility = 'friends' AND EXISTS (
+      SELECT 1 FROM friendships
+      WHERE friendships.user_id = bet.creator_id AND friendships.friend_id = viewer_id
+      AND friendships.status = 'accepted'
+    )),
+    FALSE
+  )
+$$ LANGUAGE SQL STABLE;
+
+DROP TABLE "bet_challenges";
+DROP TYPE "challenge_status";
+
+-- Postgres can't drop an enum value, so the type is rebuilt without it.
+-- Challenges nobody answered are called off.
+UPDATE "bets" SET "status" = 'cancelled' WHERE "status" = 'pending';
+
+ALTER TYPE "bet_status" RENAME TO "bet_status_old";
+
+CREATE TYPE "bet_status" AS ENUM (
+  'active',
+  'finished',
+  'payed_out',
+  'cancelled'
+);
+
+ALTER TABLE "bets" ALTER COLUMN "status" TYPE "bet_status" USING "status"::TEXT::"bet_status";
+
+DROP TYPE "bet_status_old";
@@ -0,0 +1,56 @@
+ALTER TYPE "bet_status" ADD VALUE 'pending';
+
+CREATE TYPE "challenge_status" AS ENUM (
+  'pending',
+  'accepted',
+  'declined',
+  'expired'
+);
+
+-- A one-on-one bet: the challenger created the bet and joined one side, the
+-- challenged friend takes the other side at the same stake by accepting
+CREATE TABLE "bet_challenges" (
+  "bet_id" INTEGER PRIMARY KEY,
+  "challenger_id" INTEGER NOT NULL,
+  "challenged_id" INTEGER NOT NULL,
+  "status" challenge_status NOT NULL DEFAULT 'pending',
+  "expires_at" TIMESTAMP NOT NULL,
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW()),
+  "responded_at" TIMESTAMP DEFAULT NULL
+);
+
+ALTER TABLE "bet_challenges" ADD FOREIGN KEY ("bet_id") REFERENCES "bets" ("id");
+ALTER TABLE "bet_challenges" ADD FOREIGN KEY ("challenger_id") REFERENCES "users" ("id");
+ALTER TABLE "bet_challenges" ADD FOREIGN KEY ("challenged_id") REFERENCES "users" ("id");
+
+CREATE INDEX ON "bet_challenges" ("challenged_id");
+CREATE INDEX ON "bet_challenges" ("status", "expires_at");
+
+-- The challenged friend can see the challenge without being invited
+CREATE OR REPLACE FUNCTION "bet_visible_to"("bet" bets, "viewer_id" INTEGER)
+RETURNS BOOLEAN AS $$
+  SELECT COALESCE(
+    bet.visibility = 'public'
+    OR bet.creator_id = viewer_id
+    OR bet.arbiter_id = viewer_id
+    OR EXISTS (
+      SELECT 1 FROM bet_participants
+      WHERE bet_participants.bet_id = bet.id AND bet_participants.user_id = viewer_id
+    )
+    OR EXISTS (
+      SELECT 1 FROM bet_invitations
+      WHERE bet_invitations.bet_id = bet.id AND bet_invitations.user_id = viewer_id
+      AND bet_invitations.status <> 'declined'
+    )
+    OR EXISTS (
+      SELECT 1 FROM bet_challenges
+      WHERE bet_challenges.bet_id = bet.id AND bet_challenges.challenged_id = viewer_id
+    )
+    OR (bet.visibility = 'friends' AND EXISTS (
+      SELECT 1 FROM friendships
+      WHERE friendships.user_id = bet.creator_id AND friendships.friend_id = viewer_id
+      AND friendships.status = 'accepted'
+    )),
+    FALSE
+  )
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,4 @@
+ALTER TABLE "bets" DROP CONSTRAINT "bets_stake_limits_check";
+ALTER TABLE "bets" DROP COLUMN "max_participants";
+ALTER TABLE "bets" DROP COLUMN "max_stake";
+ALTER TABLE "bets" DROP COLUMN "min_stake";
@@ -0,0 +1,9 @@
+-- Optional rules set by the creator, checked whenever someone joins or restakes
+ALTER TABLE "bets" ADD COLUMN "min_stake" INTEGER;
+ALTER TABLE "bets" ADD COLUMN "max_stake" INTEGER;
+ALTER TABLE "bets" ADD COLUMN "max_participants" INTEGER;
+
+ALTER TABLE "bets" ADD CONSTRAINT "bets_stake_limits_check" CHECK (
+  "min_stake" > 0 AND "max_stake" > 0 AND "max_participants" > 0
+  AND "min_stake" <= "max_stake"
+);
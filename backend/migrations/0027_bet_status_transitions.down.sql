@@ -0,0 +1,4 @@
+DROP TRIGGER "check_bet_status_update" ON bets;
+DROP TRIGGER "check_bet_status_insert" ON bets;
+DROP FUNCTION check_bet_status_transition();
+DROP TABLE "bet_status_transitions";
@@ -0,0 +1,50 @@
+-- Mirrors `BetStatus::INITIAL` and `BetStatus::TRANSITIONS`, new statuses are
+-- added to both. Rows without a "from_status" are the statuses bets can be
+-- created with.
+CREATE TABLE "bet_status_transitions" (
+  "from_status" bet_status,
+  "to_status" bet_status NOT NULL,
+  UNIQUE NULLS NOT DISTINCT ("from_status", "to_status")
+);
+
+INSERT INTO "bet_status_transitions" ("from_status", "to_status") VALUES
+  (NULL, 'active'),
+  (NULL, 'pending'),
+  ('pending', 'active'),
+  ('pending', 'cancelled'),
+  ('active', 'finished'),
+  ('active', 'cancelled'),
+  ('finished', 'payed_out'),
+  ('finished', 'cancelled'),
+  ('payed_out', 'cancelled');
+
+CREATE FUNCTION check_bet_status_transition()
+RETURNS TRIGGER AS $$
+DECLARE
+  old_status bet_status;
+BEGIN
+  IF TG_OP = 'UPDATE' THEN
+    old_status := OLD.status;
+  END IF;
+  IF NOT EXISTS (
+    SELECT 1 FROM "bet_status_transitions" AS transitions
+    WHERE transitions.from_status IS NOT DISTINCT FROM old_status
+    AND transitions.to_status = NEW.status
+  ) THEN
+    RAISE EXCEPTION 'bet % can''t go from % to %', NEW.id, COALESCE(old_status::TEXT, 'nothing'), NEW.status
+      USING ERRCODE = 'check_violation';
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER check_bet_status_insert
+BEFORE INSERT ON bets
+FOR EACH ROW
+EXECUTE FUNCTION check_bet_status_transition();
+
+CREATE TRIGGER check_bet_status_update
+BEFORE UPDATE OF status ON bets
+FOR EACH ROW
+WHEN (OLD.status IS DISTINCT FROM NEW.status)
+EXECUTE FUNCTION check_bet_status_transition();
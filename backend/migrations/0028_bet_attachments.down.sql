@@ -0,0 +1 @@
+DROP TABLE "bet_attachments";
@@ -0,0 +1,21 @@
+-- Evidence for a bet's settlement. Files are kept in attachment storage under
+-- "storage_key", links only have a "url".
+CREATE TABLE "bet_attachments" (
+  "id" SERIAL PRIMARY KEY,
+  "bet_id" INTEGER NOT NULL,
+  "uploader_id" INTEGER NOT NULL,
+  "content_type" TEXT NOT NULL,
+  "size_bytes" INTEGER NOT NULL CHECK ("size_bytes" >= 0),
+  "url" TEXT,
+  "storage_key" TEXT GENERATED ALWAYS AS (
+    CASE WHEN "url" IS NULL THEN 'bets/' || "bet_id" || '/' || "id" END
+  ) STORED,
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW()),
+  -- Set when the bet is paid out with this attachment as evidence
+  "settled_at" TIMESTAMP DEFAULT NULL
+);
+
+ALTER TABLE "bet_attachments" ADD FOREIGN KEY ("bet_id") REFERENCES "bets" ("id");
+ALTER TABLE "bet_attachments" ADD FOREIGN KEY ("uploader_id") REFERENCES "users" ("id");
+
+CREATE INDEX ON "bet_attachments" ("bet_id");
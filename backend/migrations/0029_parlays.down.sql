@@ -0,0 +1,3 @@
+DROP TABLE "parlay_legs";
+DROP TABLE "parlays";
+DROP TYPE "parlay_status";
@@ -0,0 +1,38 @@
+CREATE TYPE "parlay_status" AS ENUM (
+  'open',
+  'won',
+  'lost',
+  'cancelled'
+);
+
+-- One position over several bets that wins only if every leg wins
+CREATE TABLE "parlays" (
+  "id" SERIAL PRIMARY KEY,
+  "user_id" INTEGER NOT NULL,
+  "stake" INTEGER NOT NULL CHECK ("stake" > 0),
+  "status" parlay_status NOT NULL DEFAULT 'open',
+  -- Points earned once won, the stake for every leg that won
+  "points" INTEGER DEFAULT NULL,
+  "created_at" TIMESTAMP NOT NULL DEFAULT (NOW()),
+  "settled_at" TIMESTAMP DEFAULT NULL
+);
+
+CREATE TABLE "parlay_legs" (
+  "parlay_id" INTEGER NOT NULL,
+  "bet_id" INTEGER NOT NULL,
+  "option_id" INTEGER NOT NULL,
+  -- The bet's version when picked, legs picked before a later edit are pushed
+  "version" INTEGER NOT NULL,
+  -- NULL until the bet is paid out, and for pushed legs after that
+  "won" BOOLEAN DEFAULT NULL,
+  "settled_at" TIMESTAMP DEFAULT NULL,
+  PRIMARY KEY ("parlay_id", "bet_id")
+);
+
+ALTER TABLE "parlays" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id");
+ALTER TABLE "parlay_legs" ADD FOREIGN KEY ("parlay_id") REFERENCES "parlays" ("id");
+ALTER TABLE "parlay_legs" ADD FOREIGN KEY ("bet_id") REFERENCES "bets" ("id");
+ALTER TABLE "parlay_legs" ADD FOREIGN KEY ("option_id") REFERENCES "bet_options" ("id");
+
+CREATE INDEX ON "parlays" ("user_id");
+CREATE INDEX ON "parlay_legs" ("bet_id");
@@ -0,0 +1,2 @@
+-- Keys are deleted once they expire, see "KEY_TTL"
+CREATE INDEX "idempotency_keys_created_at_idx" ON "idempotency_keys" ("created_at");
@@ -0,0 +1,7 @@
+-- Escapes text for HTML, so search snippets only carry the markup they add
+-- around matches
+CREATE FUNCTION "html_escape"("text" TEXT)
+RETURNS TEXT AS $$
+  SELECT replace(replace(replace(replace(replace(
+    text, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'), '''', '&#39;')
+$$ LANGUAGE SQL IMMUTABLE STRICT;
@@ -0,0 +1,199 @@
+use bet_with_friends::{telemetry, AllResult, Bet, BetStatus, Config, Score, User, MIGRATOR};
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{migrate::Migrate, PgPool};
+
+/// Operations tool for Bet with Friends. Every command prints JSON to stdout.
+#[derive(Parser)]
+#[command(name = "bwf-admin")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a user with a default score
+    CreateUser {
+        #[arg(long)]
+        username: String,
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        password: String,
+    },
+    /// Stop a user from creating, joining or managing bets
+    SuspendUser {
+        #[arg(long)]
+        username: String,
+    },
+    /// Lift a suspension
+    UnsuspendUser {
+        #[arg(long)]
+        username: String,
+    },
+    /// List active bets past their cutoff and finished bets that were never paid out
+    StuckBets {
+        /// How long a bet may stay finished before it counts as stuck
+        #[arg(long, default_value_t = 24)]
+        older_than_hours: i64,
+    },
+    /// Close an active bet, whoever created it
+    CloseBet {
+        #[arg(long)]
+        bet_id: i32,
+    },
+    /// Close the bet if needed, then pay it out to the given option, or with
+    /// the actual value for over/under bets
+    SettleBet {
+        #[arg(long)]
+        bet_id: i32,
+        #[arg(long, required_unless_present = "actual_value")]
+        winning_option_id: Option<i32>,
+        #[arg(
+            long,
+            conflicts_with = "winning_option_id",
+            allow_negative_numbers = true
+        )]
+        actual_value: Option<f64>,
+    },
+    /// Call off a bet at any time, taking back its results if it was paid out
+    VoidBet {
+        #[arg(long)]
+        bet_id: i32,
+    },
+    /// Rebuild every score from the results of paid out bets
+    RecomputeScores,
+    /// Run or revert database migrations
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateCommand {
+    /// Apply every pending migration
+    Run,
+    /// Revert the latest reversible migration
+    Revert,
+}
+
+#[tokio::main]
+async fn main() -> AllResult<()> {
+    let cli = Cli::parse();
+    telemetry::install_logger();
+    let config = Config::from_env()?;
+    let connection = PgPool::connect(&config.database_url).await?;
+
+    match cli.command {
+        Command::CreateUser {
+            username,
+            email,
+            password,
+        } => print(&User::new(&connection, username, email, password).await?),
+        Command::SuspendUser { username } => {
+            let mut user = User::read_from_name(&connection, &username).await?;
+            user.suspend(&connection).await?;
+            print(&user)
+        }
+        Command::UnsuspendUser { username } => {
+            let mut user = User::read_from_name(&connection, &username).await?;
+            user.unsuspend(&connection).await?;
+            print(&user)
+        }
+        Command::StuckBets { older_than_hours } => {
+            let now = sqlx::types::chrono::Local::now().naive_local();
+            let finished_before = now - chrono::TimeDelta::hours(older_than_hours);
+            print(&Bet::read_stuck(&connection, finished_before).await?)
+        }
+        Command::CloseBet { bet_id } => {
+            let mut bet = Bet::read_by_id(&connection, bet_id).await?;
+            if bet.status != BetStatus::Active {
+                return Err(format!("bet {bet_id} is not active").into());
+            }
+            bet.close(&connection).await?;
+            print(&bet)
+        }
+        Command::SettleBet {
+            bet_id,
+            winning_option_id,
+            actual_value,
+        } => {
+            let mut bet = Bet::read_by_id(&connection, bet_id).await?;
+            if bet.status == BetStatus::Active {
+                bet.close(&connection).await?;
+            }
+            if bet.status != BetStatus::Finished {
+                return Err(format!("bet {bet_id} was already paid out or cancelled").into());
+            }
+            match (winning_option_id, actual_value) {
+                (Some(winning_option_id), _) => bet.payout(&connection, winning_option_id).await?,
+                (None, Some(actual_value)) => {
+                    bet.settle_over_under(&connection, actual_value).await?
+                }
+                (None, None) => unreachable!("clap requires one of them"),
+            }
+            print(&bet)
+        }
+        Command::VoidBet { bet_id } => {
+            let mut bet = Bet::read_by_id(&connection, bet_id).await?;
+            bet.force_void(&connection).await?;
+            print(&bet)
+        }
+        Command::RecomputeScores => print(&Score::recompute_all(&connection).await?),
+        Command::Migrate { command } => match command {
+            MigrateCommand::Run => {
+                MIGRATOR.run(&connection).await?;
+                print(&json!({ "applied": applied_versions(&connection).await? }))
+            }
+            MigrateCommand::Revert => {
+                let reverted = revert_latest(&connection).await?;
+                print(&json!({ "reverted": reverted }))
+            }
+        },
+    }
+}
+
+fn print(value: &impl Serialize) -> AllResult<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+async fn applied_versions(connection: &PgPool) -> AllResult<Vec<i64>> {
+    let mut connection = connection.acquire().await?;
+    connection.ensure_migrations_table().await?;
+    let mut versions: Vec<i64> = connection
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+    versions.sort();
+    Ok(versions)
+}
+
+/// Reverts the newest applied migration that has a down script. Returns its
+/// version, or `None` if there is nothing that can be reverted.
+async fn revert_latest(connection: &PgPool) -> AllResult<Option<i64>> {
+    let applied = applied_versions(connection).await?;
+    let latest = MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .filter(|version| applied.contains(version))
+        .max();
+    let Some(latest) = latest else {
+        return Ok(None);
+    };
+
+    let target = applied
+        .iter()
+        .copied()
+        .filter(|version| *version < latest)
+        .max()
+        .unwrap_or(0);
+    MIGRATOR.undo(connection, target).await?;
+    Ok(Some(latest))
+}
@@ -0,0 +1,33 @@
+use std::{env, time::Duration};
+
+use crate::{storage::StorageConfig, AllResult};
+
+/// Settings shared by the server and the admin tool
+pub struct Config {
+    pub database_url: String,
+    /// How often the server closes bets past their cutoff,
+    /// `SCHEDULER_INTERVAL_SECONDS`, 60 by default
+    pub scheduler_interval: Duration,
+    /// Where attachments are kept, see `StorageConfig::from_env`
+    pub storage: StorageConfig,
+}
+
+impl Config {
+    /// Loads `.env` if there is one, then reads the environment
+    pub fn from_env() -> AllResult<Self> {
+        if let Err(error) = dotenvy::dotenv() {
+            if !error.not_found() {
+                return Err(error.into());
+            }
+        }
+        let scheduler_interval = match env::var("SCHEDULER_INTERVAL_SECONDS") {
+            Ok(seconds) => Duration::from_secs(seconds.parse()?),
+            Err(_) => Duration::from_secs(60),
+        };
+        Ok(Config {
+            database_url: env::var("DATABASE_URL")?,
+            scheduler_interval,
+            storage: StorageConfig::from_env()?,
+        })
+    }
+}
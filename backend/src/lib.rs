@@ -0,0 +1,28 @@
+//! Bet with Friends: users, friendships and bets stored in Postgres, and the
+//! HTTP API built on top of them.
+//!
+//! Every model method takes the `PgPool` to run against. Run [`MIGRATOR`]
+//! before using the models on a new database.
+
+mod config;
+mod models;
+mod router;
+pub mod scheduler;
+pub mod storage;
+pub mod telemetry;
+
+pub use config::Config;
+pub use models::{
+    ArbiterStatus, Bet, BetAttachment, BetChallenge, BetEdit, BetError, BetFilter, BetInvitation,
+    BetKind, BetLabels, BetMatch, BetOption, BetParticipant, BetParticipantChange, BetResolution,
+    BetSchedule, BetSettings, BetStatus, BetTemplate, BetVersion, BetVisibility, BetVote, Category,
+    CategoryScore, ChallengeStatus, ChallengeTerms, Friendship, FriendshipStatus, InvitationStatus,
+    Parlay, ParlayLeg, ParlayPick, ParlayStatus, ParticipantAction, Recurrence, Score, StakeLimits,
+    User, VoteResolution, MAX_ATTACHMENT_BYTES, MAX_PARLAY_LEGS, MAX_TAGS, MAX_TAG_LENGTH,
+};
+pub use router::create_router;
+
+pub type AllResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Migrations for the schema the models expect
+pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!();
@@ -1,19 +1,30 @@
-mod models;
-mod router;
+use std::sync::Arc;
 
-use std::env;
-
-type AllResult<T> = Result<T, Box<dyn std::error::Error>>;
+use bet_with_friends::{
+    create_router,
+    scheduler::{self, SystemClock},
+    telemetry, AllResult, Config, MIGRATOR,
+};
 
 #[tokio::main]
 async fn main() -> AllResult<()> {
-    dotenvy::dotenv()?;
-    let url = env::var("DATABASE_URL")?;
-    let connection = sqlx::postgres::PgPool::connect(&url).await?;
+    telemetry::install_logger();
+    let config = Config::from_env()?;
+    let connection = telemetry::pool_options()
+        .connect(&config.database_url)
+        .await?;
+    let metrics = telemetry::install_recorder()?;
+
+    MIGRATOR.run(&connection).await?;
 
-    sqlx::migrate!().run(&connection).await?;
+    scheduler::spawn(
+        connection.clone(),
+        Arc::new(SystemClock),
+        config.scheduler_interval,
+    );
 
-    let app: axum::Router = router::create_router(connection);
+    let storage = config.storage.open()?;
+    let app: axum::Router = create_router(connection, metrics, storage);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
     axum::serve(listener, app).await.unwrap();
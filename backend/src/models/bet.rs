@@ -1,18 +1,104 @@
 use super::{
-    repositories::{bet_participants, bets},
-    BetParticipant,
+    repositories::{
+        bet_attachments, bet_invitations, bet_options, bet_participant_changes, bet_participants,
+        bet_versions, bet_votes, bets, categories,
+    },
+    BetAttachment, BetInvitation, BetOption, BetParticipant, BetParticipantChange, BetVersion,
+    BetVote, User,
 };
-use crate::AllResult;
-use serde::Serialize;
+use crate::{storage::Storage, AllResult};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use sqlx::{types::chrono::NaiveDateTime, PgPool};
+use std::fmt;
 
-#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize)]
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
 #[sqlx(type_name = "bet_status", rename_all = "lowercase")]
 pub enum BetStatus {
     Active,
     Finished,
     #[sqlx(rename = "payed_out")]
     PayedOut,
+    /// Called off, every stake went back and no result was recorded
+    Cancelled,
+    /// A challenge waiting for the challenged friend, see `BetChallenge`
+    Pending,
+}
+
+impl BetStatus {
+    /// The statuses bets are created with
+    pub const INITIAL: [BetStatus; 2] = [BetStatus::Active, BetStatus::Pending];
+
+    /// Every status change a bet can go through. The database rejects any
+    /// other change with the `bet_status_transitions` table, which has to be
+    /// kept in sync with this one.
+    pub const TRANSITIONS: [(BetStatus, BetStatus); 7] = [
+        (BetStatus::Pending, BetStatus::Active),
+        (BetStatus::Pending, BetStatus::Cancelled),
+        (BetStatus::Active, BetStatus::Finished),
+        (BetStatus::Active, BetStatus::Cancelled),
+        (BetStatus::Finished, BetStatus::PayedOut),
+        (BetStatus::Finished, BetStatus::Cancelled),
+        // Voided after the payout
+        (BetStatus::PayedOut, BetStatus::Cancelled),
+    ];
+
+    pub fn can_become(self, next: BetStatus) -> bool {
+        BetStatus::TRANSITIONS.contains(&(self, next))
+    }
+}
+
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize)]
+#[sqlx(type_name = "bet_kind", rename_all = "snake_case")]
+pub enum BetKind {
+    /// Participants pick one of the bet's options
+    Options,
+    /// Participants pick Over or Under a numeric line, and the creator
+    /// settles with the actual value
+    OverUnder,
+}
+
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize)]
+#[sqlx(type_name = "bet_resolution", rename_all = "lowercase")]
+pub enum BetResolution {
+    /// The creator pays the bet out
+    Creator,
+    /// Participants vote on the outcome, see `VoteResolution`
+    Vote,
+}
+
+/// Whether the user named as a bet's arbiter took the role
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize)]
+#[sqlx(type_name = "arbiter_status", rename_all = "lowercase")]
+pub enum ArbiterStatus {
+    Pending,
+    /// The arbiter closes and pays out the bet instead of the creator
+    Accepted,
+    Declined,
+}
+
+/// Who can see and join a bet. The creator, the arbiter, participants and
+/// invitees who didn't decline can always see it.
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[sqlx(type_name = "bet_visibility", rename_all = "lowercase")]
+pub enum BetVisibility {
+    /// Everyone, public bets appear in discovery
+    #[default]
+    Public,
+    /// The creator's accepted friends
+    Friends,
+    /// Only friends the creator invited, see `Bet::invite`
+    Private,
+}
+
+/// Rules for bets resolved by participant votes. Once `quorum` participants
+/// vote for the same option it is proposed as the outcome, and the bet is paid
+/// out if nobody disputes it within `dispute_window_seconds`. Disputed bets
+/// are escalated and paid out by hand, see `Bet::manager_id`.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct VoteResolution {
+    pub quorum: i32,
+    pub dispute_window_seconds: i32,
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize)]
@@ -26,29 +112,480 @@ pub struct Bet {
     pub updated_at: NaiveDateTime,
     pub paid_out: bool,
     pub paid_out_at: Option<NaiveDateTime>,
+    /// Set when the bet is paid out, stays empty when an over/under bet
+    /// pushes
+    pub winning_option_id: Option<i32>,
+    pub kind: BetKind,
+    /// Set on over/under bets
+    pub line: Option<f64>,
+    /// The value an over/under bet was settled with
+    pub actual_value: Option<f64>,
+    pub resolution: BetResolution,
+    /// Set on bets resolved by vote
+    pub quorum: Option<i32>,
+    /// Set on bets resolved by vote
+    pub dispute_window_seconds: Option<i32>,
+    /// The option that reached the quorum
+    pub proposed_option_id: Option<i32>,
+    pub proposed_at: Option<NaiveDateTime>,
+    /// The participant who disputed the proposed outcome
+    pub disputed_by: Option<i32>,
+    /// Set when the bet is escalated
+    pub disputed_at: Option<NaiveDateTime>,
+    /// A user who doesn't participate and judges the bet, see `manager_id`
+    pub arbiter_id: Option<i32>,
+    pub arbiter_status: Option<ArbiterStatus>,
+    pub visibility: BetVisibility,
+    /// Bumped every time the bet is edited after someone joined, see
+    /// `Bet::edit`
+    pub version: i32,
+    /// One of the curated categories, see `Category`
+    pub category_id: Option<i32>,
+    /// Free-form, lowercase tags
+    pub tags: Vec<String>,
+    /// Smallest stake a participant can put on the bet, see `StakeLimits`
+    pub min_stake: Option<i32>,
+    /// Largest stake a participant can put on the bet
+    pub max_stake: Option<i32>,
+    /// How many people can join the bet
+    pub max_participants: Option<i32>,
+}
+
+/// A bet found by `Bet::search`
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BetMatch {
+    #[serde(flatten)]
+    pub bet: Bet,
+    /// How well the bet matches, higher is better
+    pub rank: f32,
+    /// The matching part of the description as HTML-escaped text, with
+    /// matched words in `<b>` tags
+    pub snippet: String,
+}
+
+/// Changes to a bet's terms, fields left out stay as they are. Options are
+/// matched to the bet's options in order: labels can change, new ones are
+/// added at the end and trailing ones are dropped.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize)]
+pub struct BetEdit {
+    pub description: Option<String>,
+    pub stop_bets_at: Option<NaiveDateTime>,
+    pub options: Option<Vec<String>>,
+}
+
+/// How a bet is filed: one of the curated categories, by name, and free-form
+/// tags. Tags are normalized, see `categories::normalize_tags`.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct BetLabels {
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
+impl BetLabels {
+    /// Fails with `BetError::UnknownCategory` or `BetError::InvalidTags` like
+    /// `Bet::label` would, without labelling anything
+    pub async fn validate(&self, connection: &PgPool) -> AllResult<()> {
+        categories::resolve_labels(connection, self).await?;
+        Ok(())
+    }
+}
+
+/// Rules the creator sets on who can join and how much they can stake, limits
+/// left out don't apply. They are checked when someone joins or restakes, so
+/// lowering them doesn't affect people who already joined.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct StakeLimits {
+    pub min_stake: Option<i32>,
+    pub max_stake: Option<i32>,
+    pub max_participants: Option<i32>,
+}
+
+impl StakeLimits {
+    /// Fails with `BetError::InvalidLimits` like `Bet::limit` would, without
+    /// limiting anything
+    pub fn validate(&self) -> Result<(), BetError> {
+        bets::validate_limits(self)
+    }
+}
+
+/// Who can see a new bet, how it's filed and what it can be staked, all
+/// written with the bet itself so it's never open without them
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct BetSettings {
+    /// Defaults to public
+    #[serde(default)]
+    pub visibility: BetVisibility,
+    #[serde(flatten)]
+    pub labels: BetLabels,
+    #[serde(flatten)]
+    pub limits: StakeLimits,
+}
+
+/// Narrows bet listings down, filters left out match every bet
+#[derive(Debug, PartialEq, Clone, Default, Deserialize)]
+pub struct BetFilter {
+    /// A category name, unknown categories match no bet
+    pub category: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BetError {
+    /// The bet was changed by someone else since it was read
+    Stale,
+    /// The bet is closed or paid out
+    NotActive,
+    /// The bet's `stop_bets_at` has passed
+    CutoffPassed,
+    /// Stakes must be positive
+    InvalidStake,
+    /// The bet has to be closed before it is paid out
+    NotFinished,
+    /// The bet was already paid out
+    AlreadyPaidOut,
+    /// Bets need at least two distinct options
+    InvalidOptions,
+    /// The option isn't one of the bet's options
+    UnknownOption,
+    /// Lines and actual values must be finite numbers
+    InvalidLine,
+    /// The bet is settled some other way, by option or by actual value
+    WrongKind,
+    /// The bet was called off
+    Cancelled,
+    /// Quorums must be positive and dispute windows can't be negative
+    InvalidVoteRules,
+    /// Only bets resolved by vote take votes and disputes
+    NotVoting,
+    /// The bet is resolved by participant votes unless it is disputed
+    AwaitingVotes,
+    /// Only participants can vote and dispute
+    NotParticipant,
+    /// An outcome was already proposed or the bet was escalated
+    VotingClosed,
+    /// There is no proposed outcome to dispute, or the dispute window passed
+    NotDisputable,
+    /// The arbiter can't be the creator or a participant
+    InvalidArbiter,
+    /// The user isn't the bet's pending arbiter
+    NotArbiter,
+    /// The arbiter already accepted and can't be replaced
+    ArbiterAccepted,
+    /// The bet's arbiter can't take part in it
+    ArbiterCannotJoin,
+    /// Only private bets take invitations
+    NotPrivate,
+    /// Only the creator's accepted friends can be invited
+    NotFriend,
+    /// The user was already invited and didn't decline
+    AlreadyInvited,
+    /// The user has no pending or accepted invitation to the bet
+    NotInvited,
+    /// The bet is friends-only and the user isn't a friend of the creator
+    FriendsOnly,
+    /// Edits can't remove an option a participant picked
+    OptionPicked,
+    /// Template schedules need a valid cron expression for cron recurrence
+    /// only, and a positive cutoff
+    InvalidSchedule,
+    /// There is no category with that name
+    UnknownCategory,
+    /// Tags are short words made of letters, digits, `-` and `_`, and bets
+    /// have a limited number of them
+    InvalidTags,
+    /// Challenges need exactly two options, and have to expire in the future
+    /// but no later than the cutoff
+    InvalidChallenge,
+    /// The user wasn't challenged with this bet
+    NotChallenged,
+    /// The challenge was already answered, expired or called off
+    ChallengeClosed,
+    /// Limits must be positive and the minimum stake can't exceed the maximum
+    InvalidLimits,
+    /// The stake is below the bet's `min_stake`
+    StakeTooLow,
+    /// The stake is above the bet's `max_stake`
+    StakeTooHigh,
+    /// The bet already has `max_participants` participants
+    BetFull,
+    /// The bet's status can't change this way, see `BetStatus::TRANSITIONS`
+    InvalidTransition,
+    /// Attachments are at most `MAX_ATTACHMENT_BYTES`
+    AttachmentTooLarge,
+    /// Attachments are PNG, JPEG, GIF or WebP images whose content matches
+    /// their declared type, or links
+    UnsupportedContentType,
+    /// Links are a single http or https URL
+    InvalidLink,
+    /// Parlays combine 2 to `MAX_PARLAY_LEGS` legs on different bets
+    InvalidParlay,
+}
+
+impl fmt::Display for BetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BetError::Stale => write!(f, "bet was modified since it was read"),
+            BetError::NotActive => write!(f, "bet is not accepting participants"),
+            BetError::CutoffPassed => write!(f, "bet stopped accepting participants"),
+            BetError::InvalidStake => write!(f, "bet amount must be positive"),
+            BetError::NotFinished => write!(f, "bet must be closed before it is paid out"),
+            BetError::AlreadyPaidOut => write!(f, "bet was already paid out"),
+            BetError::InvalidOptions => write!(f, "bet needs at least two distinct options"),
+            BetError::UnknownOption => write!(f, "option does not belong to this bet"),
+            BetError::InvalidLine => write!(f, "line must be a finite number"),
+            BetError::WrongKind => write!(f, "bet can't be settled this way"),
+            BetError::Cancelled => write!(f, "bet was cancelled"),
+            BetError::InvalidVoteRules => write!(
+                f,
+                "quorum must be positive and the dispute window can't be negative"
+            ),
+            BetError::NotVoting => write!(f, "bet isn't resolved by vote"),
+            BetError::AwaitingVotes => write!(f, "bet is resolved by participant votes"),
+            BetError::NotParticipant => write!(f, "user isn't participating in this bet"),
+            BetError::VotingClosed => write!(f, "voting on this bet is closed"),
+            BetError::NotDisputable => write!(f, "bet has no outcome open to dispute"),
+            BetError::InvalidArbiter => {
+                write!(f, "arbiter can't be the creator or a participant")
+            }
+            BetError::NotArbiter => write!(f, "user wasn't asked to arbitrate this bet"),
+            BetError::ArbiterAccepted => write!(f, "arbiter already accepted"),
+            BetError::ArbiterCannotJoin => write!(f, "arbiter can't join the bet"),
+            BetError::NotPrivate => write!(f, "bet isn't private"),
+            BetError::NotFriend => write!(f, "user isn't a friend of the creator"),
+            BetError::AlreadyInvited => write!(f, "user was already invited"),
+            BetError::NotInvited => write!(f, "user wasn't invited to this bet"),
+            BetError::FriendsOnly => write!(f, "bet is only open to the creator's friends"),
+            BetError::OptionPicked => write!(f, "option was picked by a participant"),
+            BetError::InvalidSchedule => write!(f, "schedule is invalid"),
+            BetError::UnknownCategory => write!(f, "category does not exist"),
+            BetError::InvalidTags => write!(f, "tags are invalid"),
+            BetError::InvalidChallenge => write!(f, "challenge is invalid"),
+            BetError::NotChallenged => write!(f, "user wasn't challenged with this bet"),
+            BetError::ChallengeClosed => write!(f, "challenge is no longer open"),
+            BetError::InvalidLimits => write!(f, "stake limits are invalid"),
+            BetError::StakeTooLow => write!(f, "bet amount is below the minimum stake"),
+            BetError::StakeTooHigh => write!(f, "bet amount is above the maximum stake"),
+            BetError::BetFull => write!(f, "bet has reached its participant limit"),
+            BetError::InvalidTransition => write!(f, "bet status can't change this way"),
+            BetError::AttachmentTooLarge => write!(f, "attachment is too large"),
+            BetError::UnsupportedContentType => write!(f, "attachment type isn't supported"),
+            BetError::InvalidLink => write!(f, "link is invalid"),
+            BetError::InvalidParlay => write!(f, "parlay legs are invalid"),
+        }
+    }
+}
+
+impl std::error::Error for BetError {}
+
 impl Bet {
+    /// Version tag for the bet, changes every time the bet row is updated
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.updated_at.and_utc().timestamp_micros())
+    }
+
+    /// The user who closes and pays out the bet: the arbiter once they
+    /// accepted, the creator otherwise
+    pub fn manager_id(&self) -> i32 {
+        match (self.arbiter_id, self.arbiter_status) {
+            (Some(arbiter_id), Some(ArbiterStatus::Accepted)) => arbiter_id,
+            _ => self.creator_id,
+        }
+    }
+
+    /// Reads any bet, regardless of visibility. Use `read_visible_by_id` to
+    /// read a bet on someone's behalf.
     pub async fn read_by_id(connection: &PgPool, id: i32) -> AllResult<Bet> {
         bets::get_bet_by_id(connection, id).await
     }
 
+    /// Reads a bet `viewer` can see, bets they can't see are not found.
+    /// Anonymous viewers only see public bets.
+    pub async fn read_visible_by_id(
+        connection: &PgPool,
+        id: i32,
+        viewer: Option<&User>,
+    ) -> AllResult<Bet> {
+        bets::get_visible_bet_by_id(connection, id, viewer).await
+    }
+
+    /// Bets with `status` that `viewer` can see and that match `filter`,
+    /// ordered by id
     pub async fn read_all_by_status(
         connection: &PgPool,
         status: &BetStatus,
+        viewer: Option<&User>,
+        filter: &BetFilter,
+    ) -> AllResult<Vec<Bet>> {
+        bets::get_bets_by_status(connection, status, viewer, filter).await
+    }
+
+    /// At most `limit` bets `viewer` can see whose description matches
+    /// `query`, best matches first, see `bets::search_bets`
+    pub async fn search(
+        connection: &PgPool,
+        query: &str,
+        viewer: Option<&User>,
+        limit: i64,
+    ) -> AllResult<Vec<BetMatch>> {
+        bets::search_bets(connection, query, viewer, limit).await
+    }
+
+    /// Bets that should have been closed or paid out already, see
+    /// `bets::get_stuck_bets`
+    pub async fn read_stuck(
+        connection: &PgPool,
+        finished_before: NaiveDateTime,
     ) -> AllResult<Vec<Bet>> {
-        bets::get_bets_by_status(connection, status).await
+        bets::get_stuck_bets(connection, finished_before).await
+    }
+
+    /// Closes every active bet whose cutoff is at or before `now`, and
+    /// returns them
+    pub async fn close_expired(connection: &PgPool, now: NaiveDateTime) -> AllResult<Vec<Bet>> {
+        bets::close_expired_bets(connection, now).await
+    }
+
+    /// Files an active bet under a category and replaces its tags. Labels
+    /// aren't part of the bet's terms, so this doesn't bump its version.
+    /// Fails with `BetError::Stale` if the bet was updated since it was read.
+    pub async fn label(&mut self, connection: &PgPool, labels: &BetLabels) -> AllResult<()> {
+        bets::label_bet(connection, self, labels).await
+    }
+
+    /// Replaces the bet's stake limits, fails with `BetError::InvalidLimits`
+    /// if they aren't valid, with `BetError::NotActive` once the bet is
+    /// closed and with `BetError::Stale` if it was updated since it was read
+    pub async fn limit(&mut self, connection: &PgPool, limits: &StakeLimits) -> AllResult<()> {
+        bets::limit_bet(connection, self, limits).await
     }
 
     pub async fn close(&mut self, connection: &PgPool) -> AllResult<()> {
         bets::close_bet(connection, self).await
     }
 
-    pub async fn payout(&mut self, connection: &PgPool, bet_outcome: bool) -> AllResult<()> {
-        bets::payout_bet(connection, self, bet_outcome).await
+    pub async fn payout(&mut self, connection: &PgPool, winning_option_id: i32) -> AllResult<()> {
+        bets::payout_bet(connection, self, winning_option_id).await
+    }
+
+    /// Settles an over/under bet. Participants on the winning side win,
+    /// everyone is pushed if `actual_value` is exactly on the line.
+    pub async fn settle_over_under(
+        &mut self,
+        connection: &PgPool,
+        actual_value: f64,
+    ) -> AllResult<()> {
+        bets::settle_over_under_bet(connection, self, actual_value).await
+    }
+
+    /// Calls off a bet that wasn't paid out yet. Every stake goes back and no
+    /// win or loss is recorded.
+    pub async fn void(&mut self, connection: &PgPool) -> AllResult<()> {
+        bets::void_bet(connection, self, false).await
+    }
+
+    /// Like `void`, but also calls off bets that were already paid out by
+    /// taking back the wins and losses they recorded. Only meant for admins.
+    pub async fn force_void(&mut self, connection: &PgPool) -> AllResult<()> {
+        bets::void_bet(connection, self, true).await
+    }
+
+    /// Records `user`'s vote on a closed bet resolved by vote, and proposes
+    /// the option as the outcome once it reaches the quorum
+    pub async fn vote(
+        &mut self,
+        connection: &PgPool,
+        user: &User,
+        option_id: i32,
+    ) -> AllResult<()> {
+        bets::vote_on_bet(connection, self, user, option_id).await
+    }
+
+    /// Contests the proposed outcome, escalating the bet
+    pub async fn dispute(&mut self, connection: &PgPool, user: &User) -> AllResult<()> {
+        bets::dispute_bet(connection, self, user).await
+    }
+
+    /// Pays out every bet whose proposed outcome went undisputed for its
+    /// dispute window as of `now`, and returns them
+    pub async fn settle_voted(connection: &PgPool, now: NaiveDateTime) -> AllResult<Vec<Bet>> {
+        bets::settle_voted_bets(connection, now).await
+    }
+
+    /// Asks `arbiter` to judge the bet. An arbiter who hasn't accepted yet can
+    /// be replaced.
+    pub async fn name_arbiter(&mut self, connection: &PgPool, arbiter: &User) -> AllResult<()> {
+        bets::name_arbiter(connection, self, arbiter).await
+    }
+
+    pub async fn accept_arbiter_role(&mut self, connection: &PgPool, user: &User) -> AllResult<()> {
+        bets::answer_arbiter_role(connection, self, user, ArbiterStatus::Accepted).await
+    }
+
+    pub async fn decline_arbiter_role(
+        &mut self,
+        connection: &PgPool,
+        user: &User,
+    ) -> AllResult<()> {
+        bets::answer_arbiter_role(connection, self, user, ArbiterStatus::Declined).await
+    }
+
+    /// Changes the bet's description, cutoff or options while it is active.
+    /// Once someone joined every edit is a new version, which the other
+    /// participants confirm or withdraw from, see `User::confirm_bet`.
+    pub async fn edit(&mut self, connection: &PgPool, edit: BetEdit) -> AllResult<()> {
+        bets::edit_bet(connection, self, edit).await
+    }
+
+    /// Every version of the bet's terms, oldest first
+    pub async fn versions(&self, connection: &PgPool) -> AllResult<Vec<BetVersion>> {
+        bet_versions::get_bet_versions(connection, self).await
+    }
+
+    /// Attaches proof to the closed bet, see `bet_attachments::create_attachment`
+    pub async fn attach(
+        &self,
+        connection: &PgPool,
+        storage: &dyn Storage,
+        user: &User,
+        content_type: &str,
+        content: Bytes,
+    ) -> AllResult<BetAttachment> {
+        bet_attachments::create_attachment(connection, storage, self, user, content_type, content)
+            .await
+    }
+
+    /// The bet's attachments, oldest first
+    pub async fn attachments(&self, connection: &PgPool) -> AllResult<Vec<BetAttachment>> {
+        bet_attachments::get_attachments(connection, self).await
+    }
+
+    /// Invites one of the creator's friends to a private bet
+    pub async fn invite(&self, connection: &PgPool, friend: &User) -> AllResult<BetInvitation> {
+        bet_invitations::invite_to_bet(connection, self, friend).await
+    }
+
+    pub async fn invitations(&self, connection: &PgPool) -> AllResult<Vec<BetInvitation>> {
+        bet_invitations::get_bet_invitations(connection, self).await
+    }
+
+    pub async fn votes(&self, connection: &PgPool) -> AllResult<Vec<BetVote>> {
+        bet_votes::get_bet_votes(connection, self).await
+    }
+
+    pub async fn options(&self, connection: &PgPool) -> AllResult<Vec<BetOption>> {
+        bet_options::get_bet_options(connection, self).await
     }
 
     pub async fn participants(&self, connection: &PgPool) -> AllResult<Vec<BetParticipant>> {
         bet_participants::get_bet_participants(connection, self).await
     }
+
+    /// Every join, switch, restake and withdrawal on the bet, oldest first
+    pub async fn participant_changes(
+        &self,
+        connection: &PgPool,
+    ) -> AllResult<Vec<BetParticipantChange>> {
+        bet_participant_changes::get_bet_participant_changes(connection, self).await
+    }
 }
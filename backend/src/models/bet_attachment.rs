@@ -0,0 +1,43 @@
+use bytes::Bytes;
+use serde::Serialize;
+use sqlx::{types::chrono::NaiveDateTime, PgPool};
+
+use super::repositories::bet_attachments;
+use crate::{storage::Storage, AllResult};
+
+/// Largest attachment accepted, in bytes
+pub const MAX_ATTACHMENT_BYTES: usize = 5 * 1024 * 1024;
+
+/// Proof attached to a closed bet before it is paid out: a PNG, JPEG, GIF or
+/// WebP image kept in `Storage`, or a link. Everything attached by the time
+/// the bet is paid out is linked to that settlement with `settled_at`.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BetAttachment {
+    pub id: i32,
+    pub bet_id: i32,
+    pub uploader_id: i32,
+    pub content_type: String,
+    pub size_bytes: i32,
+    /// Set on links, see `bet_attachments::LINK_CONTENT_TYPE`
+    pub url: Option<String>,
+    /// Where an uploaded file is kept in `Storage`
+    #[serde(skip)]
+    pub storage_key: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// When the bet was paid out with this attachment as evidence
+    pub settled_at: Option<NaiveDateTime>,
+}
+
+impl BetAttachment {
+    pub async fn read_by_id(connection: &PgPool, id: i32) -> AllResult<BetAttachment> {
+        bet_attachments::get_attachment(connection, id).await
+    }
+
+    /// The uploaded file, links have no content
+    pub async fn content(&self, storage: &dyn Storage) -> AllResult<Option<Bytes>> {
+        match &self.storage_key {
+            Some(key) => Ok(Some(storage.get(key).await?)),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::chrono::NaiveDateTime, PgPool};
+
+use super::{repositories::bet_challenges, Bet};
+use crate::AllResult;
+
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize)]
+#[sqlx(type_name = "challenge_status", rename_all = "lowercase")]
+pub enum ChallengeStatus {
+    Pending,
+    /// The challenged friend took the other side, the bet is active
+    Accepted,
+    Declined,
+    /// Nobody answered before `expires_at`
+    Expired,
+}
+
+/// A one-on-one bet between the bet's creator and one of their friends. The
+/// bet stays pending until the friend accepts, and is called off if they
+/// decline or let the challenge expire.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BetChallenge {
+    pub bet_id: i32,
+    pub challenger_id: i32,
+    pub challenged_id: i32,
+    pub status: ChallengeStatus,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub responded_at: Option<NaiveDateTime>,
+}
+
+/// What the challenger bets on. The challenged friend takes the other option
+/// at the same stake.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct ChallengeTerms {
+    pub description: String,
+    /// Exactly two options, Yes and No when left out
+    pub options: Option<Vec<String>>,
+    /// The label of the option the challenger picks
+    pub side: String,
+    pub amount: i32,
+    /// Defaults to a day from now, or the cutoff if it comes sooner
+    pub expires_at: Option<NaiveDateTime>,
+    pub stop_bets_at: Option<NaiveDateTime>,
+}
+
+impl BetChallenge {
+    pub async fn read_by_bet(connection: &PgPool, bet: &Bet) -> AllResult<BetChallenge> {
+        bet_challenges::get_bet_challenge(connection, bet).await
+    }
+
+    /// Marks every pending challenge that expired at or before `now`, calls
+    /// off their bets and returns them
+    pub async fn expire_due(
+        connection: &PgPool,
+        now: NaiveDateTime,
+    ) -> AllResult<Vec<BetChallenge>> {
+        bet_challenges::expire_challenges(connection, now).await
+    }
+}
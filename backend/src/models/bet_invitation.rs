@@ -0,0 +1,22 @@
+use serde::Serialize;
+use sqlx::types::chrono::NaiveDateTime;
+
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize)]
+#[sqlx(type_name = "invitation_status", rename_all = "lowercase")]
+pub enum InvitationStatus {
+    Invited,
+    /// The invitee can join the bet
+    Accepted,
+    Declined,
+}
+
+/// An invitation from a bet's creator to one of their friends, only invitees
+/// can see and join private bets
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BetInvitation {
+    pub bet_id: i32,
+    pub user_id: i32,
+    pub status: InvitationStatus,
+    pub created_at: NaiveDateTime,
+    pub responded_at: Option<NaiveDateTime>,
+}
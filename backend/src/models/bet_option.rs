@@ -0,0 +1,9 @@
+use serde::Serialize;
+
+/// One of the outcomes participants can pick on a bet
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BetOption {
+    pub id: i32,
+    pub bet_id: i32,
+    pub label: String,
+}
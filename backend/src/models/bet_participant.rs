@@ -3,15 +3,21 @@ use super::{
     User,
 };
 use crate::AllResult;
+use serde::Serialize;
 use sqlx::PgPool;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct BetParticipant {
     pub bet_id: i32,
     pub user_id: i32,
-    pub for_bet: bool,
+    pub option_id: i32,
     pub bet_amount: i32,
     pub paid_out: bool,
+    /// Set when the participant is paid out
+    pub won: Option<bool>,
+    /// The bet version the participant agreed to. Participants who didn't
+    /// confirm the current version get their stake back instead of a result.
+    pub confirmed_version: i32,
 }
 
 impl BetParticipant {
@@ -0,0 +1,28 @@
+use serde::Serialize;
+use sqlx::types::chrono::NaiveDateTime;
+
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize)]
+#[sqlx(type_name = "participant_action", rename_all = "lowercase")]
+pub enum ParticipantAction {
+    Joined,
+    /// Picked another option
+    Switched,
+    /// Changed the stake
+    Restaked,
+    Withdrew,
+}
+
+/// One change to a participant's position. Replaying a bet's changes in order
+/// gives every participant's final position.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BetParticipantChange {
+    pub id: i32,
+    pub bet_id: i32,
+    pub user_id: i32,
+    pub action: ParticipantAction,
+    /// The option picked after the change, or the one withdrawn from
+    pub option_id: i32,
+    /// The stake after the change, or the one withdrawn
+    pub bet_amount: i32,
+    pub created_at: NaiveDateTime,
+}
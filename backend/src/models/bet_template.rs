@@ -0,0 +1,192 @@
+use super::{repositories::bet_templates, Bet, BetError, BetVisibility, User};
+use crate::AllResult;
+use chrono::TimeDelta;
+use serde::{Deserialize, Serialize};
+use sqlx::{types::chrono::NaiveDateTime, PgPool};
+use std::{collections::VecDeque, str::FromStr};
+
+/// The shortest time a cron schedule can leave between two occurrences
+pub const MIN_TEMPLATE_INTERVAL: TimeDelta = TimeDelta::hours(1);
+
+/// How many missed occurrences a template catches up on after downtime, the
+/// older ones are skipped
+pub const MAX_CATCH_UP: usize = 7;
+
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[sqlx(type_name = "recurrence", rename_all = "lowercase")]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    /// Follows `BetSchedule::cron`
+    Cron,
+}
+
+/// When a template creates bets. Daily and weekly schedules start at
+/// `starts_at`, cron schedules at the first time their expression matches
+/// from `starts_at` on. Occurrences that had passed when the template was
+/// created are skipped.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BetSchedule {
+    pub recurrence: Recurrence,
+    /// A cron expression starting with seconds, like `0 0 18 * * Sun`. Set
+    /// for cron schedules only.
+    pub cron: Option<String>,
+    pub starts_at: NaiveDateTime,
+    /// Each bet stops taking participants this long after its occurrence.
+    /// Bets stay open until they are closed by hand without one.
+    pub cutoff_seconds: Option<i32>,
+}
+
+impl BetSchedule {
+    fn cron_schedule(&self) -> Result<Option<cron::Schedule>, BetError> {
+        match (self.recurrence, &self.cron) {
+            (Recurrence::Cron, Some(expression)) => cron::Schedule::from_str(expression)
+                .map(Some)
+                .map_err(|_| BetError::InvalidSchedule),
+            (Recurrence::Daily | Recurrence::Weekly, None) => Ok(None),
+            _ => Err(BetError::InvalidSchedule),
+        }
+    }
+
+    /// The first occurrence at or after `now`, so a template started in the
+    /// past doesn't open bets for occurrences from before it existed.
+    ///
+    /// Fails with `BetError::InvalidSchedule` if the cron expression doesn't
+    /// parse, never matches or matches less than `MIN_TEMPLATE_INTERVAL`
+    /// apart, or if the cutoff isn't positive
+    pub fn first_occurrence(&self, now: NaiveDateTime) -> Result<NaiveDateTime, BetError> {
+        if self.cutoff_seconds.is_some_and(|cutoff| cutoff <= 0) {
+            return Err(BetError::InvalidSchedule);
+        }
+        match self.cron_schedule()? {
+            Some(schedule) => {
+                let before_start = self.starts_at.max(now) - TimeDelta::seconds(1);
+                let first = next_match(&schedule, before_start).ok_or(BetError::InvalidSchedule)?;
+                check_interval(&schedule, first)?;
+                Ok(first)
+            }
+            None => {
+                let mut occurrence = self.starts_at;
+                while occurrence < now {
+                    occurrence = self
+                        .next_occurrence(occurrence)
+                        .ok_or(BetError::InvalidSchedule)?;
+                }
+                Ok(occurrence)
+            }
+        }
+    }
+
+    /// The occurrence after `occurrence`, if the schedule has one
+    pub fn next_occurrence(&self, occurrence: NaiveDateTime) -> Option<NaiveDateTime> {
+        match (self.recurrence, self.cron_schedule()) {
+            (Recurrence::Daily, _) => Some(occurrence + TimeDelta::days(1)),
+            (Recurrence::Weekly, _) => Some(occurrence + TimeDelta::weeks(1)),
+            (Recurrence::Cron, Ok(Some(schedule))) => next_match(&schedule, occurrence),
+            (Recurrence::Cron, _) => None,
+        }
+    }
+
+    /// The occurrences that are due as of `now` from `next_run_at` on, only
+    /// the latest `MAX_CATCH_UP` of them, and the occurrence after those
+    pub fn due_occurrences(
+        &self,
+        next_run_at: Option<NaiveDateTime>,
+        now: NaiveDateTime,
+    ) -> (VecDeque<NaiveDateTime>, Option<NaiveDateTime>) {
+        let mut due = VecDeque::new();
+        let mut next_run_at = next_run_at;
+        while let Some(occurrence) = next_run_at.filter(|occurrence| *occurrence <= now) {
+            if due.len() == MAX_CATCH_UP {
+                due.pop_front();
+            }
+            due.push_back(occurrence);
+            next_run_at = self.next_occurrence(occurrence);
+        }
+        (due, next_run_at)
+    }
+
+    /// When the bet for `occurrence` stops taking participants
+    pub fn cutoff(&self, occurrence: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.cutoff_seconds
+            .map(|cutoff| occurrence + TimeDelta::seconds(cutoff.into()))
+    }
+}
+
+/// Times of day repeat on every day the expression matches, so the gaps over
+/// the day of the first occurrence and into the next one are the shortest
+fn check_interval(schedule: &cron::Schedule, first: NaiveDateTime) -> Result<(), BetError> {
+    let mut occurrence = first;
+    while occurrence < first + TimeDelta::days(2) {
+        let Some(next) = next_match(schedule, occurrence) else {
+            return Ok(());
+        };
+        if next - occurrence < MIN_TEMPLATE_INTERVAL {
+            return Err(BetError::InvalidSchedule);
+        }
+        occurrence = next;
+    }
+    Ok(())
+}
+
+/// Times are matched as wall clock times, like every other timestamp
+fn next_match(schedule: &cron::Schedule, after: NaiveDateTime) -> Option<NaiveDateTime> {
+    schedule
+        .after(&after.and_utc())
+        .next()
+        .map(|next| next.naive_utc())
+}
+
+/// A bet that is created again on every occurrence of its schedule. The
+/// template's invitees are invited to every bet it creates.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BetTemplate {
+    pub id: i32,
+    pub creator_id: i32,
+    pub description: String,
+    pub options: Vec<String>,
+    pub visibility: BetVisibility,
+    pub recurrence: Recurrence,
+    pub cron: Option<String>,
+    pub starts_at: NaiveDateTime,
+    pub cutoff_seconds: Option<i32>,
+    /// The next occurrence without a bet, empty once the template stopped
+    pub next_run_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl BetTemplate {
+    pub fn schedule(&self) -> BetSchedule {
+        BetSchedule {
+            recurrence: self.recurrence,
+            cron: self.cron.clone(),
+            starts_at: self.starts_at,
+            cutoff_seconds: self.cutoff_seconds,
+        }
+    }
+
+    pub async fn read_by_id(connection: &PgPool, id: i32) -> AllResult<BetTemplate> {
+        bet_templates::get_bet_template_by_id(connection, id).await
+    }
+
+    /// Creates a bet for every occurrence of every template that is due as of
+    /// `now`, including up to `MAX_CATCH_UP` occurrences missed while nothing
+    /// was running, and returns them
+    pub async fn create_due_bets(connection: &PgPool, now: NaiveDateTime) -> AllResult<Vec<Bet>> {
+        bet_templates::create_due_bets(connection, now).await
+    }
+
+    /// Stops creating bets, the ones already created are left alone
+    pub async fn stop(&mut self, connection: &PgPool) -> AllResult<()> {
+        bet_templates::stop_bet_template(connection, self).await
+    }
+
+    pub async fn invitees(&self, connection: &PgPool) -> AllResult<Vec<User>> {
+        bet_templates::get_template_invitees(connection, self).await
+    }
+
+    /// The bets the template created, oldest first
+    pub async fn bets(&self, connection: &PgPool) -> AllResult<Vec<Bet>> {
+        bet_templates::get_template_bets(connection, self).await
+    }
+}
@@ -0,0 +1,14 @@
+use serde::Serialize;
+use sqlx::types::chrono::NaiveDateTime;
+
+/// A bet's terms as of one of its versions
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BetVersion {
+    pub bet_id: i32,
+    pub version: i32,
+    pub description: String,
+    pub stop_bets_at: Option<NaiveDateTime>,
+    /// Option labels, in order
+    pub options: Vec<String>,
+    pub created_at: NaiveDateTime,
+}
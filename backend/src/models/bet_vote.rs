@@ -0,0 +1,11 @@
+use serde::Serialize;
+use sqlx::types::chrono::NaiveDateTime;
+
+/// A participant's vote on the outcome of a bet resolved by vote
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct BetVote {
+    pub bet_id: i32,
+    pub user_id: i32,
+    pub option_id: i32,
+    pub created_at: NaiveDateTime,
+}
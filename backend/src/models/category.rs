@@ -0,0 +1,30 @@
+use serde::Serialize;
+use sqlx::{types::chrono::NaiveDateTime, PgPool};
+
+use super::repositories::categories;
+use crate::AllResult;
+
+/// Bets can have at most this many tags
+pub const MAX_TAGS: usize = 10;
+/// Tags can be at most this many characters long
+pub const MAX_TAG_LENGTH: usize = 32;
+
+/// One of the curated categories bets can be filed under. Categories are
+/// added by migrations, users pick from them.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Category {
+    pub id: i32,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl Category {
+    /// Every category, ordered by id
+    pub async fn read_all(connection: &PgPool) -> AllResult<Vec<Category>> {
+        categories::get_categories(connection).await
+    }
+
+    pub async fn read_by_name(connection: &PgPool, name: &str) -> AllResult<Category> {
+        categories::get_category_by_name(connection, name).await
+    }
+}
@@ -19,7 +19,7 @@ pub struct Friendship {
 }
 
 impl Friendship {
-    async fn read_from_users(
+    pub async fn read_from_users(
         connection: &PgPool,
         sender: &User,
         recipient: &User,
@@ -0,0 +1,67 @@
+use super::{repositories::idempotency_keys, User};
+use crate::AllResult;
+use serde_json::Value;
+use sqlx::{types::chrono::NaiveDateTime, PgPool};
+use std::time::Duration;
+
+/// How long a claim that never completed counts as still running. After that
+/// the server handling it is assumed to have died, maybe after the request
+/// went through, so it's never run again.
+pub const CLAIM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long keys are kept. Once a key expires it starts a new request.
+pub const KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, PartialEq)]
+pub struct IdempotencyKey {
+    pub user_id: i32,
+    pub key: String,
+    pub route: String,
+    pub request_body: Value,
+    pub response_body: Option<Value>,
+    pub created_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IdempotencyClaim {
+    /// The key is new, the caller should run the request and then `complete`
+    /// it
+    Claimed(IdempotencyKey),
+    /// The key was already used for this request, replay the stored response
+    Completed(Value),
+    /// The first request with this key has not finished yet, and was claimed
+    /// less than `CLAIM_TIMEOUT` ago
+    InProgress,
+    /// The first request with this key never finished, and may or may not
+    /// have gone through
+    Abandoned,
+    /// The key was already used with a different request body
+    Mismatch,
+}
+
+impl IdempotencyKey {
+    pub async fn claim(
+        connection: &PgPool,
+        user: &User,
+        key: &str,
+        route: &str,
+        request_body: Value,
+    ) -> AllResult<IdempotencyClaim> {
+        idempotency_keys::claim_idempotency_key(connection, user, key, route, request_body).await
+    }
+
+    pub async fn complete(&mut self, connection: &PgPool, response_body: Value) -> AllResult<()> {
+        idempotency_keys::complete_idempotency_key(connection, self, response_body).await
+    }
+
+    pub async fn release(self, connection: &PgPool) -> AllResult<()> {
+        idempotency_keys::delete_idempotency_key(connection, &self).await
+    }
+
+    /// Deletes every key created more than `KEY_TTL` before `now`, and returns
+    /// how many were deleted
+    pub async fn purge_expired(connection: &PgPool, now: NaiveDateTime) -> AllResult<u64> {
+        idempotency_keys::purge_expired_idempotency_keys(connection, now).await
+    }
+}
@@ -1,16 +1,39 @@
-#![allow(dead_code)]
-
 mod bet;
+mod bet_attachment;
+mod bet_challenge;
+mod bet_invitation;
+mod bet_option;
 mod bet_participant;
+mod bet_participant_change;
+mod bet_template;
+mod bet_version;
+mod bet_vote;
+mod category;
 mod friendship;
+mod idempotency_key;
+mod parlay;
 mod repositories;
 mod score;
 #[cfg(test)]
 mod tests;
 mod user;
 
-pub use bet::{Bet, BetStatus};
+pub use bet::{
+    ArbiterStatus, Bet, BetEdit, BetError, BetFilter, BetKind, BetLabels, BetMatch, BetResolution,
+    BetSettings, BetStatus, BetVisibility, StakeLimits, VoteResolution,
+};
+pub use bet_attachment::{BetAttachment, MAX_ATTACHMENT_BYTES};
+pub use bet_challenge::{BetChallenge, ChallengeStatus, ChallengeTerms};
+pub use bet_invitation::{BetInvitation, InvitationStatus};
+pub use bet_option::BetOption;
 pub use bet_participant::BetParticipant;
+pub use bet_participant_change::{BetParticipantChange, ParticipantAction};
+pub use bet_template::{BetSchedule, BetTemplate, Recurrence, MIN_TEMPLATE_INTERVAL};
+pub use bet_version::BetVersion;
+pub use bet_vote::BetVote;
+pub use category::{Category, MAX_TAGS, MAX_TAG_LENGTH};
 pub use friendship::{Friendship, FriendshipStatus};
-pub use score::Score;
+pub(crate) use idempotency_key::{IdempotencyClaim, IdempotencyKey, CLAIM_TIMEOUT, KEY_TTL};
+pub use parlay::{Parlay, ParlayLeg, ParlayPick, ParlayStatus, MAX_PARLAY_LEGS};
+pub use score::{CategoryScore, Score};
 pub use user::User;
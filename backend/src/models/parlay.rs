@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::chrono::NaiveDateTime, PgPool};
+
+use super::repositories::parlays;
+use crate::AllResult;
+
+/// Most bets a single parlay can combine
+pub const MAX_PARLAY_LEGS: usize = 10;
+
+#[derive(sqlx::Type, PartialEq, Debug, Clone, Copy, Serialize)]
+#[sqlx(type_name = "parlay_status", rename_all = "lowercase")]
+pub enum ParlayStatus {
+    /// Some legs weren't paid out yet
+    Open,
+    Won,
+    Lost,
+    /// A leg was voided or pushed
+    Cancelled,
+}
+
+/// One position over several bets that wins only if every leg wins. It is
+/// settled when the last leg's bet is paid out: a pushed leg cancels it like
+/// a voided one, and a won parlay earns the stake once for every leg.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Parlay {
+    pub id: i32,
+    pub user_id: i32,
+    pub stake: i32,
+    pub status: ParlayStatus,
+    /// Points earned once won
+    pub points: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub settled_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ParlayLeg {
+    pub parlay_id: i32,
+    pub bet_id: i32,
+    pub option_id: i32,
+    /// Unset until the bet is paid out, and for pushed legs after that
+    pub won: Option<bool>,
+    pub settled_at: Option<NaiveDateTime>,
+}
+
+/// The option picked on one of the bets a parlay combines
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+pub struct ParlayPick {
+    pub bet_id: i32,
+    pub option_id: i32,
+}
+
+impl Parlay {
+    pub async fn legs(&self, connection: &PgPool) -> AllResult<Vec<ParlayLeg>> {
+        parlays::get_parlay_legs(connection, self).await
+    }
+}
@@ -0,0 +1,345 @@
+use bytes::Bytes;
+use sqlx::{types::chrono::NaiveDateTime, PgConnection, PgPool};
+
+use super::bet_participants::get_bet_participant;
+use crate::models::{Bet, BetAttachment, BetError, BetStatus, User, MAX_ATTACHMENT_BYTES};
+use crate::{storage::Storage, telemetry, AllResult};
+
+/// Links are attached as a `text/uri-list` holding a single http or https URL
+pub const LINK_CONTENT_TYPE: &str = "text/uri-list";
+
+const MAX_LINK_LENGTH: usize = 2048;
+
+/// Checks the content against its declared type and returns the URL of
+/// links. Images have to start with their format's signature, so a file
+/// can't be passed off as another type.
+pub fn validate_attachment(content_type: &str, content: &[u8]) -> Result<Option<String>, BetError> {
+    if content.len() > MAX_ATTACHMENT_BYTES {
+        return Err(BetError::AttachmentTooLarge);
+    }
+    let signed = match content_type {
+        LINK_CONTENT_TYPE => return parse_link(content).map(Some),
+        "image/png" => content.starts_with(b"\x89PNG\r\n\x1a\n"),
+        "image/jpeg" => content.starts_with(b"\xff\xd8\xff"),
+        "image/gif" => content.starts_with(b"GIF87a") || content.starts_with(b"GIF89a"),
+        "image/webp" => content.starts_with(b"RIFF") && content.get(8..12) == Some(b"WEBP"),
+        _ => false,
+    };
+    if !signed {
+        return Err(BetError::UnsupportedContentType);
+    }
+    Ok(None)
+}
+
+/// The one URL of a `text/uri-list`, comment lines start with `#`
+fn parse_link(content: &[u8]) -> Result<String, BetError> {
+    let content = std::str::from_utf8(content).map_err(|_| BetError::InvalidLink)?;
+    let mut urls = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    let (Some(url), None) = (urls.next(), urls.next()) else {
+        return Err(BetError::InvalidLink);
+    };
+    let host = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .unwrap_or_default();
+    let printable = url.chars().all(|c| c.is_ascii_graphic());
+    if host.is_empty() || !printable || url.len() > MAX_LINK_LENGTH {
+        return Err(BetError::InvalidLink);
+    }
+    Ok(url.to_string())
+}
+
+/// Attaches proof to a closed bet that wasn't paid out yet. Only the user who
+/// manages the bet and participants can attach. Files are put in `storage`
+/// before the attachment is saved, and deleted again if it can't be.
+///
+/// Fails with `BetError::AttachmentTooLarge`,
+/// `BetError::UnsupportedContentType` or `BetError::InvalidLink` if the
+/// content isn't valid, see `validate_attachment`.
+pub async fn create_attachment(
+    connection: &PgPool,
+    storage: &dyn Storage,
+    bet: &Bet,
+    user: &User,
+    content_type: &str,
+    content: Bytes,
+) -> AllResult<BetAttachment> {
+    let content_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let url = validate_attachment(&content_type, &content)?;
+    let size_bytes = i32::try_from(content.len())?;
+
+    // Checked again once the bet is locked, this keeps uploads to bets and
+    // users that can take them
+    let status = sqlx::query_scalar!(
+        r#"SELECT status AS "status: BetStatus" FROM bets WHERE id = $1"#,
+        bet.id
+    )
+    .fetch_one(connection)
+    .await?;
+    check_attachable(status)?;
+    if user.id != bet.manager_id() {
+        get_bet_participant(connection, bet, user).await?;
+    }
+
+    // The id is taken up front so the file can be put under the key the
+    // row's generated storage_key will hold, without locking the bet for
+    // the upload
+    let id = sqlx::query_scalar!(
+        r#"SELECT nextval(pg_get_serial_sequence('bet_attachments', 'id'))::INTEGER AS "id!""#
+    )
+    .fetch_one(connection)
+    .await?;
+    let storage_key = url.is_none().then(|| format!("bets/{}/{id}", bet.id));
+    if let Some(key) = &storage_key {
+        storage.put(key, content).await?;
+    }
+
+    match insert_attachment(connection, id, bet, user, &content_type, size_bytes, url).await {
+        Ok(attachment) => Ok(attachment),
+        Err(error) => {
+            if let Some(key) = &storage_key {
+                if let Err(delete_error) = storage.delete(key).await {
+                    telemetry::report_failure(
+                        "attachments",
+                        format_args!("unable to delete {key}: {delete_error}"),
+                    );
+                }
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Fails unless attachments can be added to a bet with this status
+fn check_attachable(status: BetStatus) -> Result<(), BetError> {
+    match status {
+        BetStatus::Active | BetStatus::Pending => Err(BetError::NotFinished),
+        BetStatus::PayedOut => Err(BetError::AlreadyPaidOut),
+        BetStatus::Cancelled => Err(BetError::Cancelled),
+        BetStatus::Finished => Ok(()),
+    }
+}
+
+/// Saves the attachment for `create_attachment` if the bet still takes
+/// attachments. The error is `Send` so the file can be deleted after a
+/// failure.
+async fn insert_attachment(
+    connection: &PgPool,
+    id: i32,
+    bet: &Bet,
+    user: &User,
+    content_type: &str,
+    size_bytes: i32,
+    url: Option<String>,
+) -> Result<BetAttachment, Box<dyn std::error::Error + Send + Sync>> {
+    let mut transaction = connection.begin().await?;
+    // Share locked so a concurrent payout waits and settles with this
+    // attachment
+    let status = sqlx::query_scalar!(
+        r#"SELECT status AS "status: BetStatus" FROM bets WHERE id = $1 FOR SHARE"#,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    check_attachable(status)?;
+
+    let attachment = sqlx::query_as!(
+        BetAttachment,
+        r#"
+        INSERT INTO bet_attachments (id, bet_id, uploader_id, content_type, size_bytes, url)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, bet_id, uploader_id, content_type, size_bytes, url, storage_key,
+        created_at, settled_at
+        "#,
+        id,
+        bet.id,
+        user.id,
+        content_type,
+        size_bytes,
+        url
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(attachment)
+}
+
+/// Links everything attached to the bet so far to its payout, inside the
+/// payout's transaction
+pub(super) async fn settle_attachments(
+    connection: &mut PgConnection,
+    bet_id: i32,
+    now: NaiveDateTime,
+) -> AllResult<()> {
+    sqlx::query!(
+        "UPDATE bet_attachments SET settled_at = $1 WHERE bet_id = $2 AND settled_at IS NULL",
+        now,
+        bet_id
+    )
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_attachment(connection: &PgPool, id: i32) -> AllResult<BetAttachment> {
+    let attachment = sqlx::query_as!(
+        BetAttachment,
+        r#"
+        SELECT id, bet_id, uploader_id, content_type, size_bytes, url, storage_key,
+        created_at, settled_at
+        FROM bet_attachments WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(attachment)
+}
+
+/// The bet's attachments, oldest first
+pub async fn get_attachments(connection: &PgPool, bet: &Bet) -> AllResult<Vec<BetAttachment>> {
+    let attachments = sqlx::query_as!(
+        BetAttachment,
+        r#"
+        SELECT id, bet_id, uploader_id, content_type, size_bytes, url, storage_key,
+        created_at, settled_at
+        FROM bet_attachments WHERE bet_id = $1
+        ORDER BY id
+        "#,
+        bet.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(attachments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        bet_options::yes_no_options,
+        bet_participants::create_bet_participant,
+        bets::{close_bet, create_timeless_bet, payout_bet},
+        users::create_users,
+    };
+    use super::*;
+    use crate::storage::LocalStorage;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n rest of the image";
+
+    #[test]
+    fn content_rules() {
+        assert_eq!(validate_attachment("image/png", PNG), Ok(None));
+        assert_eq!(
+            validate_attachment("image/jpeg", PNG),
+            Err(BetError::UnsupportedContentType)
+        );
+        assert_eq!(
+            validate_attachment("application/pdf", b"%PDF-1.7"),
+            Err(BetError::UnsupportedContentType)
+        );
+        let too_large = vec![0; MAX_ATTACHMENT_BYTES + 1];
+        assert_eq!(
+            validate_attachment("image/png", &too_large),
+            Err(BetError::AttachmentTooLarge)
+        );
+
+        let link = b"# the final score\r\nhttps://example.com/scores?game=1\r\n";
+        assert_eq!(
+            validate_attachment(LINK_CONTENT_TYPE, link),
+            Ok(Some(String::from("https://example.com/scores?game=1")))
+        );
+        for link in [
+            &b"ftp://example.com"[..],
+            b"https://",
+            b"https://example.com/a b",
+            b"https://example.com\nhttps://example.org",
+            b"",
+        ] {
+            assert_eq!(
+                validate_attachment(LINK_CONTENT_TYPE, link),
+                Err(BetError::InvalidLink)
+            );
+        }
+    }
+
+    #[sqlx::test]
+    async fn evidence_is_linked_to_the_payout(pool: PgPool) -> AllResult<()> {
+        let root = std::env::temp_dir().join(format!("bwf-evidence-{}", std::process::id()));
+        let storage = LocalStorage::new(&root);
+        let mut users = create_users(&pool, vec!["Bob", "John", "Alice"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        let alice = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        create_bet_participant(&pool, &john, &bet, 10, yes).await?;
+        let png = Bytes::from_static(PNG);
+        let error = create_attachment(&pool, &storage, &bet, &bob, "image/png", png.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotFinished));
+
+        close_bet(&pool, &mut bet).await?;
+        let photo =
+            create_attachment(&pool, &storage, &bet, &bob, "Image/PNG", png.clone()).await?;
+        assert_eq!(photo.content_type, "image/png");
+        assert_eq!(photo.content(&storage).await?, Some(png.clone()));
+        let link = Bytes::from_static(b"https://example.com/scores");
+        let link = create_attachment(&pool, &storage, &bet, &john, LINK_CONTENT_TYPE, link).await?;
+        assert_eq!(link.url.as_deref(), Some("https://example.com/scores"));
+        assert_eq!(link.storage_key, None);
+        let error = create_attachment(&pool, &storage, &bet, &alice, "image/png", png.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotParticipant));
+
+        // Rewinding the id sequence makes the next attachment clash with the
+        // link, so it can't be saved and its file is deleted again
+        sqlx::query_scalar!(
+            "SELECT setval(pg_get_serial_sequence('bet_attachments', 'id'), $1, false)",
+            i64::from(link.id)
+        )
+        .fetch_one(&pool)
+        .await?;
+        assert!(
+            create_attachment(&pool, &storage, &bet, &bob, "image/png", png.clone())
+                .await
+                .is_err()
+        );
+        let clashing_key = format!("bets/{}/{}", bet.id, link.id);
+        assert!(storage.get(&clashing_key).await.is_err());
+        sqlx::query_scalar!(
+            "SELECT setval(pg_get_serial_sequence('bet_attachments', 'id'), $1)",
+            i64::from(link.id)
+        )
+        .fetch_one(&pool)
+        .await?;
+
+        payout_bet(&pool, &mut bet, yes).await?;
+        let attachments = get_attachments(&pool, &bet).await?;
+        assert_eq!(
+            attachments.iter().map(|a| a.id).collect::<Vec<_>>(),
+            [photo.id, link.id]
+        );
+        assert!(attachments
+            .iter()
+            .all(|attachment| attachment.settled_at == bet.paid_out_at));
+        let error = create_attachment(&pool, &storage, &bet, &bob, "image/png", png)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::AlreadyPaidOut));
+
+        tokio::fs::remove_dir_all(root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,401 @@
+use sqlx::{types::chrono::NaiveDateTime, PgConnection, PgPool};
+
+use super::bet_options::{get_bet_options, validate_options};
+use super::bet_participants::{get_bet_participants, insert_bet_participant, void_participant};
+use super::bets::{create_bet_with, transition_bet, yes_no};
+use super::friendships::are_friends;
+use crate::models::{
+    Bet, BetChallenge, BetError, BetSettings, BetStatus, BetVisibility, ChallengeStatus,
+    ChallengeTerms, User,
+};
+use crate::{telemetry, AllResult};
+
+/// How long a challenge waits for an answer unless the challenger says
+/// otherwise
+pub const DEFAULT_EXPIRY_HOURS: i64 = 24;
+
+/// Creates a private, pending bet with the challenger on `terms.side`, and
+/// challenges `challenged` to take the other side.
+///
+/// Fails with `BetError::NotFriend` unless `challenged` is an accepted
+/// friend, and with `BetError::InvalidChallenge` if the bet doesn't have
+/// exactly two options or the challenge would expire in the past or after
+/// the cutoff.
+pub async fn create_challenge(
+    connection: &PgPool,
+    challenger: &User,
+    challenged: &User,
+    terms: &ChallengeTerms,
+) -> AllResult<(Bet, BetChallenge)> {
+    let options = terms.options.clone().unwrap_or_else(yes_no);
+    if options.len() != 2 {
+        return Err(BetError::InvalidChallenge.into());
+    }
+    validate_options(&options)?;
+    let side = options
+        .iter()
+        .position(|label| *label == terms.side)
+        .ok_or(BetError::UnknownOption)?;
+    if terms.amount <= 0 {
+        return Err(BetError::InvalidStake.into());
+    }
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    let default_expiry = now + chrono::TimeDelta::hours(DEFAULT_EXPIRY_HOURS);
+    let expires_at = match (terms.expires_at, terms.stop_bets_at) {
+        (Some(expires_at), _) => expires_at,
+        (None, Some(stop_bets_at)) => default_expiry.min(stop_bets_at),
+        (None, None) => default_expiry,
+    };
+    let expires_after_cutoff = terms
+        .stop_bets_at
+        .is_some_and(|stop_bets_at| expires_at > stop_bets_at);
+    if expires_at <= now || expires_after_cutoff {
+        return Err(BetError::InvalidChallenge.into());
+    }
+
+    let mut transaction = connection.begin().await?;
+    if !are_friends(&mut *transaction, challenger.id, challenged.id).await? {
+        return Err(BetError::NotFriend.into());
+    }
+    let bet = create_bet_with(
+        &mut transaction,
+        challenger.id,
+        terms.description.clone(),
+        terms.stop_bets_at,
+        &options,
+        &BetSettings {
+            visibility: BetVisibility::Private,
+            ..BetSettings::default()
+        },
+        BetStatus::Pending,
+    )
+    .await?;
+    let option = &get_bet_options(&mut *transaction, &bet).await?[side];
+    insert_bet_participant(
+        &mut transaction,
+        bet.id,
+        challenger.id,
+        option.id,
+        terms.amount,
+        bet.version,
+    )
+    .await?;
+    let challenge = sqlx::query_as!(
+        BetChallenge,
+        r#"
+        INSERT INTO bet_challenges (bet_id, challenger_id, challenged_id, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING bet_id, challenger_id, challenged_id, status AS "status: ChallengeStatus",
+        expires_at, created_at, responded_at
+        "#,
+        bet.id,
+        challenger.id,
+        challenged.id,
+        expires_at
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    metrics::counter!(telemetry::BETS_CREATED).increment(1);
+    Ok((bet, challenge))
+}
+
+/// Accepts or declines the challenge `user` got with `bet`. Accepting joins
+/// them on the other option at the challenger's stake and opens the bet,
+/// declining calls the bet off.
+///
+/// Fails with `BetError::NotChallenged` if the challenge went to someone
+/// else, and with `BetError::ChallengeClosed` if it was already answered,
+/// expired or the bet was voided.
+pub async fn answer_challenge(
+    connection: &PgPool,
+    bet: &mut Bet,
+    user: &User,
+    accept: bool,
+) -> AllResult<BetChallenge> {
+    let mut transaction = connection.begin().await?;
+    let current = sqlx::query!(
+        r#"
+        SELECT challenged_id, bet_challenges.status AS "status: ChallengeStatus", expires_at,
+        bets.status AS "bet_status: BetStatus", stop_bets_at
+        FROM bet_challenges JOIN bets ON bets.id = bet_id
+        WHERE bet_id = $1
+        FOR UPDATE
+        "#,
+        bet.id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(BetError::NotChallenged)?;
+    if current.challenged_id != user.id {
+        return Err(BetError::NotChallenged.into());
+    }
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    if current.status != ChallengeStatus::Pending
+        || current.bet_status != BetStatus::Pending
+        || current.expires_at <= now
+    {
+        return Err(BetError::ChallengeClosed.into());
+    }
+
+    let status = if accept {
+        if current
+            .stop_bets_at
+            .is_some_and(|stop_bets_at| stop_bets_at <= now)
+        {
+            return Err(BetError::CutoffPassed.into());
+        }
+        let challenger = get_bet_participants(&mut *transaction, bet)
+            .await?
+            .pop()
+            .ok_or(BetError::NotParticipant)?;
+        let other_option = get_bet_options(&mut *transaction, bet)
+            .await?
+            .into_iter()
+            .find(|option| option.id != challenger.option_id)
+            .ok_or(BetError::InvalidChallenge)?;
+        *bet = transition_bet(&mut transaction, bet.id, BetStatus::Active).await?;
+        insert_bet_participant(
+            &mut transaction,
+            bet.id,
+            user.id,
+            other_option.id,
+            challenger.bet_amount,
+            bet.version,
+        )
+        .await?;
+        ChallengeStatus::Accepted
+    } else {
+        *bet = call_off(&mut transaction, bet.id).await?;
+        ChallengeStatus::Declined
+    };
+    let challenge = sqlx::query_as!(
+        BetChallenge,
+        r#"
+        UPDATE bet_challenges
+        SET status = $1, responded_at = $2
+        WHERE bet_id = $3
+        RETURNING bet_id, challenger_id, challenged_id, status AS "status: ChallengeStatus",
+        expires_at, created_at, responded_at
+        "#,
+        status as _,
+        now,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    if accept {
+        metrics::counter!(telemetry::BET_PARTICIPANTS_JOINED).increment(1);
+    }
+    Ok(challenge)
+}
+
+/// Returns the challenger's stake and cancels the pending bet
+async fn call_off(connection: &mut PgConnection, bet_id: i32) -> AllResult<Bet> {
+    let bet = transition_bet(connection, bet_id, BetStatus::Cancelled).await?;
+    let participants = get_bet_participants(&mut *connection, &bet).await?;
+    for participant in participants {
+        void_participant(connection, participant).await?;
+    }
+    Ok(bet)
+}
+
+/// Challenges being answered are skipped and expire on a later pass
+pub async fn expire_challenges(
+    connection: &PgPool,
+    now: NaiveDateTime,
+) -> AllResult<Vec<BetChallenge>> {
+    let mut transaction = connection.begin().await?;
+    let challenges = sqlx::query_as!(
+        BetChallenge,
+        r#"
+        UPDATE bet_challenges
+        SET status = $1
+        WHERE bet_id IN (
+            SELECT bet_id FROM bet_challenges
+            WHERE status = $2 AND expires_at <= $3
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING bet_id, challenger_id, challenged_id, status AS "status: ChallengeStatus",
+        expires_at, created_at, responded_at
+        "#,
+        ChallengeStatus::Expired as _,
+        ChallengeStatus::Pending as _,
+        now
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    for challenge in &challenges {
+        // The challenger may have voided the bet already
+        let status = sqlx::query_scalar!(
+            r#"SELECT status AS "status: BetStatus" FROM bets WHERE id = $1 FOR UPDATE"#,
+            challenge.bet_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        if status == BetStatus::Pending {
+            call_off(&mut transaction, challenge.bet_id).await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(challenges)
+}
+
+/// Fails with `BetError::NotChallenged` if `bet` isn't a challenge
+pub async fn get_bet_challenge(connection: &PgPool, bet: &Bet) -> AllResult<BetChallenge> {
+    let challenge = sqlx::query_as!(
+        BetChallenge,
+        r#"
+        SELECT bet_id, challenger_id, challenged_id, status AS "status: ChallengeStatus",
+        expires_at, created_at, responded_at
+        FROM bet_challenges WHERE bet_id = $1
+        "#,
+        bet.id
+    )
+    .fetch_optional(connection)
+    .await?
+    .ok_or(BetError::NotChallenged)?;
+    Ok(challenge)
+}
+
+/// Challenges `user` sent or received, newest first
+pub async fn get_challenges_by_user(
+    connection: &PgPool,
+    user: &User,
+) -> AllResult<Vec<BetChallenge>> {
+    let challenges = sqlx::query_as!(
+        BetChallenge,
+        r#"
+        SELECT bet_id, challenger_id, challenged_id, status AS "status: ChallengeStatus",
+        expires_at, created_at, responded_at
+        FROM bet_challenges WHERE challenger_id = $1 OR challenged_id = $1
+        ORDER BY created_at DESC, bet_id DESC
+        "#,
+        user.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(challenges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        bet_options::yes_no_options,
+        bet_participants::{create_bet_participant, get_bet_participant},
+        bets::get_visible_bet_by_id,
+        friendships::{respond_to_friend_request, send_friend_request, FriendRequestResponse},
+        users::create_users,
+    };
+    use super::*;
+
+    fn terms(side: &str) -> ChallengeTerms {
+        ChallengeTerms {
+            description: String::from("I bet you 20 points the printer jams today"),
+            options: None,
+            side: String::from(side),
+            amount: 20,
+            expires_at: None,
+            stop_bets_at: None,
+        }
+    }
+
+    #[sqlx::test]
+    async fn friend_takes_the_other_side(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John", "Stranger"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        let stranger = users.pop().unwrap();
+        send_friend_request(&pool, &bob, &john).await?;
+        respond_to_friend_request(&pool, &john, &bob, FriendRequestResponse::Accept).await?;
+
+        let error = create_challenge(&pool, &bob, &stranger, &terms("Yes"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotFriend));
+        let error = create_challenge(&pool, &bob, &john, &terms("Maybe"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::UnknownOption));
+        let three_options = ChallengeTerms {
+            options: Some(vec!["Red".into(), "Green".into(), "Blue".into()]),
+            ..terms("Red")
+        };
+        let error = create_challenge(&pool, &bob, &john, &three_options)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidChallenge));
+
+        let (mut bet, challenge) = create_challenge(&pool, &bob, &john, &terms("Yes")).await?;
+        assert_eq!(bet.status, BetStatus::Pending);
+        assert_eq!(challenge.status, ChallengeStatus::Pending);
+        assert!(get_visible_bet_by_id(&pool, bet.id, Some(&john))
+            .await
+            .is_ok());
+        assert!(get_visible_bet_by_id(&pool, bet.id, Some(&stranger))
+            .await
+            .is_err());
+        let (yes, no) = yes_no_options(&pool, &bet).await?;
+        let error = create_bet_participant(&pool, &john, &bet, 20, no)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotActive));
+        let error = answer_challenge(&pool, &mut bet, &stranger, true)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotChallenged));
+
+        let challenge = answer_challenge(&pool, &mut bet, &john, true).await?;
+        assert_eq!(challenge.status, ChallengeStatus::Accepted);
+        assert_eq!(bet.status, BetStatus::Active);
+        let bob_side = get_bet_participant(&pool, &bet, &bob).await?;
+        let john_side = get_bet_participant(&pool, &bet, &john).await?;
+        assert_eq!((bob_side.option_id, bob_side.bet_amount), (yes, 20));
+        assert_eq!((john_side.option_id, john_side.bet_amount), (no, 20));
+        let error = answer_challenge(&pool, &mut bet, &john, false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::ChallengeClosed));
+
+        let (mut bet, _) = create_challenge(&pool, &bob, &john, &terms("No")).await?;
+        let challenge = answer_challenge(&pool, &mut bet, &john, false).await?;
+        assert_eq!(challenge.status, ChallengeStatus::Declined);
+        assert_eq!(bet.status, BetStatus::Cancelled);
+        let bob_side = get_bet_participant(&pool, &bet, &bob).await?;
+        assert!(bob_side.paid_out);
+        assert_eq!(bob_side.won, None);
+        assert_eq!(get_challenges_by_user(&pool, &john).await?.len(), 2);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn expiry_comes_before_the_cutoff(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        send_friend_request(&pool, &bob, &john).await?;
+        respond_to_friend_request(&pool, &john, &bob, FriendRequestResponse::Accept).await?;
+
+        let now = sqlx::types::chrono::Local::now().naive_local();
+        let kickoff = now + chrono::TimeDelta::hours(2);
+        let before_kickoff = ChallengeTerms {
+            stop_bets_at: Some(kickoff),
+            ..terms("Yes")
+        };
+        let (bet, challenge) = create_challenge(&pool, &bob, &john, &before_kickoff).await?;
+        assert_eq!(Some(challenge.expires_at), bet.stop_bets_at);
+
+        let after_kickoff = ChallengeTerms {
+            expires_at: Some(kickoff + chrono::TimeDelta::hours(1)),
+            ..before_kickoff
+        };
+        let error = create_challenge(&pool, &bob, &john, &after_kickoff)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidChallenge));
+
+        Ok(())
+    }
+}
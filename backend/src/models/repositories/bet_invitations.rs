@@ -0,0 +1,209 @@
+use sqlx::{PgExecutor, PgPool};
+
+use super::friendships::are_friends;
+use crate::models::{
+    Bet, BetError, BetInvitation, BetStatus, BetVisibility, InvitationStatus, User,
+};
+use crate::AllResult;
+
+/// Invites one of the creator's accepted friends to an active private bet.
+/// Friends who declined can be invited again.
+pub async fn invite_to_bet(
+    connection: &PgPool,
+    bet: &Bet,
+    invitee: &User,
+) -> AllResult<BetInvitation> {
+    let mut transaction = connection.begin().await?;
+
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", visibility AS "visibility: BetVisibility"
+        FROM bets WHERE id = $1
+        FOR SHARE
+        "#,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    if current.visibility != BetVisibility::Private {
+        return Err(BetError::NotPrivate.into());
+    }
+    if current.status != BetStatus::Active {
+        return Err(BetError::NotActive.into());
+    }
+    if !are_friends(&mut *transaction, bet.creator_id, invitee.id).await? {
+        return Err(BetError::NotFriend.into());
+    }
+
+    let bet_invitation = sqlx::query_as!(
+        BetInvitation,
+        r#"
+        INSERT INTO bet_invitations (bet_id, user_id, status)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (bet_id, user_id)
+        DO UPDATE SET status = EXCLUDED.status, created_at = NOW(), responded_at = NULL
+        WHERE bet_invitations.status = $4
+        RETURNING bet_id, user_id, status AS "status: InvitationStatus", created_at, responded_at
+        "#,
+        bet.id,
+        invitee.id,
+        InvitationStatus::Invited as _,
+        InvitationStatus::Declined as _
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(BetError::AlreadyInvited)?;
+
+    transaction.commit().await?;
+    Ok(bet_invitation)
+}
+
+/// Accepts or declines `user`'s pending invitation to `bet`
+pub async fn answer_invitation(
+    connection: &PgPool,
+    bet: &Bet,
+    user: &User,
+    answer: InvitationStatus,
+) -> AllResult<BetInvitation> {
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    let bet_invitation = sqlx::query_as!(
+        BetInvitation,
+        r#"
+        UPDATE bet_invitations
+        SET status = $1, responded_at = $2
+        WHERE bet_id = $3 AND user_id = $4 AND status = $5
+        RETURNING bet_id, user_id, status AS "status: InvitationStatus", created_at, responded_at
+        "#,
+        answer as _,
+        now,
+        bet.id,
+        user.id,
+        InvitationStatus::Invited as _
+    )
+    .fetch_optional(connection)
+    .await?
+    .ok_or(BetError::NotInvited)?;
+    Ok(bet_invitation)
+}
+
+pub async fn get_bet_invitation(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
+    user: &User,
+) -> AllResult<Option<BetInvitation>> {
+    let bet_invitation = sqlx::query_as!(
+        BetInvitation,
+        r#"
+        SELECT bet_id, user_id, status AS "status: InvitationStatus", created_at, responded_at
+        FROM bet_invitations WHERE bet_id = $1 AND user_id = $2
+        "#,
+        bet.id,
+        user.id
+    )
+    .fetch_optional(connection)
+    .await?;
+    Ok(bet_invitation)
+}
+
+pub async fn get_bet_invitations(connection: &PgPool, bet: &Bet) -> AllResult<Vec<BetInvitation>> {
+    let bet_invitations = sqlx::query_as!(
+        BetInvitation,
+        r#"
+        SELECT bet_id, user_id, status AS "status: InvitationStatus", created_at, responded_at
+        FROM bet_invitations WHERE bet_id = $1 ORDER BY created_at
+        "#,
+        bet.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(bet_invitations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        bet_options::yes_no_options,
+        bet_participants::create_bet_participant,
+        bets::{create_bet, create_timeless_bet, get_visible_bet_by_id},
+        friendships::{respond_to_friend_request, send_friend_request, FriendRequestResponse},
+        users::create_users,
+    };
+    use super::*;
+    use crate::models::BetSettings;
+
+    #[sqlx::test]
+    async fn only_accepted_invitees_join_private_bets(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John", "Jane", "Stranger"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        let jane = users.pop().unwrap();
+        let stranger = users.pop().unwrap();
+        for friend in [&john, &jane] {
+            send_friend_request(&pool, &bob, friend).await?;
+            respond_to_friend_request(&pool, friend, &bob, FriendRequestResponse::Accept).await?;
+        }
+
+        let open = create_timeless_bet(&pool, &bob, String::from("open")).await?;
+        let error = invite_to_bet(&pool, &open, &john).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotPrivate));
+
+        let bet = create_bet(
+            &pool,
+            &bob,
+            String::from("private"),
+            None,
+            None,
+            &BetSettings {
+                visibility: BetVisibility::Private,
+                ..BetSettings::default()
+            },
+        )
+        .await?;
+        assert_eq!(bet.visibility, BetVisibility::Private);
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        let error = invite_to_bet(&pool, &bet, &stranger).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotFriend));
+        assert!(get_visible_bet_by_id(&pool, bet.id, Some(&john))
+            .await
+            .is_err());
+        assert!(get_visible_bet_by_id(&pool, bet.id, None).await.is_err());
+        assert!(get_visible_bet_by_id(&pool, bet.id, Some(&bob))
+            .await
+            .is_ok());
+
+        let invitation = invite_to_bet(&pool, &bet, &john).await?;
+        assert_eq!(invitation.status, InvitationStatus::Invited);
+        assert!(get_visible_bet_by_id(&pool, bet.id, Some(&john))
+            .await
+            .is_ok());
+        let error = invite_to_bet(&pool, &bet, &john).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::AlreadyInvited));
+
+        let error = create_bet_participant(&pool, &john, &bet, 10, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotInvited));
+        let invitation = answer_invitation(&pool, &bet, &john, InvitationStatus::Accepted).await?;
+        assert_eq!(invitation.status, InvitationStatus::Accepted);
+        assert!(invitation.responded_at.is_some());
+        create_bet_participant(&pool, &john, &bet, 10, yes).await?;
+
+        invite_to_bet(&pool, &bet, &jane).await?;
+        answer_invitation(&pool, &bet, &jane, InvitationStatus::Declined).await?;
+        assert!(get_visible_bet_by_id(&pool, bet.id, Some(&jane))
+            .await
+            .is_err());
+        let error = answer_invitation(&pool, &bet, &jane, InvitationStatus::Accepted)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotInvited));
+        // Declined friends can be invited again
+        let invitation = invite_to_bet(&pool, &bet, &jane).await?;
+        assert_eq!(invitation.status, InvitationStatus::Invited);
+        assert_eq!(invitation.responded_at, None);
+
+        assert_eq!(get_bet_invitations(&pool, &bet).await?.len(), 2);
+
+        Ok(())
+    }
+}
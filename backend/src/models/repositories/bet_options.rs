@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+
+use sqlx::{PgConnection, PgExecutor};
+
+use crate::models::{Bet, BetError, BetOption};
+use crate::AllResult;
+
+/// Options every bet gets unless it is created with its own
+pub const YES_NO: [&str; 2] = ["Yes", "No"];
+
+/// Options of an over/under bet
+pub const OVER_UNDER: [&str; 2] = ["Over", "Under"];
+
+/// A bet needs at least two options with distinct, non blank labels
+pub fn validate_options(labels: &[String]) -> Result<(), BetError> {
+    let mut seen = HashSet::new();
+    let distinct = labels
+        .iter()
+        .all(|label| !label.trim().is_empty() && seen.insert(label.trim()));
+    if labels.len() < 2 || !distinct {
+        return Err(BetError::InvalidOptions);
+    }
+    Ok(())
+}
+
+pub async fn create_bet_option(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
+    label: &str,
+) -> AllResult<BetOption> {
+    let bet_option = sqlx::query_as!(
+        BetOption,
+        r#"
+        INSERT INTO bet_options (bet_id, label)
+        VALUES ($1, $2)
+        RETURNING *
+        "#,
+        bet.id,
+        label.trim()
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(bet_option)
+}
+
+pub async fn get_bet_options(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
+) -> AllResult<Vec<BetOption>> {
+    let bet_options = sqlx::query_as!(
+        BetOption,
+        r#"
+        SELECT * FROM bet_options WHERE bet_id = $1 ORDER BY id
+        "#,
+        bet.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(bet_options)
+}
+
+/// Gives `bet` the options `labels`, matched to its current options in order.
+/// Matched options are relabeled, extra labels are added and options past the
+/// last label are removed.
+///
+/// Fails with `BetError::OptionPicked` if a participant ever picked an option
+/// that would be removed.
+pub async fn set_bet_options(
+    connection: &mut PgConnection,
+    bet: &Bet,
+    labels: &[String],
+) -> AllResult<()> {
+    let current = get_bet_options(&mut *connection, bet).await?;
+    for (option, label) in current.iter().zip(labels) {
+        sqlx::query!(
+            "UPDATE bet_options SET label = $1 WHERE id = $2",
+            label.trim(),
+            option.id
+        )
+        .execute(&mut *connection)
+        .await?;
+    }
+    for label in labels.iter().skip(current.len()) {
+        create_bet_option(&mut *connection, bet, label).await?;
+    }
+    for option in current.iter().skip(labels.len()) {
+        // Every position ever taken is in the change log, withdrawn ones too
+        let picked = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM bet_participant_changes WHERE option_id = $1
+            ) AS "picked!"
+            "#,
+            option.id
+        )
+        .fetch_one(&mut *connection)
+        .await?;
+        if picked {
+            return Err(BetError::OptionPicked.into());
+        }
+        sqlx::query!("DELETE FROM bet_options WHERE id = $1", option.id)
+            .execute(&mut *connection)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Fails with `BetError::UnknownOption` if the option isn't one of `bet`'s
+pub async fn get_bet_option(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
+    option_id: i32,
+) -> AllResult<BetOption> {
+    let bet_option = sqlx::query_as!(
+        BetOption,
+        r#"
+        SELECT * FROM bet_options WHERE id = $1 AND bet_id = $2
+        "#,
+        option_id,
+        bet.id
+    )
+    .fetch_optional(connection)
+    .await?
+    .ok_or(BetError::UnknownOption)?;
+    Ok(bet_option)
+}
+
+/// Ids of the Yes and No options of a bet created without options
+pub async fn get_bet_option_by_label(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
+    label: &str,
+) -> AllResult<BetOption> {
+    let bet_option = sqlx::query_as!(
+        BetOption,
+        r#"
+        SELECT * FROM bet_options WHERE bet_id = $1 AND label = $2
+        "#,
+        bet.id,
+        label
+    )
+    .fetch_optional(connection)
+    .await?
+    .ok_or(BetError::UnknownOption)?;
+    Ok(bet_option)
+}
+
+#[cfg(test)]
+pub async fn yes_no_options(pool: &sqlx::PgPool, bet: &Bet) -> AllResult<(i32, i32)> {
+    let options = get_bet_options(pool, bet).await?;
+    Ok((options[0].id, options[1].id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        bets::{create_bet, create_timeless_bet},
+        users::create_users,
+    };
+    use super::*;
+    use crate::models::BetSettings;
+    use sqlx::PgPool;
+
+    #[test]
+    fn option_rules() {
+        let labels = |labels: &[&str]| labels.iter().map(|label| label.to_string()).collect();
+        let labels: Vec<Vec<String>> = vec![
+            labels(&[]),
+            labels(&["Alice"]),
+            labels(&["Alice", "Alice"]),
+            labels(&["Alice", " Alice "]),
+            labels(&["Alice", ""]),
+            labels(&["Alice", "   "]),
+        ];
+        for labels in labels {
+            assert_eq!(validate_options(&labels), Err(BetError::InvalidOptions));
+        }
+        assert_eq!(validate_options(&["Alice".into(), "Bob".into()]), Ok(()));
+    }
+
+    #[sqlx::test]
+    async fn bets_get_their_options(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let yes_no = create_timeless_bet(&pool, &bob, String::from("yes or no")).await?;
+        let labels: Vec<_> = get_bet_options(&pool, &yes_no)
+            .await?
+            .into_iter()
+            .map(|option| option.label)
+            .collect();
+        assert_eq!(labels, YES_NO);
+
+        let entrants = vec!["Alice".into(), "Bob".into(), "Carol".into()];
+        let chili = create_bet(
+            &pool,
+            &bob,
+            String::from("chili"),
+            None,
+            Some(&entrants),
+            &BetSettings::default(),
+        )
+        .await?;
+        let options = get_bet_options(&pool, &chili).await?;
+        let labels: Vec<_> = options.iter().map(|option| option.label.clone()).collect();
+        assert_eq!(labels, entrants);
+
+        assert_eq!(
+            get_bet_option(&pool, &chili, options[1].id).await?,
+            options[1]
+        );
+        let yes = get_bet_options(&pool, &yes_no).await?[0].id;
+        let error = get_bet_option(&pool, &chili, yes).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::UnknownOption));
+
+        let error = create_bet(
+            &pool,
+            &bob,
+            String::from("bad"),
+            None,
+            Some(&["Alice".into()]),
+            &BetSettings::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidOptions));
+
+        Ok(())
+    }
+}
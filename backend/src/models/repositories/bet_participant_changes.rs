@@ -0,0 +1,49 @@
+use sqlx::{PgExecutor, PgPool};
+
+use crate::models::{Bet, BetParticipant, BetParticipantChange, ParticipantAction};
+use crate::AllResult;
+
+/// Records `participant`'s position after `action`
+pub async fn record_change(
+    connection: impl PgExecutor<'_>,
+    participant: &BetParticipant,
+    action: ParticipantAction,
+) -> AllResult<BetParticipantChange> {
+    let change = sqlx::query_as!(
+        BetParticipantChange,
+        r#"
+        INSERT INTO bet_participant_changes (bet_id, user_id, action, option_id, bet_amount)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, bet_id, user_id, action AS "action: ParticipantAction", option_id,
+        bet_amount, created_at
+        "#,
+        participant.bet_id,
+        participant.user_id,
+        action as _,
+        participant.option_id,
+        participant.bet_amount
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(change)
+}
+
+/// Every change participants made to their positions on `bet`, oldest first
+pub async fn get_bet_participant_changes(
+    connection: &PgPool,
+    bet: &Bet,
+) -> AllResult<Vec<BetParticipantChange>> {
+    let changes = sqlx::query_as!(
+        BetParticipantChange,
+        r#"
+        SELECT id, bet_id, user_id, action AS "action: ParticipantAction", option_id,
+        bet_amount, created_at
+        FROM bet_participant_changes WHERE bet_id = $1
+        ORDER BY id
+        "#,
+        bet.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(changes)
+}
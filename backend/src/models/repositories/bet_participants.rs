@@ -1,9 +1,15 @@
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgExecutor, PgPool};
 
-use crate::models::{Bet, BetParticipant, Score, User};
-use crate::AllResult;
+use crate::models::{
+    ArbiterStatus, Bet, BetError, BetParticipant, BetStatus, BetVisibility, InvitationStatus,
+    ParticipantAction, Score, User,
+};
+use crate::{telemetry, AllResult};
 
-use super::scores;
+use super::{
+    bet_invitations::get_bet_invitation, bet_options::get_bet_option,
+    bet_participant_changes::record_change, friendships::are_friends, scores,
+};
 
 pub async fn get_bet_participant_by_bet_id(
     connection: &PgPool,
@@ -12,7 +18,7 @@ pub async fn get_bet_participant_by_bet_id(
     let bet_participant = sqlx::query_as!(
         BetParticipant,
         r#"
-        SELECT bet_id, user_id, for_bet, bet_amount, paid_out
+        SELECT bet_id, user_id, option_id, bet_amount, paid_out, won, confirmed_version
         FROM bet_participants WHERE bet_id = $1
         "#,
         bet_id,
@@ -29,7 +35,7 @@ pub async fn get_bet_participants_by_bet_user(
     let bet_participant = sqlx::query_as!(
         BetParticipant,
         r#"
-        SELECT bet_id, user_id, for_bet, bet_amount, paid_out
+        SELECT bet_id, user_id, option_id, bet_amount, paid_out, won, confirmed_version
         FROM bet_participants WHERE user_id = $1
         "#,
         user.id,
@@ -39,33 +45,368 @@ pub async fn get_bet_participants_by_bet_user(
     Ok(bet_participant)
 }
 
+/// Fails with `BetError::NotParticipant` if `user` didn't join `bet`
+pub async fn get_bet_participant(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
+    user: &User,
+) -> AllResult<BetParticipant> {
+    let bet_participant = sqlx::query_as!(
+        BetParticipant,
+        r#"
+        SELECT * FROM bet_participants WHERE bet_id = $1 AND user_id = $2
+        "#,
+        bet.id,
+        user.id
+    )
+    .fetch_optional(connection)
+    .await?
+    .ok_or(BetError::NotParticipant)?;
+    Ok(bet_participant)
+}
+
+/// Joins `user` to `bet`. The bet row is locked while joining, so a
+/// concurrent close either waits for the join or makes it fail, and
+/// concurrent joins are counted against `max_participants` one at a time.
 pub async fn create_bet_participant(
     connection: &PgPool,
     user: &User,
     bet: &Bet,
     amount: i32,
-    for_bet: bool,
+    option_id: i32,
+) -> AllResult<BetParticipant> {
+    if amount <= 0 {
+        return Err(BetError::InvalidStake.into());
+    }
+
+    let mut transaction = connection.begin().await?;
+
+    let current = lock_joinable_bet(&mut transaction, user, bet).await?;
+    get_bet_option(&mut *transaction, bet, option_id).await?;
+    check_stake(amount, current.min_stake, current.max_stake)?;
+    if let Some(max_participants) = current.max_participants {
+        let participants = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM bet_participants WHERE bet_id = $1"#,
+            bet.id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        if participants >= i64::from(max_participants) {
+            return Err(BetError::BetFull.into());
+        }
+    }
+
+    let bet_participant = insert_bet_participant(
+        &mut transaction,
+        bet.id,
+        user.id,
+        option_id,
+        amount,
+        current.version,
+    )
+    .await?;
+    transaction.commit().await?;
+    metrics::counter!(telemetry::BET_PARTICIPANTS_JOINED).increment(1);
+    Ok(bet_participant)
+}
+
+/// The bet's joining rules, read while the bet is locked against closing
+/// and concurrent joins
+pub(super) struct JoinableBet {
+    pub version: i32,
+    pub min_stake: Option<i32>,
+    pub max_stake: Option<i32>,
+    pub max_participants: Option<i32>,
+}
+
+/// Locks the bet until the transaction ends and fails unless `user` may
+/// take a position on it right now
+pub(super) async fn lock_joinable_bet(
+    connection: &mut PgConnection,
+    user: &User,
+    bet: &Bet,
+) -> AllResult<JoinableBet> {
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", stop_bets_at, arbiter_id,
+        arbiter_status AS "arbiter_status: ArbiterStatus",
+        visibility AS "visibility: BetVisibility", version,
+        min_stake, max_stake, max_participants
+        FROM bets WHERE id = $1
+        FOR NO KEY UPDATE
+        "#,
+        bet.id
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+    if current.status != BetStatus::Active {
+        return Err(BetError::NotActive.into());
+    }
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    if current
+        .stop_bets_at
+        .is_some_and(|stop_bets_at| stop_bets_at <= now)
+    {
+        return Err(BetError::CutoffPassed.into());
+    }
+    if current.arbiter_id == Some(user.id)
+        && current.arbiter_status != Some(ArbiterStatus::Declined)
+    {
+        return Err(BetError::ArbiterCannotJoin.into());
+    }
+    let invitation = match current.visibility {
+        BetVisibility::Public => None,
+        _ => get_bet_invitation(&mut *connection, bet, user).await?,
+    };
+    let invited =
+        invitation.map(|invitation| invitation.status) == Some(InvitationStatus::Accepted);
+    match current.visibility {
+        _ if user.id == bet.creator_id || invited => {}
+        BetVisibility::Public => {}
+        BetVisibility::Friends => {
+            if !are_friends(&mut *connection, bet.creator_id, user.id).await? {
+                return Err(BetError::FriendsOnly.into());
+            }
+        }
+        BetVisibility::Private => return Err(BetError::NotInvited.into()),
+    }
+    Ok(JoinableBet {
+        version: current.version,
+        min_stake: current.min_stake,
+        max_stake: current.max_stake,
+        max_participants: current.max_participants,
+    })
+}
+
+/// Adds the participant and records that they joined, without checking
+/// whether they may join. Callers check the bet and the option first.
+pub(super) async fn insert_bet_participant(
+    connection: &mut PgConnection,
+    bet_id: i32,
+    user_id: i32,
+    option_id: i32,
+    amount: i32,
+    version: i32,
 ) -> AllResult<BetParticipant> {
     let bet_participant = sqlx::query_as!(
         BetParticipant,
         r#"
-        INSERT INTO bet_participants (bet_id, user_id, for_bet, bet_amount, paid_out)
-        VALUES ($1, $2, $3, $4, FALSE)
+        INSERT INTO bet_participants (
+            bet_id, user_id, option_id, bet_amount, paid_out, confirmed_version
+        )
+        VALUES ($1, $2, $3, $4, FALSE, $5)
         RETURNING *;
         "#,
-        bet.id,
-        user.id,
-        for_bet,
-        amount
+        bet_id,
+        user_id,
+        option_id,
+        amount,
+        version
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+    record_change(connection, &bet_participant, ParticipantAction::Joined).await?;
+    Ok(bet_participant)
+}
+
+/// Fails with `BetError::StakeTooLow` or `BetError::StakeTooHigh` if `amount`
+/// is outside the bet's stake limits
+pub(super) fn check_stake(
+    amount: i32,
+    min_stake: Option<i32>,
+    max_stake: Option<i32>,
+) -> Result<(), BetError> {
+    if min_stake.is_some_and(|min_stake| amount < min_stake) {
+        return Err(BetError::StakeTooLow);
+    }
+    if max_stake.is_some_and(|max_stake| amount > max_stake) {
+        return Err(BetError::StakeTooHigh);
+    }
+    Ok(())
+}
+
+/// An active bet, share locked against edits and closing
+struct LockedBet {
+    version: i32,
+    cutoff_passed: bool,
+    min_stake: Option<i32>,
+    max_stake: Option<i32>,
+}
+
+async fn lock_active_bet(connection: &mut PgConnection, bet: &Bet) -> AllResult<LockedBet> {
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", stop_bets_at, version, min_stake, max_stake
+        FROM bets WHERE id = $1
+        FOR SHARE
+        "#,
+        bet.id
     )
     .fetch_one(connection)
     .await?;
+    if current.status != BetStatus::Active {
+        return Err(BetError::NotActive.into());
+    }
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    Ok(LockedBet {
+        version: current.version,
+        cutoff_passed: current
+            .stop_bets_at
+            .is_some_and(|stop_bets_at| stop_bets_at <= now),
+        min_stake: current.min_stake,
+        max_stake: current.max_stake,
+    })
+}
+
+/// Fails with `BetError::NotParticipant` if `user` didn't join `bet`
+pub async fn confirm_bet_version(
+    connection: &PgPool,
+    bet: &Bet,
+    user: &User,
+) -> AllResult<BetParticipant> {
+    let mut transaction = connection.begin().await?;
+    let locked = lock_active_bet(&mut transaction, bet).await?;
+    let bet_participant = sqlx::query_as!(
+        BetParticipant,
+        r#"
+        UPDATE bet_participants SET confirmed_version = $1
+        WHERE bet_id = $2 AND user_id = $3
+        RETURNING *
+        "#,
+        locked.version,
+        bet.id,
+        user.id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(BetError::NotParticipant)?;
+    transaction.commit().await?;
     Ok(bet_participant)
 }
 
-pub async fn get_bet_participants(
+/// Removes `user` from an active bet and returns their stake. Participants
+/// can withdraw until the cutoff, and after it only if they didn't confirm
+/// the bet's current version, otherwise this fails with
+/// `BetError::CutoffPassed`.
+pub async fn withdraw_bet_participant(
     connection: &PgPool,
     bet: &Bet,
+    user: &User,
+) -> AllResult<BetParticipant> {
+    let mut transaction = connection.begin().await?;
+    let locked = lock_active_bet(&mut transaction, bet).await?;
+    let bet_participant = get_bet_participant(&mut *transaction, bet, user).await?;
+    if locked.cutoff_passed && bet_participant.confirmed_version == locked.version {
+        return Err(BetError::CutoffPassed.into());
+    }
+    sqlx::query!(
+        "DELETE FROM bet_participants WHERE bet_id = $1 AND user_id = $2",
+        bet.id,
+        user.id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_change(
+        &mut *transaction,
+        &bet_participant,
+        ParticipantAction::Withdrew,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(bet_participant)
+}
+
+/// Moves `user`'s stake to another option of an active bet before its cutoff.
+/// Changing a position confirms the bet's current version.
+pub async fn switch_bet_option(
+    connection: &PgPool,
+    bet: &Bet,
+    user: &User,
+    option_id: i32,
+) -> AllResult<BetParticipant> {
+    let mut transaction = connection.begin().await?;
+    get_bet_option(&mut *transaction, bet, option_id).await?;
+    let bet_participant = change_position(
+        &mut transaction,
+        bet,
+        user,
+        Some(option_id),
+        None,
+        ParticipantAction::Switched,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(bet_participant)
+}
+
+/// Changes `user`'s stake on an active bet before its cutoff, like
+/// `switch_bet_option`. The new stake has to be within the bet's stake
+/// limits.
+pub async fn adjust_bet_amount(
+    connection: &PgPool,
+    bet: &Bet,
+    user: &User,
+    amount: i32,
+) -> AllResult<BetParticipant> {
+    if amount <= 0 {
+        return Err(BetError::InvalidStake.into());
+    }
+    let mut transaction = connection.begin().await?;
+    let bet_participant = change_position(
+        &mut transaction,
+        bet,
+        user,
+        None,
+        Some(amount),
+        ParticipantAction::Restaked,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(bet_participant)
+}
+
+/// Sets the participant's option and stake, leaving out the ones that are
+/// `None`, and records the change
+async fn change_position(
+    connection: &mut PgConnection,
+    bet: &Bet,
+    user: &User,
+    option_id: Option<i32>,
+    amount: Option<i32>,
+    action: ParticipantAction,
+) -> AllResult<BetParticipant> {
+    let locked = lock_active_bet(&mut *connection, bet).await?;
+    if locked.cutoff_passed {
+        return Err(BetError::CutoffPassed.into());
+    }
+    if let Some(amount) = amount {
+        check_stake(amount, locked.min_stake, locked.max_stake)?;
+    }
+    let bet_participant = sqlx::query_as!(
+        BetParticipant,
+        r#"
+        UPDATE bet_participants
+        SET option_id = COALESCE($1, option_id), bet_amount = COALESCE($2, bet_amount),
+        confirmed_version = $3
+        WHERE bet_id = $4 AND user_id = $5
+        RETURNING *
+        "#,
+        option_id,
+        amount,
+        locked.version,
+        bet.id,
+        user.id
+    )
+    .fetch_optional(&mut *connection)
+    .await?
+    .ok_or(BetError::NotParticipant)?;
+    record_change(&mut *connection, &bet_participant, action).await?;
+    Ok(bet_participant)
+}
+
+pub async fn get_bet_participants(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
 ) -> AllResult<Vec<BetParticipant>> {
     let bet_participants = sqlx::query_as!(
         BetParticipant,
@@ -80,15 +421,49 @@ pub async fn get_bet_participants(
 }
 
 pub(crate) async fn payout_participant(
-    connection: &PgPool,
+    connection: &mut PgConnection,
+    participant: BetParticipant,
+    winning_option_id: Option<i32>,
+) -> AllResult<(BetParticipant, Score)> {
+    let participant = sqlx::query_as!(
+        BetParticipant,
+        r#"
+        UPDATE bet_participants
+        SET paid_out = TRUE, won = (option_id = $3)
+        WHERE bet_id = $1 AND user_id = $2
+        RETURNING *
+        "#,
+        participant.bet_id,
+        participant.user_id,
+        winning_option_id
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+    let score = match participant.won {
+        Some(true) => scores::update_score_winning_bet(connection, &participant).await?,
+        Some(false) => scores::update_score_losing_bet(connection, &participant).await?,
+        // A push, the stake goes back and the score doesn't change
+        None => scores::read_score_by_user_id(connection, participant.user_id).await?,
+    };
+    Ok((participant, score))
+}
+
+/// Returns the participant's stake, taking back the win or loss if they were
+/// already paid out
+pub(crate) async fn void_participant(
+    connection: &mut PgConnection,
     participant: BetParticipant,
-    bet_outcome: bool,
 ) -> AllResult<(BetParticipant, Score)> {
+    let score = match participant.won {
+        Some(true) => scores::revert_score_winning_bet(&mut *connection, &participant).await?,
+        Some(false) => scores::revert_score_losing_bet(&mut *connection, &participant).await?,
+        None => scores::read_score_by_user_id(&mut *connection, participant.user_id).await?,
+    };
     let participant = sqlx::query_as!(
         BetParticipant,
         r#"
         UPDATE bet_participants
-        SET paid_out = TRUE
+        SET paid_out = TRUE, won = NULL
         WHERE bet_id = $1 AND user_id = $2
         RETURNING *
         "#,
@@ -97,22 +472,20 @@ pub(crate) async fn payout_participant(
     )
     .fetch_one(connection)
     .await?;
-    let win = bet_outcome == participant.for_bet;
-    let score = match win {
-        true => scores::update_score_winning_bet(connection, &participant).await?,
-        false => scores::update_score_losing_bet(connection, &participant).await?,
-    };
     Ok((participant, score))
 }
 
 #[cfg(test)]
 mod tests {
     use super::super::{
+        bet_options::yes_no_options,
+        bet_participant_changes::get_bet_participant_changes,
         bet_participants,
-        bets::{create_timed_bet, create_timeless_bet},
+        bets::{close_bet, create_timed_bet, create_timeless_bet, limit_bet, payout_bet},
         users::create_users,
     };
     use super::*;
+    use crate::models::StakeLimits;
     use bet_participants::create_bet_participant;
     use sqlx::PgPool;
 
@@ -123,18 +496,22 @@ mod tests {
         let john = users.pop().unwrap();
 
         let timeless_bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (timeless_yes, _) = yes_no_options(&pool, &timeless_bet).await?;
 
         let now = sqlx::types::chrono::Local::now().naive_local();
         let tommorow = now + chrono::TimeDelta::days(1);
 
         let timed_bet =
             create_timed_bet(&pool, &bob, String::from("description"), tommorow).await?;
+        let (timed_yes, _) = yes_no_options(&pool, &timed_bet).await?;
 
-        let bob_timeless_bet = create_bet_participant(&pool, &bob, &timeless_bet, 10, true).await?;
+        let bob_timeless_bet =
+            create_bet_participant(&pool, &bob, &timeless_bet, 10, timeless_yes).await?;
         let john_timeless_bet =
-            create_bet_participant(&pool, &john, &timeless_bet, 10, true).await?;
-        let bob_timed_bet = create_bet_participant(&pool, &bob, &timed_bet, 10, true).await?;
-        let john_timed_bet = create_bet_participant(&pool, &john, &timed_bet, 10, true).await?;
+            create_bet_participant(&pool, &john, &timeless_bet, 10, timeless_yes).await?;
+        let bob_timed_bet = create_bet_participant(&pool, &bob, &timed_bet, 10, timed_yes).await?;
+        let john_timed_bet =
+            create_bet_participant(&pool, &john, &timed_bet, 10, timed_yes).await?;
 
         assert_eq!(bob_timeless_bet.bet_id, timeless_bet.id);
         assert_eq!(john_timeless_bet.bet_id, timeless_bet.id);
@@ -154,18 +531,23 @@ mod tests {
         let john = users.pop().unwrap();
 
         let bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, no) = yes_no_options(&pool, &bet).await?;
 
-        let bob_bet = create_bet_participant(&pool, &bob, &bet, 10, true).await?;
-        let john_bet = create_bet_participant(&pool, &john, &bet, 25, false).await?;
+        let bob_bet = create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        let john_bet = create_bet_participant(&pool, &john, &bet, 25, no).await?;
 
-        let (bob_bet, bob_score) = payout_participant(&pool, bob_bet, true).await?;
-        assert_eq!(bob_bet.paid_out, true);
+        let mut connection = pool.acquire().await?;
+        let (bob_bet, bob_score) = payout_participant(&mut connection, bob_bet, Some(yes)).await?;
+        assert!(bob_bet.paid_out);
+        assert_eq!(bob_bet.won, Some(true));
         assert_eq!(bob_score.points_earned, 10);
         assert_eq!(bob_score.total_wins, 1);
         assert_eq!(bob_score.total_losses, 0);
 
-        let (john_bet, john_score) = payout_participant(&pool, john_bet, true).await?;
-        assert_eq!(john_bet.paid_out, true);
+        let (john_bet, john_score) =
+            payout_participant(&mut connection, john_bet, Some(yes)).await?;
+        assert!(john_bet.paid_out);
+        assert_eq!(john_bet.won, Some(false));
         assert_eq!(john_score.points_earned, 0);
         assert_eq!(john_score.total_wins, 0);
         assert_eq!(john_score.total_losses, 1);
@@ -173,6 +555,250 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn join_rules(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+
+        for amount in [0, -10] {
+            let error = create_bet_participant(&pool, &bob, &bet, amount, yes)
+                .await
+                .unwrap_err();
+            assert_eq!(error.downcast_ref(), Some(&BetError::InvalidStake));
+        }
+
+        let other_bet = create_timeless_bet(&pool, &bob, String::from("other")).await?;
+        let (other_yes, _) = yes_no_options(&pool, &other_bet).await?;
+        let error = create_bet_participant(&pool, &bob, &bet, 10, other_yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::UnknownOption));
+
+        create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        close_bet(&pool, &mut bet).await?;
+
+        let error = create_bet_participant(&pool, &john, &bet, 10, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotActive));
+
+        payout_bet(&pool, &mut bet, yes).await?;
+
+        let error = create_bet_participant(&pool, &john, &bet, 10, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotActive));
+
+        let now = sqlx::types::chrono::Local::now().naive_local();
+        let yesterday = now - chrono::TimeDelta::days(1);
+        let expired = create_timed_bet(&pool, &bob, String::from("description"), yesterday).await?;
+        let (expired_yes, _) = yes_no_options(&pool, &expired).await?;
+
+        let error = create_bet_participant(&pool, &john, &expired, 10, expired_yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::CutoffPassed));
+
+        assert_eq!(get_bet_participants(&pool, &bet).await?.len(), 1);
+        assert_eq!(get_bet_participants(&pool, &expired).await?.len(), 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn change_position_before_cutoff(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let now = sqlx::types::chrono::Local::now().naive_local();
+        let tomorrow = now + chrono::TimeDelta::days(1);
+        let bet = create_timed_bet(&pool, &bob, String::from("description"), tomorrow).await?;
+        let (yes, no) = yes_no_options(&pool, &bet).await?;
+
+        create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        create_bet_participant(&pool, &john, &bet, 10, yes).await?;
+
+        let bob_bet = switch_bet_option(&pool, &bet, &bob, no).await?;
+        assert_eq!(bob_bet.option_id, no);
+        let bob_bet = adjust_bet_amount(&pool, &bet, &bob, 25).await?;
+        assert_eq!((bob_bet.option_id, bob_bet.bet_amount), (no, 25));
+        let error = adjust_bet_amount(&pool, &bet, &bob, 0).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidStake));
+        let other_bet = create_timeless_bet(&pool, &bob, String::from("other")).await?;
+        let (other_yes, _) = yes_no_options(&pool, &other_bet).await?;
+        let error = switch_bet_option(&pool, &bet, &bob, other_yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::UnknownOption));
+        let error = switch_bet_option(&pool, &other_bet, &bob, other_yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotParticipant));
+
+        withdraw_bet_participant(&pool, &bet, &john).await?;
+        create_bet_participant(&pool, &john, &bet, 5, no).await?;
+
+        let yesterday = now - chrono::TimeDelta::days(1);
+        sqlx::query!(
+            "UPDATE bets SET stop_bets_at = $1 WHERE id = $2",
+            yesterday,
+            bet.id
+        )
+        .execute(&pool)
+        .await?;
+        let error = switch_bet_option(&pool, &bet, &john, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::CutoffPassed));
+        let error = withdraw_bet_participant(&pool, &bet, &john)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::CutoffPassed));
+
+        let changes: Vec<_> = get_bet_participant_changes(&pool, &bet)
+            .await?
+            .into_iter()
+            .map(|change| {
+                (
+                    change.user_id,
+                    change.action,
+                    change.option_id,
+                    change.bet_amount,
+                )
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (bob.id, ParticipantAction::Joined, yes, 10),
+                (john.id, ParticipantAction::Joined, yes, 10),
+                (bob.id, ParticipantAction::Switched, no, 10),
+                (bob.id, ParticipantAction::Restaked, no, 25),
+                (john.id, ParticipantAction::Withdrew, yes, 10),
+                (john.id, ParticipantAction::Joined, no, 5),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn join_waits_for_concurrent_close(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+
+        // Hold the bet row like a close that hasn't committed yet
+        let mut closing = pool.begin().await?;
+        sqlx::query!("SELECT id FROM bets WHERE id = $1 FOR UPDATE", bet.id)
+            .fetch_one(&mut *closing)
+            .await?;
+
+        let join = tokio::spawn({
+            let pool = pool.clone();
+            let bet = bet.clone();
+            async move {
+                create_bet_participant(&pool, &john, &bet, 10, yes)
+                    .await
+                    .map_err(|error| error.to_string())
+            }
+        });
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!join.is_finished());
+
+        sqlx::query!(
+            "UPDATE bets SET status = $1 WHERE id = $2",
+            BetStatus::Finished as _,
+            bet.id
+        )
+        .execute(&mut *closing)
+        .await?;
+        closing.commit().await?;
+
+        let error = join.await?.unwrap_err();
+        assert_eq!(error, BetError::NotActive.to_string());
+        assert_eq!(get_bet_participants(&pool, &bet).await?.len(), 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn limits_hold_under_concurrent_joins(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John", "Alice", "Carol", "Dave"]).await?;
+        let bob = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        let error = limit_bet(
+            &pool,
+            &mut bet,
+            &StakeLimits {
+                min_stake: Some(50),
+                max_stake: Some(5),
+                max_participants: None,
+            },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidLimits));
+        let limits = StakeLimits {
+            min_stake: Some(5),
+            max_stake: Some(50),
+            max_participants: Some(3),
+        };
+        limit_bet(&pool, &mut bet, &limits).await?;
+        assert_eq!(
+            (bet.min_stake, bet.max_stake, bet.max_participants),
+            (Some(5), Some(50), Some(3))
+        );
+
+        let error = create_bet_participant(&pool, &bob, &bet, 4, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::StakeTooLow));
+        let error = create_bet_participant(&pool, &bob, &bet, 51, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::StakeTooHigh));
+        create_bet_participant(&pool, &bob, &bet, 50, yes).await?;
+        let error = adjust_bet_amount(&pool, &bet, &bob, 60).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::StakeTooHigh));
+
+        // Four people race for the two places left
+        let joins: Vec<_> = users
+            .into_iter()
+            .map(|user| {
+                let pool = pool.clone();
+                let bet = bet.clone();
+                tokio::spawn(async move {
+                    create_bet_participant(&pool, &user, &bet, 10, yes)
+                        .await
+                        .map_err(|error| error.to_string())
+                })
+            })
+            .collect();
+        let mut full = 0;
+        for join in joins {
+            if let Err(error) = join.await? {
+                assert_eq!(error, BetError::BetFull.to_string());
+                full += 1;
+            }
+        }
+        assert_eq!(full, 2);
+        assert_eq!(get_bet_participants(&pool, &bet).await?.len(), 3);
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn particpate_in_bets(pool: PgPool) -> AllResult<()> {
         let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
@@ -180,14 +806,17 @@ mod tests {
         let john = users.pop().unwrap();
 
         let bet1 = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (bet1_yes, _) = yes_no_options(&pool, &bet1).await?;
         let bet2 = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (bet2_yes, _) = yes_no_options(&pool, &bet2).await?;
         let bet3 = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (bet3_yes, _) = yes_no_options(&pool, &bet3).await?;
 
-        create_bet_participant(&pool, &bob, &bet1, 10, true).await?;
-        create_bet_participant(&pool, &bob, &bet2, 10, true).await?;
-        create_bet_participant(&pool, &bob, &bet3, 10, true).await?;
-        create_bet_participant(&pool, &john, &bet1, 10, true).await?;
-        create_bet_participant(&pool, &john, &bet2, 10, true).await?;
+        create_bet_participant(&pool, &bob, &bet1, 10, bet1_yes).await?;
+        create_bet_participant(&pool, &bob, &bet2, 10, bet2_yes).await?;
+        create_bet_participant(&pool, &bob, &bet3, 10, bet3_yes).await?;
+        create_bet_participant(&pool, &john, &bet1, 10, bet1_yes).await?;
+        create_bet_participant(&pool, &john, &bet2, 10, bet2_yes).await?;
 
         assert_eq!(
             get_bet_participants_by_bet_user(&pool, &bob).await?.len(),
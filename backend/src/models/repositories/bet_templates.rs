@@ -0,0 +1,586 @@
+use sqlx::{types::chrono::NaiveDateTime, PgConnection, PgPool};
+
+use super::bet_options::validate_options;
+use super::bets::{create_bet_with, yes_no};
+use super::friendships::are_friends;
+use crate::models::{
+    ArbiterStatus, Bet, BetError, BetKind, BetResolution, BetSchedule, BetSettings, BetStatus,
+    BetTemplate, BetVisibility, FriendshipStatus, InvitationStatus, Recurrence, User,
+};
+use crate::{telemetry, AllResult};
+
+/// Creates a template that makes a bet on every occurrence of `schedule`
+/// from now on, with Yes and No options unless `options` are given.
+/// Invitees have to be the creator's friends.
+pub async fn create_bet_template(
+    connection: &PgPool,
+    user: &User,
+    description: String,
+    options: Option<&[String]>,
+    visibility: BetVisibility,
+    schedule: &BetSchedule,
+    invitees: &[User],
+) -> AllResult<BetTemplate> {
+    let options = options.map_or_else(yes_no, <[String]>::to_vec);
+    validate_options(&options)?;
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    let first_occurrence = schedule.first_occurrence(now)?;
+
+    let mut transaction = connection.begin().await?;
+    for invitee in invitees {
+        if !are_friends(&mut *transaction, user.id, invitee.id).await? {
+            return Err(BetError::NotFriend.into());
+        }
+    }
+    let template = sqlx::query_as!(
+        BetTemplate,
+        r#"
+        INSERT INTO bet_templates (
+            creator_id, description, options, visibility, recurrence, cron, starts_at,
+            cutoff_seconds, next_run_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING id, creator_id, description, options,
+        visibility AS "visibility: BetVisibility", recurrence AS "recurrence: Recurrence",
+        cron, starts_at, cutoff_seconds, next_run_at, created_at
+        "#,
+        user.id,
+        description,
+        &options,
+        visibility as _,
+        schedule.recurrence as _,
+        schedule.cron,
+        schedule.starts_at,
+        schedule.cutoff_seconds,
+        first_occurrence
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    for invitee in invitees {
+        sqlx::query!(
+            r#"
+            INSERT INTO bet_template_invitees (template_id, user_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+            template.id,
+            invitee.id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(template)
+}
+
+pub async fn get_bet_template_by_id(connection: &PgPool, id: i32) -> AllResult<BetTemplate> {
+    let template = sqlx::query_as!(
+        BetTemplate,
+        r#"
+        SELECT id, creator_id, description, options,
+        visibility AS "visibility: BetVisibility", recurrence AS "recurrence: Recurrence",
+        cron, starts_at, cutoff_seconds, next_run_at, created_at
+        FROM bet_templates WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(template)
+}
+
+pub async fn get_bet_templates_by_user(
+    connection: &PgPool,
+    user: &User,
+) -> AllResult<Vec<BetTemplate>> {
+    let templates = sqlx::query_as!(
+        BetTemplate,
+        r#"
+        SELECT id, creator_id, description, options,
+        visibility AS "visibility: BetVisibility", recurrence AS "recurrence: Recurrence",
+        cron, starts_at, cutoff_seconds, next_run_at, created_at
+        FROM bet_templates WHERE creator_id = $1
+        ORDER BY id
+        "#,
+        user.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(templates)
+}
+
+pub async fn stop_bet_template(connection: &PgPool, template: &mut BetTemplate) -> AllResult<()> {
+    sqlx::query!(
+        "UPDATE bet_templates SET next_run_at = NULL WHERE id = $1",
+        template.id
+    )
+    .execute(connection)
+    .await?;
+    template.next_run_at = None;
+    Ok(())
+}
+
+pub async fn get_template_invitees(
+    connection: &PgPool,
+    template: &BetTemplate,
+) -> AllResult<Vec<User>> {
+    let invitees = sqlx::query_as!(
+        User,
+        r#"
+        SELECT users.* FROM bet_template_invitees JOIN users ON users.id = user_id
+        WHERE template_id = $1
+        ORDER BY users.id
+        "#,
+        template.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(invitees)
+}
+
+pub async fn get_template_bets(connection: &PgPool, template: &BetTemplate) -> AllResult<Vec<Bet>> {
+    let bets = query_bets!(
+        "SELECT",
+        r#"
+        FROM bet_template_runs JOIN bets ON bets.id = bet_id
+        WHERE template_id = $1
+        ORDER BY occurrence_at
+        "#;
+        template.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(bets)
+}
+
+/// Creates the bets of every template whose next occurrence is at or before
+/// `now`, one per occurrence, so occurrences missed while the scheduler
+/// wasn't running are caught up on.
+///
+/// Each template is caught up in its own transaction, so one that fails is
+/// logged and retried on the next pass without holding back the others. Due
+/// templates are locked with `SKIP LOCKED`, so several servers can run this
+/// at once and each occurrence gets exactly one bet.
+pub async fn create_due_bets(connection: &PgPool, now: NaiveDateTime) -> AllResult<Vec<Bet>> {
+    let due = sqlx::query_scalar!(
+        "SELECT id FROM bet_templates WHERE next_run_at <= $1 ORDER BY id",
+        now
+    )
+    .fetch_all(connection)
+    .await?;
+
+    let mut created = Vec::new();
+    for template_id in due {
+        match create_template_bets(connection, template_id, now).await {
+            Ok(bets) => {
+                metrics::counter!(telemetry::BETS_CREATED).increment(bets.len() as u64);
+                created.extend(bets);
+            }
+            Err(error) => telemetry::report_failure(
+                "templates",
+                format_args!("unable to create the bets of template {template_id}: {error}"),
+            ),
+        }
+    }
+    Ok(created)
+}
+
+/// Creates the due bets of one template for `create_due_bets` and commits,
+/// creating none when it's locked by another server or no longer due
+async fn create_template_bets(
+    connection: &PgPool,
+    template_id: i32,
+    now: NaiveDateTime,
+) -> AllResult<Vec<Bet>> {
+    let mut transaction = connection.begin().await?;
+    let template = sqlx::query_as!(
+        BetTemplate,
+        r#"
+        SELECT id, creator_id, description, options,
+        visibility AS "visibility: BetVisibility", recurrence AS "recurrence: Recurrence",
+        cron, starts_at, cutoff_seconds, next_run_at, created_at
+        FROM bet_templates
+        WHERE id = $1 AND next_run_at <= $2
+        FOR UPDATE SKIP LOCKED
+        "#,
+        template_id,
+        now
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(template) = template else {
+        return Ok(Vec::new());
+    };
+
+    let schedule = template.schedule();
+    let (due, next_run_at) = schedule.due_occurrences(template.next_run_at, now);
+    let mut created = Vec::new();
+    for occurrence in due {
+        let bet = create_occurrence(&mut transaction, &template, &schedule, occurrence).await?;
+        created.push(bet);
+    }
+    sqlx::query!(
+        "UPDATE bet_templates SET next_run_at = $1 WHERE id = $2",
+        next_run_at,
+        template.id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(created)
+}
+
+/// Creates the bet for one occurrence and invites the template's invitees who
+/// are still the creator's friends
+async fn create_occurrence(
+    connection: &mut PgConnection,
+    template: &BetTemplate,
+    schedule: &BetSchedule,
+    occurrence: NaiveDateTime,
+) -> AllResult<Bet> {
+    let bet = create_bet_with(
+        &mut *connection,
+        template.creator_id,
+        template.description.clone(),
+        schedule.cutoff(occurrence),
+        &template.options,
+        &BetSettings {
+            visibility: template.visibility,
+            ..BetSettings::default()
+        },
+        BetStatus::Active,
+    )
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO bet_template_runs (template_id, occurrence_at, bet_id) VALUES ($1, $2, $3)
+        "#,
+        template.id,
+        occurrence,
+        bet.id
+    )
+    .execute(&mut *connection)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO bet_invitations (bet_id, user_id, status)
+        SELECT $1, invitees.user_id, $2 FROM bet_template_invitees AS invitees
+        JOIN friendships ON friendships.user_id = $3 AND friendships.friend_id = invitees.user_id
+        WHERE invitees.template_id = $4 AND friendships.status = $5
+        "#,
+        bet.id,
+        InvitationStatus::Invited as _,
+        template.creator_id,
+        template.id,
+        FriendshipStatus::Accepted as _
+    )
+    .execute(&mut *connection)
+    .await?;
+    Ok(bet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        bet_invitations::get_bet_invitations,
+        friendships::{respond_to_friend_request, send_friend_request, FriendRequestResponse},
+        users::create_users,
+    };
+    use super::*;
+    use crate::models::bet_template::MAX_CATCH_UP;
+    use chrono::{NaiveDate, SubsecRound, TimeDelta};
+
+    /// A Monday
+    fn monday_evening() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, 3)
+            .unwrap()
+            .and_hms_opt(18, 0, 0)
+            .unwrap()
+    }
+
+    fn schedule(recurrence: Recurrence, cron: Option<&str>) -> BetSchedule {
+        BetSchedule {
+            recurrence,
+            cron: cron.map(String::from),
+            starts_at: monday_evening(),
+            cutoff_seconds: Some(3600),
+        }
+    }
+
+    #[test]
+    fn schedule_rules() {
+        let start = monday_evening();
+        let daily = schedule(Recurrence::Daily, None);
+        assert_eq!(daily.first_occurrence(start), Ok(start));
+        assert_eq!(
+            daily.next_occurrence(start),
+            Some(start + TimeDelta::days(1))
+        );
+        assert_eq!(daily.cutoff(start), Some(start + TimeDelta::hours(1)));
+        // Occurrences that already passed are skipped
+        assert_eq!(
+            daily.first_occurrence(start + TimeDelta::days(2) + TimeDelta::minutes(1)),
+            Ok(start + TimeDelta::days(3))
+        );
+
+        let weekly = schedule(Recurrence::Weekly, None);
+        assert_eq!(
+            weekly.next_occurrence(start),
+            Some(start + TimeDelta::weeks(1))
+        );
+
+        // Sundays at noon, starting from a Monday
+        let sundays = schedule(Recurrence::Cron, Some("0 0 12 * * Sun"));
+        let first_sunday = start + TimeDelta::days(5) + TimeDelta::hours(18);
+        assert_eq!(sundays.first_occurrence(start), Ok(first_sunday));
+        assert_eq!(
+            sundays.first_occurrence(first_sunday + TimeDelta::minutes(1)),
+            Ok(first_sunday + TimeDelta::weeks(1))
+        );
+        assert_eq!(
+            sundays.next_occurrence(first_sunday),
+            Some(first_sunday + TimeDelta::weeks(1))
+        );
+        let mondays = schedule(Recurrence::Cron, Some("0 0 18 * * Mon"));
+        assert_eq!(mondays.first_occurrence(start), Ok(start));
+        // An hour apart, including across midnight
+        let hourly = schedule(Recurrence::Cron, Some("0 0 0,23 * * *"));
+        assert_eq!(
+            hourly.first_occurrence(start),
+            Ok(start + TimeDelta::hours(5))
+        );
+
+        let invalid = [
+            schedule(Recurrence::Daily, Some("0 0 12 * * Sun")),
+            schedule(Recurrence::Cron, None),
+            schedule(Recurrence::Cron, Some("every sunday")),
+            schedule(Recurrence::Cron, Some("0 0 12 1 1 * 2000")),
+            schedule(Recurrence::Cron, Some("0 */30 * * * *")),
+            schedule(Recurrence::Cron, Some("0 0,59 12 31 * *")),
+            BetSchedule {
+                cutoff_seconds: Some(0),
+                ..daily
+            },
+        ];
+        for schedule in invalid {
+            assert_eq!(
+                schedule.first_occurrence(start),
+                Err(BetError::InvalidSchedule),
+                "{schedule:?}"
+            );
+        }
+    }
+
+    #[sqlx::test]
+    async fn creates_one_bet_per_occurrence(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        send_friend_request(&pool, &bob, &john).await?;
+        respond_to_friend_request(&pool, &john, &bob, FriendRequestResponse::Accept).await?;
+
+        let start = sqlx::types::chrono::Local::now()
+            .naive_local()
+            .trunc_subsecs(0)
+            + TimeDelta::hours(1);
+        let mut template = create_bet_template(
+            &pool,
+            &bob,
+            String::from("Will standup finish in 15 minutes?"),
+            None,
+            BetVisibility::Private,
+            &BetSchedule {
+                starts_at: start,
+                ..schedule(Recurrence::Daily, None)
+            },
+            &[john],
+        )
+        .await?;
+        assert_eq!(template.next_run_at, Some(start));
+        assert!(create_due_bets(&pool, start - TimeDelta::minutes(1))
+            .await?
+            .is_empty());
+
+        // Two days of downtime
+        let now = start + TimeDelta::days(2) + TimeDelta::minutes(5);
+        let created = create_due_bets(&pool, now).await?;
+        let cutoffs: Vec<_> = created.iter().map(|bet| bet.stop_bets_at).collect();
+        assert_eq!(
+            cutoffs,
+            [0, 1, 2].map(|day| Some(start + TimeDelta::days(day) + TimeDelta::hours(1)))
+        );
+        for bet in &created {
+            assert_eq!(bet.creator_id, bob.id);
+            assert_eq!(bet.visibility, BetVisibility::Private);
+            let invitations = get_bet_invitations(&pool, bet).await?;
+            assert_eq!(invitations.len(), 1);
+            assert_eq!(invitations[0].status, InvitationStatus::Invited);
+        }
+        assert!(create_due_bets(&pool, now).await?.is_empty());
+        assert_eq!(get_template_bets(&pool, &template).await?, created);
+        let template_now = get_bet_template_by_id(&pool, template.id).await?;
+        assert_eq!(template_now.next_run_at, Some(start + TimeDelta::days(3)));
+
+        stop_bet_template(&pool, &mut template).await?;
+        assert_eq!(template.next_run_at, None);
+        assert!(create_due_bets(&pool, now + TimeDelta::weeks(1))
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn catches_up_on_the_latest_occurrences_only(pool: PgPool) -> AllResult<()> {
+        let bob = create_users(&pool, vec!["Bob"]).await?.pop().unwrap();
+
+        let start = sqlx::types::chrono::Local::now()
+            .naive_local()
+            .trunc_subsecs(0)
+            + TimeDelta::hours(1);
+        let template = create_bet_template(
+            &pool,
+            &bob,
+            String::from("Will the coffee machine work?"),
+            None,
+            BetVisibility::Public,
+            &BetSchedule {
+                starts_at: start,
+                ..schedule(Recurrence::Daily, None)
+            },
+            &[],
+        )
+        .await?;
+
+        // A month of downtime
+        let now = start + TimeDelta::days(30) + TimeDelta::minutes(5);
+        let created = create_due_bets(&pool, now).await?;
+        let cutoffs: Vec<_> = created.iter().map(|bet| bet.stop_bets_at).collect();
+        let latest = (31 - MAX_CATCH_UP as i64)..31;
+        assert_eq!(
+            cutoffs,
+            latest
+                .map(|day| Some(start + TimeDelta::days(day) + TimeDelta::hours(1)))
+                .collect::<Vec<_>>()
+        );
+        let template_now = get_bet_template_by_id(&pool, template.id).await?;
+        assert_eq!(template_now.next_run_at, Some(start + TimeDelta::days(31)));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn past_start_skips_missed_occurrences(pool: PgPool) -> AllResult<()> {
+        let bob = create_users(&pool, vec!["Bob"]).await?.pop().unwrap();
+
+        let now = sqlx::types::chrono::Local::now().naive_local();
+        let template = create_bet_template(
+            &pool,
+            &bob,
+            String::from("Will the build be green?"),
+            None,
+            BetVisibility::Public,
+            &BetSchedule {
+                starts_at: now - TimeDelta::weeks(3),
+                ..schedule(Recurrence::Weekly, None)
+            },
+            &[],
+        )
+        .await?;
+        let next_run_at = template.next_run_at.unwrap();
+        assert!(next_run_at >= now && next_run_at < now + TimeDelta::weeks(1));
+        assert!(create_due_bets(&pool, now).await?.is_empty());
+        assert_eq!(create_due_bets(&pool, next_run_at).await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn failed_template_does_not_block_others(pool: PgPool) -> AllResult<()> {
+        let bob = create_users(&pool, vec!["Bob"]).await?.pop().unwrap();
+
+        let start = sqlx::types::chrono::Local::now()
+            .naive_local()
+            .trunc_subsecs(0)
+            + TimeDelta::hours(1);
+        let mut templates = Vec::new();
+        for description in ["broken", "fine"] {
+            let template = create_bet_template(
+                &pool,
+                &bob,
+                String::from(description),
+                None,
+                BetVisibility::Public,
+                &BetSchedule {
+                    starts_at: start,
+                    ..schedule(Recurrence::Daily, None)
+                },
+                &[],
+            )
+            .await?;
+            templates.push(template);
+        }
+        let fine = templates.pop().unwrap();
+        let broken = templates.pop().unwrap();
+        // Bets need two options, so the first template can't create any
+        sqlx::query!(
+            "UPDATE bet_templates SET options = '{}' WHERE id = $1",
+            broken.id
+        )
+        .execute(&pool)
+        .await?;
+
+        let created = create_due_bets(&pool, start).await?;
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].description, "fine");
+        assert_eq!(get_template_bets(&pool, &fine).await?, created);
+        let broken = get_bet_template_by_id(&pool, broken.id).await?;
+        assert_eq!(broken.next_run_at, Some(start));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn templates_invite_friends_only(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John", "Stranger"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        let stranger = users.pop().unwrap();
+        send_friend_request(&pool, &bob, &john).await?;
+        respond_to_friend_request(&pool, &john, &bob, FriendRequestResponse::Accept).await?;
+
+        for visibility in [BetVisibility::Public, BetVisibility::Private] {
+            let error = create_bet_template(
+                &pool,
+                &bob,
+                String::from("description"),
+                None,
+                visibility,
+                &schedule(Recurrence::Weekly, None),
+                std::slice::from_ref(&stranger),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(error.downcast_ref(), Some(&BetError::NotFriend));
+        }
+        assert!(get_bet_templates_by_user(&pool, &bob).await?.is_empty());
+
+        // Friends are invited whatever the visibility
+        let template = create_bet_template(
+            &pool,
+            &bob,
+            String::from("description"),
+            None,
+            BetVisibility::Public,
+            &schedule(Recurrence::Weekly, None),
+            std::slice::from_ref(&john),
+        )
+        .await?;
+        let created = create_due_bets(&pool, template.next_run_at.unwrap()).await?;
+        assert_eq!(created.len(), 1);
+        let invitations = get_bet_invitations(&pool, &created[0]).await?;
+        assert_eq!(invitations.len(), 1);
+        assert_eq!(invitations[0].user_id, john.id);
+
+        Ok(())
+    }
+}
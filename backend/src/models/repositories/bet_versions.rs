@@ -0,0 +1,45 @@
+use sqlx::{PgExecutor, PgPool};
+
+use crate::models::{Bet, BetVersion};
+use crate::AllResult;
+
+/// Records `bet`'s terms as its current version, replacing what was recorded
+/// for that version before
+pub async fn save_bet_version(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
+    options: &[String],
+) -> AllResult<BetVersion> {
+    let bet_version = sqlx::query_as!(
+        BetVersion,
+        r#"
+        INSERT INTO bet_versions (bet_id, version, description, stop_bets_at, options)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (bet_id, version)
+        DO UPDATE SET description = EXCLUDED.description, stop_bets_at = EXCLUDED.stop_bets_at,
+        options = EXCLUDED.options, created_at = NOW()
+        RETURNING *
+        "#,
+        bet.id,
+        bet.version,
+        bet.description,
+        bet.stop_bets_at,
+        options
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(bet_version)
+}
+
+pub async fn get_bet_versions(connection: &PgPool, bet: &Bet) -> AllResult<Vec<BetVersion>> {
+    let bet_versions = sqlx::query_as!(
+        BetVersion,
+        r#"
+        SELECT * FROM bet_versions WHERE bet_id = $1 ORDER BY version
+        "#,
+        bet.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(bet_versions)
+}
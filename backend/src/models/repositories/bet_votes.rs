@@ -0,0 +1,59 @@
+use sqlx::{PgExecutor, PgPool};
+
+use crate::models::{Bet, BetVote, User};
+use crate::AllResult;
+
+/// Records `user`'s vote, replacing the one they cast before
+pub async fn cast_vote(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
+    user: &User,
+    option_id: i32,
+) -> AllResult<BetVote> {
+    let bet_vote = sqlx::query_as!(
+        BetVote,
+        r#"
+        INSERT INTO bet_votes (bet_id, user_id, option_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (bet_id, user_id)
+        DO UPDATE SET option_id = EXCLUDED.option_id, created_at = NOW()
+        RETURNING *
+        "#,
+        bet.id,
+        user.id,
+        option_id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(bet_vote)
+}
+
+pub async fn count_votes(
+    connection: impl PgExecutor<'_>,
+    bet: &Bet,
+    option_id: i32,
+) -> AllResult<i64> {
+    let votes = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "votes!" FROM bet_votes WHERE bet_id = $1 AND option_id = $2
+        "#,
+        bet.id,
+        option_id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(votes)
+}
+
+pub async fn get_bet_votes(connection: &PgPool, bet: &Bet) -> AllResult<Vec<BetVote>> {
+    let bet_votes = sqlx::query_as!(
+        BetVote,
+        r#"
+        SELECT * FROM bet_votes WHERE bet_id = $1 ORDER BY created_at
+        "#,
+        bet.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(bet_votes)
+}
@@ -1,77 +1,137 @@
-use sqlx::types::chrono::NaiveDateTime;
+use std::cmp::Ordering;
 
-use super::bet_participants::{get_bet_participants, payout_participant};
-use crate::models::{Bet, BetParticipant, BetStatus, User};
-use crate::AllResult;
+use sqlx::{types::chrono::NaiveDateTime, PgConnection, PgExecutor};
 
-pub async fn get_bet_by_id(connection: &sqlx::PgPool, id: i32) -> AllResult<Bet> {
-    let bet = sqlx::query_as!(
-        Bet,
+use super::bet_attachments::settle_attachments;
+use super::bet_options::{
+    create_bet_option, get_bet_option, get_bet_option_by_label, get_bet_options, set_bet_options,
+    validate_options, OVER_UNDER, YES_NO,
+};
+use super::bet_participants::{
+    get_bet_participant, get_bet_participants, payout_participant, void_participant,
+};
+use super::bet_versions::save_bet_version;
+use super::bet_votes::{cast_vote, count_votes};
+use super::categories::resolve_labels;
+use super::parlays::{cancel_parlays, settle_parlays};
+use crate::models::{
+    ArbiterStatus, Bet, BetEdit, BetError, BetFilter, BetKind, BetLabels, BetMatch, BetParticipant,
+    BetResolution, BetSettings, BetStatus, BetVisibility, StakeLimits, User, VoteResolution,
+};
+use crate::{telemetry, AllResult};
+
+pub async fn get_bet_by_id(connection: impl PgExecutor<'_>, id: i32) -> AllResult<Bet> {
+    let bet = query_bets!("SELECT", "FROM bets WHERE id = $1"; id)
+        .fetch_one(connection)
+        .await?;
+    Ok(bet)
+}
+
+/// Like `get_bet_by_id`, but bets `viewer` can't see are not found
+pub async fn get_visible_bet_by_id(
+    connection: &sqlx::PgPool,
+    id: i32,
+    viewer: Option<&User>,
+) -> AllResult<Bet> {
+    let bet = query_bets!(
+        "SELECT",
         r#"
-        SELECT id, creator_id, description, status AS "status: BetStatus",
-        stop_bets_at, created_at, updated_at, paid_out, paid_out_at
-        FROM bets WHERE id = $1
-        "#,
+        FROM bets WHERE id = $1 AND bet_visible_to(bets, $2)
+        "#;
         id,
+        viewer.map(|viewer| viewer.id),
     )
     .fetch_one(connection)
     .await?;
     Ok(bet)
 }
 
+/// Bets `viewer` can see, see `Bet::read_visible_by_id`, that match `filter`
 pub async fn get_bets_by_status(
     connection: &sqlx::PgPool,
     status: &BetStatus,
+    viewer: Option<&User>,
+    filter: &BetFilter,
 ) -> AllResult<Vec<Bet>> {
-    let bet = sqlx::query_as!(
-        Bet,
+    let bet = query_bets!(
+        "SELECT",
         r#"
-        SELECT id, creator_id, description, status AS "status: BetStatus",
-        stop_bets_at, created_at, updated_at, paid_out, paid_out_at
-        FROM bets WHERE status = $1
-        "#,
+        FROM bets WHERE status = $1 AND bet_visible_to(bets, $2) AND bet_matches(bets, $3, $4)
+        ORDER BY id
+        "#;
         status as _,
+        viewer.map(|viewer| viewer.id),
+        filter.category,
+        filter.tag.as_deref().map(str::to_lowercase),
     )
     .fetch_all(connection)
     .await?;
     Ok(bet)
 }
 
-pub async fn get_bets_by_user(connection: &sqlx::PgPool, user: &User) -> AllResult<Vec<Bet>> {
-    let bet = sqlx::query_as!(
-        Bet,
+/// Bets `user` created that `viewer` can see and that match `filter`
+pub async fn get_bets_by_user(
+    connection: &sqlx::PgPool,
+    user: &User,
+    viewer: Option<&User>,
+    filter: &BetFilter,
+) -> AllResult<Vec<Bet>> {
+    let bet = query_bets!(
+        "SELECT",
         r#"
-        SELECT id, creator_id, description, status AS "status: BetStatus",
-        stop_bets_at, created_at, updated_at, paid_out, paid_out_at
-        FROM bets WHERE creator_id = $1
-        "#,
+        FROM bets
+        WHERE creator_id = $1 AND bet_visible_to(bets, $2) AND bet_matches(bets, $3, $4)
+        ORDER BY id
+        "#;
         user.id,
+        viewer.map(|viewer| viewer.id),
+        filter.category,
+        filter.tag.as_deref().map(str::to_lowercase),
     )
     .fetch_all(connection)
     .await?;
     Ok(bet)
 }
 
+/// Active bets past their cutoff, and finished bets that were closed before
+/// `finished_before` but never paid out
+pub async fn get_stuck_bets(
+    connection: &sqlx::PgPool,
+    finished_before: NaiveDateTime,
+) -> AllResult<Vec<Bet>> {
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    let bets = query_bets!(
+        "SELECT",
+        r#"
+        FROM bets
+        WHERE (status = $1 AND stop_bets_at < $2)
+        OR (status = $3 AND updated_at < $4)
+        ORDER BY id
+        "#;
+        BetStatus::Active as _,
+        now,
+        BetStatus::Finished as _,
+        finished_before
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(bets)
+}
+
 pub async fn create_timeless_bet(
     connection: &sqlx::PgPool,
     user: &User,
     description: String,
 ) -> AllResult<Bet> {
-    let bet = sqlx::query_as!(
-        Bet,
-        r#"
-        INSERT INTO bets (creator_id, description, status, paid_out)
-        VALUES ($1, $2, $3, FALSE)
-        RETURNING id, creator_id, description, status AS "status: BetStatus",
-        stop_bets_at, created_at, updated_at, paid_out, paid_out_at
-        "#,
-        user.id,
+    create_bet(
+        connection,
+        user,
         description,
-        BetStatus::Active as _
+        None,
+        None,
+        &BetSettings::default(),
     )
-    .fetch_one(connection)
-    .await?;
-    Ok(bet)
+    .await
 }
 
 pub async fn create_timed_bet(
@@ -80,155 +140,1246 @@ pub async fn create_timed_bet(
     description: String,
     stop_bets_at: NaiveDateTime,
 ) -> AllResult<Bet> {
-    let bet = sqlx::query_as!(
-        Bet,
-        r#"
-        INSERT INTO bets (creator_id, description, status, paid_out, stop_bets_at)
-        VALUES ($1, $2, $3, FALSE, $4)
-        RETURNING id, creator_id, description, status AS "status: BetStatus",
-        stop_bets_at, created_at, updated_at, paid_out, paid_out_at
-        "#,
+    create_bet(
+        connection,
+        user,
+        description,
+        Some(stop_bets_at),
+        None,
+        &BetSettings::default(),
+    )
+    .await
+}
+
+/// Creates a bet with its options, labels and limits in one transaction, with
+/// Yes and No options unless `options` are given
+pub async fn create_bet(
+    connection: &sqlx::PgPool,
+    user: &User,
+    description: String,
+    stop_bets_at: Option<NaiveDateTime>,
+    options: Option<&[String]>,
+    settings: &BetSettings,
+) -> AllResult<Bet> {
+    let options = options.map_or_else(yes_no, <[String]>::to_vec);
+    validate_options(&options)?;
+    insert_bet(
+        connection,
+        user,
+        description,
+        stop_bets_at,
+        BetRules {
+            status: BetStatus::Active,
+            kind: BetKind::Options,
+            line: None,
+            vote: None,
+            settings: settings.clone(),
+        },
+        &options,
+    )
+    .await
+}
+
+/// Like `create_bet`, but inside the caller's transaction, starting in
+/// `status` and without counting the bet as created
+pub(super) async fn create_bet_with(
+    connection: &mut PgConnection,
+    creator_id: i32,
+    description: String,
+    stop_bets_at: Option<NaiveDateTime>,
+    options: &[String],
+    settings: &BetSettings,
+    status: BetStatus,
+) -> AllResult<Bet> {
+    validate_options(options)?;
+    insert_bet_with(
+        connection,
+        creator_id,
+        description,
+        stop_bets_at,
+        BetRules {
+            status,
+            kind: BetKind::Options,
+            line: None,
+            vote: None,
+            settings: settings.clone(),
+        },
+        options,
+    )
+    .await
+}
+
+/// Creates a bet resolved by participant votes, with Yes and No options
+/// unless `options` are given
+pub async fn create_vote_bet(
+    connection: &sqlx::PgPool,
+    user: &User,
+    description: String,
+    stop_bets_at: Option<NaiveDateTime>,
+    options: Option<&[String]>,
+    vote: VoteResolution,
+    settings: &BetSettings,
+) -> AllResult<Bet> {
+    let options = options.map_or_else(yes_no, <[String]>::to_vec);
+    validate_options(&options)?;
+    if vote.quorum <= 0 || vote.dispute_window_seconds < 0 {
+        return Err(BetError::InvalidVoteRules.into());
+    }
+    insert_bet(
+        connection,
+        user,
+        description,
+        stop_bets_at,
+        BetRules {
+            status: BetStatus::Active,
+            kind: BetKind::Options,
+            line: None,
+            vote: Some(vote),
+            settings: settings.clone(),
+        },
+        &options,
+    )
+    .await
+}
+
+/// Creates an over/under bet on `line`, with Over and Under options
+pub async fn create_over_under_bet(
+    connection: &sqlx::PgPool,
+    user: &User,
+    description: String,
+    stop_bets_at: Option<NaiveDateTime>,
+    line: f64,
+    settings: &BetSettings,
+) -> AllResult<Bet> {
+    if !line.is_finite() {
+        return Err(BetError::InvalidLine.into());
+    }
+    let options = OVER_UNDER.map(String::from);
+    insert_bet(
+        connection,
+        user,
+        description,
+        stop_bets_at,
+        BetRules {
+            status: BetStatus::Active,
+            kind: BetKind::OverUnder,
+            line: Some(line),
+            vote: None,
+            settings: settings.clone(),
+        },
+        &options,
+    )
+    .await
+}
+
+/// How a new bet is played, settled and who can see it
+struct BetRules {
+    /// One of `BetStatus::INITIAL`
+    status: BetStatus,
+    kind: BetKind,
+    /// Set for over/under bets
+    line: Option<f64>,
+    /// Set for bets resolved by vote
+    vote: Option<VoteResolution>,
+    settings: BetSettings,
+}
+
+async fn insert_bet(
+    connection: &sqlx::PgPool,
+    user: &User,
+    description: String,
+    stop_bets_at: Option<NaiveDateTime>,
+    rules: BetRules,
+    options: &[String],
+) -> AllResult<Bet> {
+    let mut transaction = connection.begin().await?;
+    let bet = insert_bet_with(
+        &mut transaction,
         user.id,
         description,
-        BetStatus::Active as _,
-        stop_bets_at
+        stop_bets_at,
+        rules,
+        options,
     )
-    .fetch_one(connection)
     .await?;
+    transaction.commit().await?;
+    metrics::counter!(telemetry::BETS_CREATED).increment(1);
     Ok(bet)
 }
 
-pub async fn close_bet(connection: &sqlx::PgPool, bet: &mut Bet) -> AllResult<()> {
-    assert_eq!(bet.status, BetStatus::Active);
-    let new_bet = sqlx::query_as!(
-        Bet,
+/// Inserts a bet with its options and first version inside the caller's
+/// transaction
+async fn insert_bet_with(
+    connection: &mut PgConnection,
+    creator_id: i32,
+    description: String,
+    stop_bets_at: Option<NaiveDateTime>,
+    rules: BetRules,
+    options: &[String],
+) -> AllResult<Bet> {
+    let BetRules {
+        status,
+        kind,
+        line,
+        vote,
+        settings,
+    } = rules;
+    if !BetStatus::INITIAL.contains(&status) {
+        return Err(BetError::InvalidTransition.into());
+    }
+    validate_limits(&settings.limits)?;
+    let (category_id, tags) = resolve_labels(&mut *connection, &settings.labels).await?;
+    let resolution = match vote {
+        Some(_) => BetResolution::Vote,
+        None => BetResolution::Creator,
+    };
+    let bet = query_bets!(
+        r#"
+        INSERT INTO bets (
+            creator_id, description, status, paid_out, stop_bets_at, kind, line,
+            resolution, quorum, dispute_window_seconds, visibility, category_id, tags,
+            min_stake, max_stake, max_participants
+        )
+        VALUES ($1, $2, $3, FALSE, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+        RETURNING
+        "#;
+        creator_id,
+        description,
+        status as _,
+        stop_bets_at,
+        kind as _,
+        line,
+        resolution as _,
+        vote.map(|vote| vote.quorum),
+        vote.map(|vote| vote.dispute_window_seconds),
+        settings.visibility as _,
+        category_id,
+        &tags,
+        settings.limits.min_stake,
+        settings.limits.max_stake,
+        settings.limits.max_participants
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+    for label in options {
+        create_bet_option(&mut *connection, &bet, label).await?;
+    }
+    save_bet_version(&mut *connection, &bet, options).await?;
+    Ok(bet)
+}
+
+pub(super) fn yes_no() -> Vec<String> {
+    YES_NO.map(String::from).to_vec()
+}
+
+/// Applies `edit` to an active bet. Until someone joins, the current version
+/// is rewritten in place. After that every edit bumps the bet's version, and
+/// participants other than the creator have to confirm it or withdraw, see
+/// `bet_participants::confirm_bet_version`.
+///
+/// Fails with `BetError::Stale` if the bet was updated since `bet` was read,
+/// with `BetError::OptionPicked` if the edit drops an option someone picked,
+/// and with `BetError::CutoffPassed` if it moves a cutoff that already passed
+/// or moves it into the past.
+pub async fn edit_bet(connection: &sqlx::PgPool, bet: &mut Bet, edit: BetEdit) -> AllResult<()> {
+    if let Some(options) = &edit.options {
+        if bet.kind != BetKind::Options {
+            return Err(BetError::WrongKind.into());
+        }
+        validate_options(options)?;
+    }
+
+    let mut transaction = connection.begin().await?;
+    lock_active_bet(&mut transaction, bet).await?;
+    if let Some(stop_bets_at) = edit.stop_bets_at {
+        let now = sqlx::types::chrono::Local::now().naive_local();
+        // Moving the cutoff can't reopen a bet that stopped taking participants
+        let passed = bet.stop_bets_at.is_some_and(|cutoff| cutoff <= now);
+        if passed || stop_bets_at <= now {
+            return Err(BetError::CutoffPassed.into());
+        }
+    }
+    if let Some(options) = &edit.options {
+        set_bet_options(&mut transaction, bet, options).await?;
+    }
+    let joined = !get_bet_participants(&mut *transaction, bet)
+        .await?
+        .is_empty();
+    let new_bet = query_bets!(
         r#"
         UPDATE bets
-        SET status = $1
-        WHERE id = $2
-        RETURNING id, creator_id, description, status AS "status: BetStatus",
-        stop_bets_at, created_at, updated_at, paid_out, paid_out_at
+        SET description = COALESCE($1, description), stop_bets_at = COALESCE($2, stop_bets_at),
+        version = version + $3
+        WHERE id = $4
+        RETURNING
+        "#;
+        edit.description,
+        edit.stop_bets_at,
+        i32::from(joined),
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    // The creator agrees to their own edit
+    sqlx::query!(
+        "UPDATE bet_participants SET confirmed_version = $1 WHERE bet_id = $2 AND user_id = $3",
+        new_bet.version,
+        bet.id,
+        bet.creator_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let labels: Vec<String> = get_bet_options(&mut *transaction, bet)
+        .await?
+        .into_iter()
+        .map(|option| option.label)
+        .collect();
+    save_bet_version(&mut *transaction, &new_bet, &labels).await?;
+    transaction.commit().await?;
+    *bet = new_bet;
+    Ok(())
+}
+
+/// Locks an active bet for the rest of the transaction.
+///
+/// Fails with `BetError::NotActive` once the bet is closed, and with
+/// `BetError::Stale` if it was updated since `bet` was read
+async fn lock_active_bet(connection: &mut PgConnection, bet: &Bet) -> AllResult<()> {
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", updated_at FROM bets WHERE id = $1
+        FOR UPDATE
         "#,
-        BetStatus::Finished as _,
         bet.id
     )
     .fetch_one(connection)
     .await?;
-    bet.status = BetStatus::Finished;
-    bet.updated_at = new_bet.updated_at;
+    if current.status != BetStatus::Active {
+        return Err(BetError::NotActive.into());
+    }
+    if current.updated_at != bet.updated_at {
+        return Err(BetError::Stale.into());
+    }
     Ok(())
 }
 
-pub async fn payout_bet(
+/// Fails with `BetError::UnknownCategory` or `BetError::InvalidTags` if the
+/// labels aren't valid, with `BetError::NotActive` once the bet is closed and
+/// with `BetError::Stale` if the bet was updated since `bet` was read
+pub async fn label_bet(
     connection: &sqlx::PgPool,
     bet: &mut Bet,
-    bet_outcome: bool,
+    labels: &BetLabels,
 ) -> AllResult<()> {
-    assert_eq!(bet.status, BetStatus::Finished);
-    let participants_to_payout = get_bet_participants(connection, &bet).await?;
-    for participant in participants_to_payout {
-        payout_participant(connection, participant, bet_outcome).await?;
+    let (category_id, tags) = resolve_labels(connection, labels).await?;
+    let mut transaction = connection.begin().await?;
+    lock_active_bet(&mut transaction, bet).await?;
+    let new_bet = query_bets!(
+        r#"
+        UPDATE bets SET category_id = $1, tags = $2
+        WHERE id = $3
+        RETURNING
+        "#;
+        category_id,
+        &tags,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    *bet = new_bet;
+    Ok(())
+}
+
+pub fn validate_limits(limits: &StakeLimits) -> Result<(), BetError> {
+    let positive = [limits.min_stake, limits.max_stake, limits.max_participants]
+        .into_iter()
+        .flatten()
+        .all(|limit| limit > 0);
+    let ordered = match (limits.min_stake, limits.max_stake) {
+        (Some(min_stake), Some(max_stake)) => min_stake <= max_stake,
+        _ => true,
+    };
+    if !positive || !ordered {
+        return Err(BetError::InvalidLimits);
+    }
+    Ok(())
+}
+
+/// Fails with `BetError::InvalidLimits` if the limits aren't valid, with
+/// `BetError::NotActive` once the bet is closed and with `BetError::Stale` if
+/// the bet was updated since `bet` was read
+pub async fn limit_bet(
+    connection: &sqlx::PgPool,
+    bet: &mut Bet,
+    limits: &StakeLimits,
+) -> AllResult<()> {
+    validate_limits(limits)?;
+    let mut transaction = connection.begin().await?;
+    lock_active_bet(&mut transaction, bet).await?;
+    let new_bet = query_bets!(
+        r#"
+        UPDATE bets SET min_stake = $1, max_stake = $2, max_participants = $3
+        WHERE id = $4
+        RETURNING
+        "#;
+        limits.min_stake,
+        limits.max_stake,
+        limits.max_participants,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    *bet = new_bet;
+    Ok(())
+}
+
+/// Fails with `BetError::Stale` if the bet was updated since `bet` was read
+pub async fn close_bet(connection: &sqlx::PgPool, bet: &mut Bet) -> AllResult<()> {
+    let mut connection = connection.acquire().await?;
+    close_bet_with(&mut connection, bet).await
+}
+
+/// Closes every active bet whose `stop_bets_at` is at or before `now`.
+///
+/// Expired bets are locked with `SKIP LOCKED`, so several servers can run this
+/// at once and each bet is closed by exactly one of them.
+pub async fn close_expired_bets(
+    connection: &sqlx::PgPool,
+    now: NaiveDateTime,
+) -> AllResult<Vec<Bet>> {
+    let mut transaction = connection.begin().await?;
+    let mut expired = query_bets!(
+        "SELECT",
+        r#"
+        FROM bets
+        WHERE status = $1 AND stop_bets_at <= $2
+        ORDER BY id
+        FOR UPDATE SKIP LOCKED
+        "#;
+        BetStatus::Active as _,
+        now
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    for bet in &mut expired {
+        close_bet_with(&mut transaction, bet).await?;
     }
+    transaction.commit().await?;
+    Ok(expired)
+}
 
-    let new_bet = sqlx::query_as!(
-        Bet,
+async fn close_bet_with(connection: &mut PgConnection, bet: &mut Bet) -> AllResult<()> {
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", updated_at FROM bets WHERE id = $1
+        FOR UPDATE
+        "#,
+        bet.id
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+    if current.updated_at != bet.updated_at {
+        return Err(BetError::Stale.into());
+    }
+    check_transition(current.status, BetStatus::Finished)?;
+    let new_bet = query_bets!(
         r#"
         UPDATE bets
         SET status = $1
         WHERE id = $2
-        RETURNING id, creator_id, description, status AS "status: BetStatus",
-        stop_bets_at, created_at, updated_at, paid_out, paid_out_at
-        "#,
-        BetStatus::PayedOut as _,
+        RETURNING
+        "#;
+        BetStatus::Finished as _,
         bet.id
     )
     .fetch_one(connection)
     .await?;
-    bet.status = BetStatus::PayedOut;
+    bet.status = BetStatus::Finished;
     bet.updated_at = new_bet.updated_at;
+    metrics::counter!(telemetry::BETS_CLOSED).increment(1);
     Ok(())
 }
 
-pub async fn get_bets_with_user(
-    connection: &sqlx::PgPool,
-    user: &User,
-) -> AllResult<Vec<(Bet, BetParticipant)>> {
-    let result = sqlx::query!(
+/// Fails unless `BetStatus::TRANSITIONS` lets a bet go from `current` to
+/// `next`, with the error that tells why not
+pub(super) fn check_transition(current: BetStatus, next: BetStatus) -> Result<(), BetError> {
+    if current.can_become(next) {
+        return Ok(());
+    }
+    Err(match (current, next) {
+        (BetStatus::Cancelled, _) => BetError::Cancelled,
+        (BetStatus::PayedOut, _) => BetError::AlreadyPaidOut,
+        (BetStatus::Active | BetStatus::Pending, BetStatus::PayedOut) => BetError::NotFinished,
+        (_, BetStatus::Finished) => BetError::NotActive,
+        _ => BetError::InvalidTransition,
+    })
+}
+
+/// Moves the bet to `status` inside the caller's transaction, locking it
+/// first. Changes that `BetStatus::TRANSITIONS` doesn't allow fail like
+/// `check_transition`.
+pub(super) async fn transition_bet(
+    connection: &mut PgConnection,
+    bet_id: i32,
+    status: BetStatus,
+) -> AllResult<Bet> {
+    let current = sqlx::query_scalar!(
+        r#"SELECT status AS "status: BetStatus" FROM bets WHERE id = $1 FOR UPDATE"#,
+        bet_id
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+    check_transition(current, status)?;
+    let bet = query_bets!(
         r#"
-        SELECT
-            bet_id, user_id, for_bet, bet_amount, participants.paid_out AS participant_paid,
-            id, creator_id, description, status AS "status: BetStatus", stop_bets_at, created_at, updated_at, bets.paid_out, paid_out_at
-        FROM bet_participants AS participants JOIN bets ON bet_id = id WHERE user_id = $1;
-        "#,
-        user.id
-    ).map(|row| (
-        Bet {
-            id: row.id,
-            creator_id: row.creator_id,
-            description: row.description,
-            status: row.status,
-            stop_bets_at: row.stop_bets_at,
-            created_at: row.created_at,
-            updated_at: row.updated_at,
-            paid_out: row.paid_out,
-            paid_out_at: row.paid_out_at,
-        },
-        BetParticipant {
-            bet_id: row.bet_id,
-            user_id: row.user_id,
-            for_bet: row.for_bet,
-            bet_amount: row.bet_amount,
-            paid_out: row.participant_paid,
-        },
-    ))
-        .fetch_all(connection)
-        .await?;
-    Ok(result)
+        UPDATE bets
+        SET status = $1
+        WHERE id = $2
+        RETURNING
+        "#;
+        status as _,
+        bet_id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(bet)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::super::{bet_participants, users::create_users};
-    use super::*;
-    use sqlx::PgPool;
+/// Pays out every participant and marks the bet paid out, all in one
+/// transaction. The bet row stays locked until the transaction commits, so
+/// concurrent payouts of the same bet wait and then fail instead of paying
+/// twice.
+///
+/// Fails with `BetError::Stale` if the bet was updated since `bet` was read,
+/// and with `BetError::UnknownOption` if `winning_option_id` isn't one of the
+/// bet's options. Over/under bets are settled with `settle_over_under_bet`.
+pub async fn payout_bet(
+    connection: &sqlx::PgPool,
+    bet: &mut Bet,
+    winning_option_id: i32,
+) -> AllResult<()> {
+    let result = payout_participants(connection, bet, Settlement::Option(winning_option_id)).await;
+    telemetry::record_payout(&result);
+    result
+}
 
-    #[sqlx::test]
-    async fn create_and_read_bet(pool: PgPool) -> AllResult<()> {
-        let mut users = create_users(&pool, vec!["Bob"]).await?;
-        let bob = users.pop().unwrap();
+/// Pays out an over/under bet like `payout_bet`. Participants who picked the
+/// side `actual_value` falls on win, and everyone is pushed (neither wins nor
+/// loses) when it is exactly on the line.
+pub async fn settle_over_under_bet(
+    connection: &sqlx::PgPool,
+    bet: &mut Bet,
+    actual_value: f64,
+) -> AllResult<()> {
+    let result = payout_participants(connection, bet, Settlement::ActualValue(actual_value)).await;
+    telemetry::record_payout(&result);
+    result
+}
 
-        let created_bet =
-            create_timeless_bet(&pool, &bob, String::from("test_description")).await?;
-        assert_eq!(created_bet.creator_id, bob.id);
-        assert_eq!(created_bet.description, String::from("test_description"));
-        assert_eq!(created_bet.paid_out, false);
-        assert_eq!(created_bet.status, BetStatus::Active);
+enum Settlement {
+    Option(i32),
+    ActualValue(f64),
+    /// The option participants voted for
+    Vote(i32),
+}
 
-        let read_bet = get_bet_by_id(&pool, bob.id).await?;
-        assert_eq!(created_bet, read_bet);
+async fn payout_participants(
+    connection: &sqlx::PgPool,
+    bet: &mut Bet,
+    settlement: Settlement,
+) -> AllResult<()> {
+    let mut transaction = connection.begin().await?;
+    let new_bet = payout_locked(&mut transaction, bet, settlement).await?;
+    transaction.commit().await?;
+    *bet = new_bet;
+    Ok(())
+}
 
-        Ok(())
+/// Pays out `bet` inside the caller's transaction and returns the paid out
+/// bet. Nothing is paid until the caller commits.
+async fn payout_locked(
+    transaction: &mut PgConnection,
+    bet: &Bet,
+    settlement: Settlement,
+) -> AllResult<Bet> {
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", updated_at, kind AS "kind: BetKind", line,
+        resolution AS "resolution: BetResolution", disputed_at, version
+        FROM bets WHERE id = $1
+        FOR UPDATE
+        "#,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    check_transition(current.status, BetStatus::PayedOut)?;
+    if current.updated_at != bet.updated_at {
+        return Err(BetError::Stale.into());
     }
 
-    #[sqlx::test]
-    async fn run_bet_no_participants(pool: PgPool) -> AllResult<()> {
-        let mut users = create_users(&pool, vec!["Bob"]).await?;
-        let bob = users.pop().unwrap();
-
-        let mut bet = create_timeless_bet(&pool, &bob, String::from("test_description")).await?;
+    // Bets resolved by vote are paid out by hand only once escalated
+    let escalated = current.disputed_at.is_some();
+    match (&settlement, current.resolution) {
+        (Settlement::Vote(_), BetResolution::Vote) if !escalated => {}
+        (Settlement::Vote(_), _) => return Err(BetError::NotVoting.into()),
+        (_, BetResolution::Vote) if !escalated => return Err(BetError::AwaitingVotes.into()),
+        _ => {}
+    }
 
-        assert_eq!(bet.status, BetStatus::Active);
+    let (winning_option_id, actual_value) = match (settlement, current.kind, current.line) {
+        (Settlement::Option(option_id) | Settlement::Vote(option_id), BetKind::Options, _) => {
+            get_bet_option(&mut *transaction, bet, option_id).await?;
+            (Some(option_id), None)
+        }
+        (Settlement::ActualValue(actual_value), BetKind::OverUnder, Some(line)) => {
+            if !actual_value.is_finite() {
+                return Err(BetError::InvalidLine.into());
+            }
+            let [over, under] = OVER_UNDER;
+            let winning_label = match actual_value.partial_cmp(&line) {
+                Some(Ordering::Greater) => Some(over),
+                Some(Ordering::Less) => Some(under),
+                _ => None,
+            };
+            let winning_option_id = match winning_label {
+                Some(label) => Some(
+                    get_bet_option_by_label(&mut *transaction, bet, label)
+                        .await?
+                        .id,
+                ),
+                None => None,
+            };
+            (winning_option_id, Some(actual_value))
+        }
+        _ => return Err(BetError::WrongKind.into()),
+    };
 
-        let bet_copy = bet.clone();
-        close_bet(&pool, &mut bet).await?;
+    let participants_to_payout = get_bet_participants(&mut *transaction, bet).await?;
+    for participant in participants_to_payout {
+        // Participants who never confirmed the latest edit are pushed
+        let winning_option_id = if participant.confirmed_version == current.version {
+            winning_option_id
+        } else {
+            None
+        };
+        payout_participant(&mut *transaction, participant, winning_option_id).await?;
+    }
 
-        assert_eq!(bet_copy.id, bet.id);
-        assert_eq!(bet_copy.creator_id, bet.creator_id);
-        assert_ne!(bet_copy.updated_at, bet.updated_at);
-        assert_eq!(bet.status, BetStatus::Finished);
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    let new_bet = query_bets!(
+        r#"
+        UPDATE bets
+        SET status = $1, paid_out = TRUE, paid_out_at = $2, winning_option_id = $3,
+        actual_value = $4
+        WHERE id = $5
+        RETURNING
+        "#;
+        BetStatus::PayedOut as _,
+        now,
+        winning_option_id,
+        actual_value,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    settle_attachments(&mut *transaction, bet.id, now).await?;
+    settle_parlays(
+        &mut *transaction,
+        bet.id,
+        current.version,
+        winning_option_id,
+        now,
+    )
+    .await?;
+    Ok(new_bet)
+}
 
-        let bet_copy = bet.clone();
-        payout_bet(&pool, &mut bet, true).await?;
+/// Records `user`'s vote on a closed bet resolved by vote. Once `quorum`
+/// votes agree the option is proposed as the outcome, and votes can't change
+/// anymore.
+pub async fn vote_on_bet(
+    connection: &sqlx::PgPool,
+    bet: &mut Bet,
+    user: &User,
+    option_id: i32,
+) -> AllResult<()> {
+    let mut transaction = connection.begin().await?;
+
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", resolution AS "resolution: BetResolution",
+        quorum, proposed_option_id, disputed_at
+        FROM bets WHERE id = $1
+        FOR UPDATE
+        "#,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let (BetResolution::Vote, Some(quorum)) = (current.resolution, current.quorum) else {
+        return Err(BetError::NotVoting.into());
+    };
+    match current.status {
+        BetStatus::Active | BetStatus::Pending => return Err(BetError::NotFinished.into()),
+        BetStatus::PayedOut => return Err(BetError::AlreadyPaidOut.into()),
+        BetStatus::Cancelled => return Err(BetError::Cancelled.into()),
+        BetStatus::Finished => {}
+    }
+    if current.proposed_option_id.is_some() || current.disputed_at.is_some() {
+        return Err(BetError::VotingClosed.into());
+    }
+    get_bet_participant(&mut *transaction, bet, user).await?;
+    get_bet_option(&mut *transaction, bet, option_id).await?;
+
+    cast_vote(&mut *transaction, bet, user, option_id).await?;
+    if count_votes(&mut *transaction, bet, option_id).await? >= i64::from(quorum) {
+        let now = sqlx::types::chrono::Local::now().naive_local();
+        *bet = query_bets!(
+            r#"
+            UPDATE bets
+            SET proposed_option_id = $1, proposed_at = $2
+            WHERE id = $3
+            RETURNING
+            "#;
+            option_id,
+            now,
+            bet.id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Contests the proposed outcome of a bet resolved by vote. Disputed bets
+/// aren't paid out automatically, they are escalated to the bet's arbiter, or
+/// to the creator when there is none.
+pub async fn dispute_bet(connection: &sqlx::PgPool, bet: &mut Bet, user: &User) -> AllResult<()> {
+    let mut transaction = connection.begin().await?;
+
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", resolution AS "resolution: BetResolution",
+        dispute_window_seconds, proposed_at, disputed_at
+        FROM bets WHERE id = $1
+        FOR UPDATE
+        "#,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let (BetResolution::Vote, Some(dispute_window_seconds)) =
+        (current.resolution, current.dispute_window_seconds)
+    else {
+        return Err(BetError::NotVoting.into());
+    };
+    match current.status {
+        BetStatus::PayedOut => return Err(BetError::AlreadyPaidOut.into()),
+        BetStatus::Cancelled => return Err(BetError::Cancelled.into()),
+        BetStatus::Active | BetStatus::Finished | BetStatus::Pending => {}
+    }
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    let window = chrono::TimeDelta::seconds(dispute_window_seconds.into());
+    let open = current
+        .proposed_at
+        .is_some_and(|proposed_at| now < proposed_at + window);
+    if !open || current.disputed_at.is_some() {
+        return Err(BetError::NotDisputable.into());
+    }
+    get_bet_participant(&mut *transaction, bet, user).await?;
+
+    *bet = query_bets!(
+        r#"
+        UPDATE bets
+        SET disputed_by = $1, disputed_at = $2
+        WHERE id = $3
+        RETURNING
+        "#;
+        user.id,
+        now,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Pays out every bet resolved by vote whose proposed outcome went
+/// undisputed for its whole dispute window as of `now`.
+///
+/// Each bet is paid out in its own transaction, so one that fails is logged
+/// and retried on the next pass without holding back the others. Like
+/// `close_expired_bets`, due bets are locked with `SKIP LOCKED` so several
+/// servers can run this at once.
+pub async fn settle_voted_bets(
+    connection: &sqlx::PgPool,
+    now: NaiveDateTime,
+) -> AllResult<Vec<Bet>> {
+    let due = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM bets
+        WHERE status = $1 AND resolution = $2 AND disputed_at IS NULL
+        AND proposed_at + make_interval(secs => dispute_window_seconds) <= $3
+        ORDER BY id
+        "#,
+        BetStatus::Finished as _,
+        BetResolution::Vote as _,
+        now
+    )
+    .fetch_all(connection)
+    .await?;
+
+    let mut settled = Vec::with_capacity(due.len());
+    for bet_id in due {
+        let result = settle_voted_bet(connection, bet_id, now).await;
+        // Bets another server took or that stopped being due aren't counted
+        if !matches!(result, Ok(None)) {
+            telemetry::record_payout(&result);
+        }
+        match result {
+            Ok(Some(bet)) => settled.push(bet),
+            Ok(None) => {}
+            Err(error) => telemetry::report_failure(
+                "settle",
+                format_args!("unable to pay out bet {bet_id}: {error}"),
+            ),
+        }
+    }
+    Ok(settled)
+}
+
+/// Pays out one bet for `settle_voted_bets` and commits, or returns `None`
+/// when it's locked by another server or no longer due
+async fn settle_voted_bet(
+    connection: &sqlx::PgPool,
+    bet_id: i32,
+    now: NaiveDateTime,
+) -> AllResult<Option<Bet>> {
+    let mut transaction = connection.begin().await?;
+    let bet = query_bets!(
+        "SELECT",
+        r#"
+        FROM bets
+        WHERE id = $1 AND status = $2 AND resolution = $3 AND disputed_at IS NULL
+        AND proposed_at + make_interval(secs => dispute_window_seconds) <= $4
+        FOR UPDATE SKIP LOCKED
+        "#;
+        bet_id,
+        BetStatus::Finished as _,
+        BetResolution::Vote as _,
+        now
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(bet) = bet else {
+        return Ok(None);
+    };
+    let Some(proposed_option_id) = bet.proposed_option_id else {
+        return Ok(None);
+    };
+
+    let settled =
+        payout_locked(&mut transaction, &bet, Settlement::Vote(proposed_option_id)).await?;
+    transaction.commit().await?;
+    Ok(Some(settled))
+}
+
+/// Asks `arbiter` to judge an active bet, replacing an arbiter who didn't
+/// accept yet. The arbiter can be neither the creator nor a participant.
+///
+/// Fails with `BetError::Stale` if the bet was updated since `bet` was read
+pub async fn name_arbiter(
+    connection: &sqlx::PgPool,
+    bet: &mut Bet,
+    arbiter: &User,
+) -> AllResult<()> {
+    let mut transaction = connection.begin().await?;
+
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", updated_at,
+        arbiter_status AS "arbiter_status: ArbiterStatus"
+        FROM bets WHERE id = $1
+        FOR UPDATE
+        "#,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    if current.status != BetStatus::Active {
+        return Err(BetError::NotActive.into());
+    }
+    if current.updated_at != bet.updated_at {
+        return Err(BetError::Stale.into());
+    }
+    if current.arbiter_status == Some(ArbiterStatus::Accepted) {
+        return Err(BetError::ArbiterAccepted.into());
+    }
+    // Joining share locks the bet, so nobody can join while this runs
+    let participating = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM bet_participants WHERE bet_id = $1 AND user_id = $2
+        ) AS "participating!"
+        "#,
+        bet.id,
+        arbiter.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    if participating || arbiter.id == bet.creator_id {
+        return Err(BetError::InvalidArbiter.into());
+    }
+
+    *bet = query_bets!(
+        r#"
+        UPDATE bets
+        SET arbiter_id = $1, arbiter_status = $2
+        WHERE id = $3
+        RETURNING
+        "#;
+        arbiter.id,
+        ArbiterStatus::Pending as _,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Lets the pending arbiter accept or decline judging the bet. Once accepted
+/// only the arbiter can close and pay out the bet, see `Bet::manager_id`.
+pub async fn answer_arbiter_role(
+    connection: &sqlx::PgPool,
+    bet: &mut Bet,
+    user: &User,
+    answer: ArbiterStatus,
+) -> AllResult<()> {
+    let mut transaction = connection.begin().await?;
+
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", arbiter_id,
+        arbiter_status AS "arbiter_status: ArbiterStatus"
+        FROM bets WHERE id = $1
+        FOR UPDATE
+        "#,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    match current.status {
+        BetStatus::PayedOut => return Err(BetError::AlreadyPaidOut.into()),
+        BetStatus::Cancelled => return Err(BetError::Cancelled.into()),
+        BetStatus::Active | BetStatus::Finished | BetStatus::Pending => {}
+    }
+    if current.arbiter_id != Some(user.id) || current.arbiter_status != Some(ArbiterStatus::Pending)
+    {
+        return Err(BetError::NotArbiter.into());
+    }
+
+    *bet = query_bets!(
+        r#"
+        UPDATE bets
+        SET arbiter_status = $1
+        WHERE id = $2
+        RETURNING
+        "#;
+        answer as _,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Cancels the bet, returning every stake and recording no win or loss. Bets
+/// that were paid out can only be voided with `after_payout`, which takes
+/// back the wins and losses the payout added to `scores` and clears the
+/// bet's payout.
+///
+/// Fails with `BetError::Stale` if the bet was updated since `bet` was read
+pub async fn void_bet(
+    connection: &sqlx::PgPool,
+    bet: &mut Bet,
+    after_payout: bool,
+) -> AllResult<()> {
+    let mut transaction = connection.begin().await?;
+
+    let current = sqlx::query!(
+        r#"
+        SELECT status AS "status: BetStatus", updated_at
+        FROM bets WHERE id = $1
+        FOR UPDATE
+        "#,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    check_transition(current.status, BetStatus::Cancelled)?;
+    if current.status == BetStatus::PayedOut && !after_payout {
+        return Err(BetError::AlreadyPaidOut.into());
+    }
+    if current.updated_at != bet.updated_at {
+        return Err(BetError::Stale.into());
+    }
+
+    let participants = get_bet_participants(&mut *transaction, bet).await?;
+    for participant in participants {
+        void_participant(&mut transaction, participant).await?;
+    }
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    cancel_parlays(&mut transaction, bet.id, now).await?;
+
+    let new_bet = query_bets!(
+        r#"
+        UPDATE bets
+        SET status = $1, winning_option_id = NULL, paid_out = FALSE, paid_out_at = NULL
+        WHERE id = $2
+        RETURNING
+        "#;
+        BetStatus::Cancelled as _,
+        bet.id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+    *bet = new_bet;
+    metrics::counter!(telemetry::BETS_VOIDED).increment(1);
+    Ok(())
+}
+
+/// Bets `user` participates in that `viewer` can see
+pub async fn get_bets_with_user(
+    connection: &sqlx::PgPool,
+    user: &User,
+    viewer: Option<&User>,
+) -> AllResult<Vec<(Bet, BetParticipant)>> {
+    let result = query_bets!(
+        record:
+        r#"
+        SELECT
+        participants.bet_id, participants.user_id, participants.option_id,
+        participants.bet_amount, participants.paid_out AS participant_paid, participants.won,
+        participants.confirmed_version,
+        "#,
+        r#"
+        FROM bet_participants AS participants JOIN bets ON participants.bet_id = bets.id
+        WHERE participants.user_id = $1 AND bet_visible_to(bets, $2)
+        "#;
+        user.id,
+        viewer.map(|viewer| viewer.id)
+    )
+    .map(|row| {
+        let participant = BetParticipant {
+            bet_id: row.bet_id,
+            user_id: row.user_id,
+            option_id: row.option_id,
+            bet_amount: row.bet_amount,
+            paid_out: row.participant_paid,
+            won: row.won,
+            confirmed_version: row.confirmed_version,
+        };
+        (bet_from_row!(row), participant)
+    })
+    .fetch_all(connection)
+    .await?;
+    Ok(result)
+}
+
+/// Bets `viewer` can see whose description matches `query`, best matches
+/// first. `query` uses web search syntax: quoted phrases, `or` and `-` to
+/// exclude a word. Matching words are wrapped in `<b>` tags in the snippet.
+pub async fn search_bets(
+    connection: &sqlx::PgPool,
+    query: &str,
+    viewer: Option<&User>,
+    limit: i64,
+) -> AllResult<Vec<BetMatch>> {
+    let matches = query_bets!(
+        record:
+        r#"
+        SELECT
+        ts_rank(search, query) AS "rank!",
+        ts_headline(
+            'english', html_escape(description), query, 'StartSel=<b>, StopSel=</b>'
+        ) AS "snippet!",
+        "#,
+        r#"
+        FROM bets, websearch_to_tsquery('english', $1) AS query
+        WHERE search @@ query AND bet_visible_to(bets, $2)
+        ORDER BY ts_rank(search, query) DESC, id
+        LIMIT $3
+        "#;
+        query,
+        viewer.map(|viewer| viewer.id),
+        limit
+    )
+    .map(|row| BetMatch {
+        rank: row.rank,
+        snippet: row.snippet,
+        bet: bet_from_row!(row),
+    })
+    .fetch_all(connection)
+    .await?;
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        bet_invitations::{answer_invitation, invite_to_bet},
+        bet_options::{get_bet_options, yes_no_options, OVER_UNDER},
+        bet_participants,
+        bet_versions::get_bet_versions,
+        friendships::{respond_to_friend_request, send_friend_request, FriendRequestResponse},
+        scores::read_category_scores_by_username,
+        users::create_users,
+    };
+    use super::*;
+    use crate::models::InvitationStatus;
+    use chrono::SubsecRound;
+    use sqlx::PgPool;
+
+    /// Ids of the active bets `viewer` sees, created by `creator` and
+    /// joined by `participant`, in that order
+    async fn visible_ids(
+        pool: &PgPool,
+        viewer: Option<&User>,
+        creator: &User,
+        participant: &User,
+    ) -> AllResult<[Vec<i32>; 3]> {
+        let by_status =
+            get_bets_by_status(pool, &BetStatus::Active, viewer, &BetFilter::default()).await?;
+        let by_user = get_bets_by_user(pool, creator, viewer, &BetFilter::default()).await?;
+        let with_user = get_bets_with_user(pool, participant, viewer).await?;
+        Ok([
+            by_status.iter().map(|bet| bet.id).collect(),
+            by_user.iter().map(|bet| bet.id).collect(),
+            with_user.iter().map(|(bet, _)| bet.id).collect(),
+        ])
+    }
+
+    async fn create_friends(pool: &PgPool, user: &User, friend: &User) -> AllResult<()> {
+        send_friend_request(pool, user, friend).await?;
+        respond_to_friend_request(pool, friend, user, FriendRequestResponse::Accept).await?;
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn create_and_read_bet(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let created_bet =
+            create_timeless_bet(&pool, &bob, String::from("test_description")).await?;
+        assert_eq!(created_bet.creator_id, bob.id);
+        assert_eq!(created_bet.description, String::from("test_description"));
+        assert!(!created_bet.paid_out);
+        assert_eq!(created_bet.status, BetStatus::Active);
+
+        let read_bet = get_bet_by_id(&pool, bob.id).await?;
+        assert_eq!(created_bet, read_bet);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn settings_are_written_with_the_bet(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+        let limits = StakeLimits {
+            min_stake: Some(5),
+            max_stake: Some(50),
+            max_participants: Some(2),
+        };
+        let labels = BetLabels {
+            category: Some(String::from("Sports")),
+            tags: vec![String::from("NBA")],
+        };
+
+        for (settings, error) in [
+            (
+                BetSettings {
+                    limits: StakeLimits {
+                        min_stake: Some(50),
+                        max_stake: Some(5),
+                        max_participants: None,
+                    },
+                    ..BetSettings::default()
+                },
+                BetError::InvalidLimits,
+            ),
+            (
+                BetSettings {
+                    labels: BetLabels {
+                        category: Some(String::from("Knitting")),
+                        tags: vec![],
+                    },
+                    limits,
+                    ..BetSettings::default()
+                },
+                BetError::UnknownCategory,
+            ),
+        ] {
+            let result =
+                create_bet(&pool, &bob, String::from("Finals"), None, None, &settings).await;
+            assert_eq!(result.unwrap_err().downcast_ref::<BetError>(), Some(&error));
+        }
+        let bets = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM bets"#)
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(bets, 0);
+
+        let settings = BetSettings {
+            visibility: BetVisibility::Friends,
+            labels,
+            limits,
+        };
+        let bet = create_bet(&pool, &bob, String::from("Finals"), None, None, &settings).await?;
+        assert_eq!(bet.visibility, BetVisibility::Friends);
+        assert!(bet.category_id.is_some());
+        assert_eq!(bet.tags, ["nba"]);
+        assert_eq!(
+            (bet.min_stake, bet.max_stake, bet.max_participants),
+            (Some(5), Some(50), Some(2))
+        );
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn run_bet_no_participants(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("test_description")).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+
+        assert_eq!(bet.status, BetStatus::Active);
+
+        let bet_copy = bet.clone();
+        close_bet(&pool, &mut bet).await?;
+
+        assert_eq!(bet_copy.id, bet.id);
+        assert_eq!(bet_copy.creator_id, bet.creator_id);
+        assert_ne!(bet_copy.updated_at, bet.updated_at);
+        assert_eq!(bet.status, BetStatus::Finished);
+
+        let bet_copy = bet.clone();
+        payout_bet(&pool, &mut bet, yes).await?;
 
         assert_eq!(bet_copy.id, bet.id);
         assert_eq!(bet_copy.creator_id, bet.creator_id);
@@ -238,6 +1389,1119 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn payout_marks_bet_paid_out(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, no) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 20, no).await?;
+
+        let error = payout_bet(&pool, &mut bet.clone(), yes).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotFinished));
+
+        close_bet(&pool, &mut bet).await?;
+        payout_bet(&pool, &mut bet, yes).await?;
+
+        assert!(bet.paid_out);
+        assert!(bet.paid_out_at.is_some());
+        assert_eq!(bet, get_bet_by_id(&pool, bet.id).await?);
+
+        let error = payout_bet(&pool, &mut bet, yes).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::AlreadyPaidOut));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn failed_payout_pays_nobody(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 20, yes).await?;
+        close_bet(&pool, &mut bet).await?;
+
+        // John's score row is missing, so paying him fails after Bob was paid
+        sqlx::query!("DELETE FROM scores WHERE user_id = $1", john.id)
+            .execute(&pool)
+            .await?;
+
+        assert!(payout_bet(&pool, &mut bet.clone(), yes).await.is_err());
+
+        let read_bet = get_bet_by_id(&pool, bet.id).await?;
+        assert_eq!(read_bet.status, BetStatus::Finished);
+        assert!(!read_bet.paid_out);
+        let participants = bet_participants::get_bet_participants(&pool, &bet).await?;
+        assert!(participants.iter().all(|participant| !participant.paid_out));
+        let bob_score = super::super::scores::read_user_score(&pool, &bob).await?;
+        assert_eq!(bob_score.total_wins, 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn concurrent_payouts_pay_once(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, no) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 20, no).await?;
+        close_bet(&pool, &mut bet).await?;
+
+        let settlements: Vec<_> = (0..8)
+            .map(|_| {
+                let pool = pool.clone();
+                let mut bet = bet.clone();
+                tokio::spawn(async move { payout_bet(&pool, &mut bet, yes).await.is_ok() })
+            })
+            .collect();
+
+        let mut succeeded = 0;
+        for settlement in settlements {
+            if settlement.await? {
+                succeeded += 1;
+            }
+        }
+        assert_eq!(succeeded, 1);
+
+        let bob_score = super::super::scores::read_user_score(&pool, &bob).await?;
+        assert_eq!(bob_score.total_wins, 1);
+        assert_eq!(bob_score.points_earned, 10);
+        let john_score = super::super::scores::read_user_score(&pool, &john).await?;
+        assert_eq!(john_score.total_losses, 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn payout_to_one_of_many_options(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Alice", "Bob", "Carol"]).await?;
+        let alice = users.pop().unwrap();
+        let bob = users.pop().unwrap();
+        let carol = users.pop().unwrap();
+
+        let entrants = vec!["Alice".into(), "Bob".into(), "Carol".into()];
+        let mut bet = create_bet(
+            &pool,
+            &alice,
+            String::from("chili"),
+            None,
+            Some(&entrants),
+            &BetSettings::default(),
+        )
+        .await?;
+        let options = get_bet_options(&pool, &bet).await?;
+
+        bet_participants::create_bet_participant(&pool, &alice, &bet, 10, options[0].id).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 20, options[2].id).await?;
+        bet_participants::create_bet_participant(&pool, &carol, &bet, 30, options[2].id).await?;
+        close_bet(&pool, &mut bet).await?;
+
+        let other_bet = create_timeless_bet(&pool, &alice, String::from("other")).await?;
+        let (other_yes, _) = yes_no_options(&pool, &other_bet).await?;
+        let error = payout_bet(&pool, &mut bet.clone(), other_yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::UnknownOption));
+        assert_eq!(
+            get_bet_by_id(&pool, bet.id).await?.status,
+            BetStatus::Finished
+        );
+
+        payout_bet(&pool, &mut bet, options[2].id).await?;
+        assert_eq!(bet.winning_option_id, Some(options[2].id));
+
+        let won: Vec<_> = bet_participants::get_bet_participants(&pool, &bet)
+            .await?
+            .into_iter()
+            .map(|participant| (participant.user_id, participant.won))
+            .collect();
+        assert!(won.contains(&(alice.id, Some(false))));
+        assert!(won.contains(&(bob.id, Some(true))));
+        assert!(won.contains(&(carol.id, Some(true))));
+
+        let carol_score = super::super::scores::read_user_score(&pool, &carol).await?;
+        assert_eq!(carol_score.points_earned, 30);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn settle_over_under(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let error = create_over_under_bet(
+            &pool,
+            &bob,
+            String::from("late"),
+            None,
+            f64::NAN,
+            &BetSettings::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidLine));
+
+        for (actual_value, bob_won) in [(7.0, Some(true)), (2.0, Some(false)), (4.5, None)] {
+            let mut bet = create_over_under_bet(
+                &pool,
+                &bob,
+                String::from("late"),
+                None,
+                4.5,
+                &BetSettings::default(),
+            )
+            .await?;
+            assert_eq!(bet.kind, BetKind::OverUnder);
+            assert_eq!(bet.line, Some(4.5));
+            let options = get_bet_options(&pool, &bet).await?;
+            let labels: Vec<_> = options.iter().map(|option| option.label.as_str()).collect();
+            assert_eq!(labels, OVER_UNDER);
+            let (over, under) = (options[0].id, options[1].id);
+
+            bet_participants::create_bet_participant(&pool, &bob, &bet, 10, over).await?;
+            bet_participants::create_bet_participant(&pool, &john, &bet, 20, under).await?;
+            close_bet(&pool, &mut bet).await?;
+
+            let error = payout_bet(&pool, &mut bet.clone(), over).await.unwrap_err();
+            assert_eq!(error.downcast_ref(), Some(&BetError::WrongKind));
+
+            settle_over_under_bet(&pool, &mut bet, actual_value).await?;
+            assert_eq!(bet.status, BetStatus::PayedOut);
+            assert_eq!(bet.actual_value, Some(actual_value));
+            let expected_winner = bob_won.map(|bob_won| if bob_won { over } else { under });
+            assert_eq!(bet.winning_option_id, expected_winner);
+
+            let participants = bet_participants::get_bet_participants(&pool, &bet).await?;
+            assert!(participants.iter().all(|participant| participant.paid_out));
+            let bob_participant = participants
+                .iter()
+                .find(|participant| participant.user_id == bob.id)
+                .unwrap();
+            assert_eq!(bob_participant.won, bob_won);
+        }
+
+        // One win, one loss and one push each
+        let bob_score = super::super::scores::read_user_score(&pool, &bob).await?;
+        assert_eq!(bob_score.total_wins, 1);
+        assert_eq!(bob_score.total_losses, 1);
+        assert_eq!(bob_score.points_earned, 10);
+        let john_score = super::super::scores::read_user_score(&pool, &john).await?;
+        assert_eq!(john_score.total_wins, 1);
+        assert_eq!(john_score.total_losses, 1);
+        assert_eq!(john_score.points_earned, 20);
+
+        let mut options_bet = create_timeless_bet(&pool, &bob, String::from("yes or no")).await?;
+        close_bet(&pool, &mut options_bet).await?;
+        let error = settle_over_under_bet(&pool, &mut options_bet, 1.0)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::WrongKind));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn void_returns_stakes(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let mut active = create_timeless_bet(&pool, &bob, String::from("active")).await?;
+        let (yes, no) = yes_no_options(&pool, &active).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &active, 10, yes).await?;
+        bet_participants::create_bet_participant(&pool, &john, &active, 20, no).await?;
+
+        let mut stale = active.clone();
+        void_bet(&pool, &mut active, false).await?;
+        assert_eq!(active.status, BetStatus::Cancelled);
+        assert_eq!(active, get_bet_by_id(&pool, active.id).await?);
+
+        let participants = bet_participants::get_bet_participants(&pool, &active).await?;
+        assert!(participants
+            .iter()
+            .all(|participant| participant.paid_out && participant.won.is_none()));
+
+        let error = void_bet(&pool, &mut stale, false).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::Cancelled));
+        let error = payout_bet(&pool, &mut active, yes).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::Cancelled));
+        let error = bet_participants::create_bet_participant(&pool, &john, &active, 5, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotActive));
+
+        let mut finished = create_timeless_bet(&pool, &bob, String::from("finished")).await?;
+        close_bet(&pool, &mut finished).await?;
+        void_bet(&pool, &mut finished, false).await?;
+        assert_eq!(finished.status, BetStatus::Cancelled);
+
+        let bob_score = super::super::scores::read_user_score(&pool, &bob).await?;
+        assert_eq!(bob_score.total_wins, 0);
+        assert_eq!(bob_score.total_losses, 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn force_void_takes_back_results(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let mut kept = create_timeless_bet(&pool, &bob, String::from("kept")).await?;
+        let (kept_yes, _) = yes_no_options(&pool, &kept).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &kept, 5, kept_yes).await?;
+        close_bet(&pool, &mut kept).await?;
+        payout_bet(&pool, &mut kept, kept_yes).await?;
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("voided")).await?;
+        let (yes, no) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 20, no).await?;
+        close_bet(&pool, &mut bet).await?;
+        payout_bet(&pool, &mut bet, yes).await?;
+
+        let error = void_bet(&pool, &mut bet, false).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::AlreadyPaidOut));
+
+        void_bet(&pool, &mut bet, true).await?;
+        assert_eq!(bet.status, BetStatus::Cancelled);
+        assert_eq!(bet.winning_option_id, None);
+        assert!(!bet.paid_out);
+        assert_eq!(bet.paid_out_at, None);
+
+        let bob_score = super::super::scores::read_user_score(&pool, &bob).await?;
+        assert_eq!(bob_score.total_wins, 1);
+        assert_eq!(bob_score.points_earned, 5);
+        let john_score = super::super::scores::read_user_score(&pool, &john).await?;
+        assert_eq!(john_score.total_losses, 0);
+
+        let recomputed = super::super::scores::recompute_scores(&pool).await?;
+        assert!(recomputed.contains(&bob_score));
+        assert!(recomputed.contains(&john_score));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn voided_bets_are_left_out_of_recomputed_scores(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("voided")).await?;
+        let sports = BetLabels {
+            category: Some(String::from("Sports")),
+            tags: vec![],
+        };
+        label_bet(&pool, &mut bet, &sports).await?;
+        let (yes, no) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 20, no).await?;
+        close_bet(&pool, &mut bet).await?;
+        payout_bet(&pool, &mut bet, yes).await?;
+        void_bet(&pool, &mut bet, true).await?;
+
+        let recomputed = super::super::scores::recompute_scores(&pool).await?;
+        assert_eq!(recomputed.len(), 2);
+        assert!(recomputed.iter().all(|score| (
+            score.total_wins,
+            score.total_losses,
+            score.points_earned
+        ) == (0, 0, 0)));
+        for user in [&bob, &john] {
+            let scores = read_category_scores_by_username(&pool, &user.username).await?;
+            assert!(scores.is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn status_transitions_match_the_database(pool: PgPool) -> AllResult<()> {
+        let mut rows: Vec<_> = sqlx::query!(
+            r#"
+            SELECT from_status AS "from_status: BetStatus", to_status AS "to_status: BetStatus"
+            FROM bet_status_transitions
+            "#
+        )
+        .fetch_all(&pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.from_status, row.to_status))
+        .collect();
+        let initial = BetStatus::INITIAL.map(|status| (None, status));
+        let transitions = BetStatus::TRANSITIONS.map(|(from, to)| (Some(from), to));
+        let mut expected: Vec<_> = initial.into_iter().chain(transitions).collect();
+        let key = |&(from, to): &(Option<BetStatus>, BetStatus)| format!("{from:?} {to:?}");
+        rows.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(rows, expected);
+
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        let error = payout_bet(&pool, &mut bet, yes).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotFinished));
+        close_bet(&pool, &mut bet).await?;
+        let error = close_bet(&pool, &mut bet).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotActive));
+        // The status of the locked row counts, not the one that was read
+        let mut misread = bet.clone();
+        misread.status = BetStatus::Active;
+        let error = close_bet(&pool, &mut misread).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotActive));
+        payout_bet(&pool, &mut bet, yes).await?;
+
+        // Code that skips the checks is stopped by the database
+        let error = sqlx::query!(
+            "UPDATE bets SET status = $1 WHERE id = $2",
+            BetStatus::Active as _,
+            bet.id
+        )
+        .execute(&pool)
+        .await
+        .unwrap_err();
+        let error = error.as_database_error().unwrap();
+        assert!(error.is_check_violation());
+        let error = sqlx::query!(
+            "INSERT INTO bets (creator_id, description, status, paid_out) VALUES ($1, $2, $3, FALSE)",
+            bob.id,
+            "description",
+            BetStatus::PayedOut as _
+        )
+        .execute(&pool)
+        .await
+        .unwrap_err();
+        assert!(error.as_database_error().unwrap().is_check_violation());
+        assert_eq!(
+            get_bet_by_id(&pool, bet.id).await?.status,
+            BetStatus::PayedOut
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn public_bets_are_visible_to_everyone(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let bet = create_timeless_bet(&pool, &bob, String::from("public")).await?;
+        assert_eq!(bet.visibility, BetVisibility::Public);
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+
+        let ids = vec![bet.id];
+        for viewer in [None, Some(&bob), Some(&john)] {
+            assert_eq!(
+                visible_ids(&pool, viewer, &bob, &bob).await?,
+                [ids.clone(), ids.clone(), ids.clone()]
+            );
+            assert_eq!(get_visible_bet_by_id(&pool, bet.id, viewer).await?, bet);
+        }
+        bet_participants::create_bet_participant(&pool, &john, &bet, 10, yes).await?;
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn friends_bets_are_visible_to_friends(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John", "Stranger"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        let stranger = users.pop().unwrap();
+        create_friends(&pool, &bob, &john).await?;
+
+        let bet = create_bet(
+            &pool,
+            &bob,
+            String::from("friends"),
+            None,
+            None,
+            &BetSettings {
+                visibility: BetVisibility::Friends,
+                ..BetSettings::default()
+            },
+        )
+        .await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+
+        let ids = vec![bet.id];
+        for viewer in [Some(&bob), Some(&john)] {
+            assert_eq!(
+                visible_ids(&pool, viewer, &bob, &bob).await?,
+                [ids.clone(), ids.clone(), ids.clone()]
+            );
+            assert!(get_visible_bet_by_id(&pool, bet.id, viewer).await.is_ok());
+        }
+        for viewer in [None, Some(&stranger)] {
+            assert_eq!(
+                visible_ids(&pool, viewer, &bob, &bob).await?,
+                <[Vec<i32>; 3]>::default()
+            );
+            assert!(get_visible_bet_by_id(&pool, bet.id, viewer).await.is_err());
+        }
+
+        let error = bet_participants::create_bet_participant(&pool, &stranger, &bet, 10, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::FriendsOnly));
+        bet_participants::create_bet_participant(&pool, &john, &bet, 10, yes).await?;
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn private_bets_are_visible_to_participants(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John", "Jane"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        let jane = users.pop().unwrap();
+        create_friends(&pool, &bob, &john).await?;
+        create_friends(&pool, &bob, &jane).await?;
+
+        let bet = create_bet(
+            &pool,
+            &bob,
+            String::from("private"),
+            None,
+            None,
+            &BetSettings {
+                visibility: BetVisibility::Private,
+                ..BetSettings::default()
+            },
+        )
+        .await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+
+        // Being a friend isn't enough
+        for viewer in [None, Some(&john)] {
+            assert_eq!(
+                visible_ids(&pool, viewer, &bob, &bob).await?,
+                <[Vec<i32>; 3]>::default()
+            );
+            assert!(get_visible_bet_by_id(&pool, bet.id, viewer).await.is_err());
+        }
+
+        invite_to_bet(&pool, &bet, &john).await?;
+        answer_invitation(&pool, &bet, &john, InvitationStatus::Accepted).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 10, yes).await?;
+        let ids = vec![bet.id];
+        for viewer in [Some(&bob), Some(&john)] {
+            assert_eq!(
+                visible_ids(&pool, viewer, &bob, &john).await?,
+                [ids.clone(), ids.clone(), ids.clone()]
+            );
+        }
+        assert_eq!(
+            visible_ids(&pool, Some(&jane), &bob, &john).await?,
+            <[Vec<i32>; 3]>::default()
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn edits_before_first_join_are_free(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("Will it rian?")).await?;
+        let edit = BetEdit {
+            description: Some(String::from("Will it rain?")),
+            options: Some(vec!["Rain".into(), "Sun".into(), "Snow".into()]),
+            ..Default::default()
+        };
+        edit_bet(&pool, &mut bet, edit).await?;
+        assert_eq!(bet.description, "Will it rain?");
+        assert_eq!(bet.version, 1);
+
+        let labels: Vec<_> = get_bet_options(&pool, &bet)
+            .await?
+            .into_iter()
+            .map(|option| option.label)
+            .collect();
+        assert_eq!(labels, ["Rain", "Sun", "Snow"]);
+        let versions = get_bet_versions(&pool, &bet).await?;
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].description, "Will it rain?");
+        assert_eq!(versions[0].options, labels);
+
+        let edit = BetEdit {
+            options: Some(vec!["Rain".into()]),
+            ..Default::default()
+        };
+        let error = edit_bet(&pool, &mut bet, edit).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidOptions));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn cutoff_only_moves_while_it_is_ahead(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let now = sqlx::types::chrono::Local::now()
+            .naive_local()
+            .trunc_subsecs(0);
+        let later = now + chrono::TimeDelta::days(1);
+        let mut bet = create_timed_bet(&pool, &bob, String::from("open"), later).await?;
+        let to = |stop_bets_at| BetEdit {
+            stop_bets_at: Some(stop_bets_at),
+            ..Default::default()
+        };
+        let error = edit_bet(&pool, &mut bet, to(now - chrono::TimeDelta::hours(1)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::CutoffPassed));
+        edit_bet(&pool, &mut bet, to(later + chrono::TimeDelta::days(1))).await?;
+        assert_eq!(bet.stop_bets_at, Some(later + chrono::TimeDelta::days(1)));
+
+        let earlier = now - chrono::TimeDelta::hours(1);
+        let mut bet = create_timed_bet(&pool, &bob, String::from("stopped"), earlier).await?;
+        let error = edit_bet(&pool, &mut bet, to(later)).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::CutoffPassed));
+        assert_eq!(bet.stop_bets_at, Some(earlier));
+        // Other terms can still change until the bet is closed
+        let edit = BetEdit {
+            description: Some(String::from("still stopped")),
+            ..Default::default()
+        };
+        edit_bet(&pool, &mut bet, edit).await?;
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn labels_filter_listings_and_records(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let mut final_bet = create_timeless_bet(&pool, &bob, String::from("Finals")).await?;
+        let mut derby = create_timeless_bet(&pool, &bob, String::from("Derby")).await?;
+        let mut election = create_timeless_bet(&pool, &bob, String::from("Election")).await?;
+        let sports = |tags: &[&str]| BetLabels {
+            category: Some(String::from("Sports")),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        };
+        label_bet(&pool, &mut final_bet, &sports(&["NBA", "finals"])).await?;
+        label_bet(&pool, &mut derby, &sports(&["football"])).await?;
+        assert_eq!(final_bet.tags, ["nba", "finals"]);
+        assert_eq!(final_bet.version, 1);
+        let mut stale_derby = derby.clone();
+        label_bet(&pool, &mut derby, &sports(&["football", "derby"])).await?;
+        let error = label_bet(&pool, &mut stale_derby, &sports(&[]))
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::Stale));
+        assert_eq!(derby.tags, ["football", "derby"]);
+
+        let unknown = BetLabels {
+            category: Some(String::from("Knitting")),
+            tags: vec![],
+        };
+        let error = label_bet(&pool, &mut election, &unknown).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::UnknownCategory));
+
+        let ids = |bets: Vec<Bet>| bets.iter().map(|bet| bet.id).collect::<Vec<_>>();
+        let filter = |category: Option<&str>, tag: Option<&str>| BetFilter {
+            category: category.map(String::from),
+            tag: tag.map(String::from),
+        };
+        let active = BetStatus::Active;
+        let by_category = filter(Some("Sports"), None);
+        let bets = get_bets_by_status(&pool, &active, None, &by_category).await?;
+        assert_eq!(ids(bets), [final_bet.id, derby.id]);
+        let by_tag = filter(None, Some("NBA"));
+        let bets = get_bets_by_user(&pool, &bob, None, &by_tag).await?;
+        assert_eq!(ids(bets), [final_bet.id]);
+        let both = filter(Some("Politics"), Some("nba"));
+        assert!(get_bets_by_status(&pool, &active, None, &both)
+            .await?
+            .is_empty());
+
+        // John wins the finals and loses the derby, the election isn't
+        // categorized and doesn't count
+        let derby_id = derby.id;
+        for bet in [&mut final_bet, &mut derby, &mut election] {
+            let (yes, no) = yes_no_options(&pool, bet).await?;
+            bet_participants::create_bet_participant(&pool, &john, bet, 10, yes).await?;
+            bet_participants::create_bet_participant(&pool, &bob, bet, 5, no).await?;
+            close_bet(&pool, bet).await?;
+            let winner = if bet.id == derby_id { no } else { yes };
+            payout_bet(&pool, bet, winner).await?;
+        }
+        let error = label_bet(&pool, &mut derby, &sports(&[]))
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotActive));
+
+        let scores = read_category_scores_by_username(&pool, &john.username).await?;
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].category, "Sports");
+        assert_eq!(
+            (
+                scores[0].total_wins,
+                scores[0].total_losses,
+                scores[0].points_earned
+            ),
+            (1, 1, 10)
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn search_ranks_visible_bets(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let description = |text: &str| String::from(text);
+        let jam =
+            create_timeless_bet(&pool, &bob, description("Will the printer jam today?")).await?;
+        let printers = create_timeless_bet(
+            &pool,
+            &bob,
+            description(
+                "Printer wars: which printer gets fixed first, the office printer or ours?",
+            ),
+        )
+        .await?;
+        let private = create_bet(
+            &pool,
+            &bob,
+            description("Does the printer guy have a crush on Ann?"),
+            None,
+            None,
+            &BetSettings {
+                visibility: BetVisibility::Private,
+                ..BetSettings::default()
+            },
+        )
+        .await?;
+        create_timeless_bet(&pool, &bob, description("Will the coffee machine work?")).await?;
+        let markup = create_timeless_bet(
+            &pool,
+            &bob,
+            description("Is the <img src=x onerror=alert(1)> scanner & copier fixed?"),
+        )
+        .await?;
+
+        let ids = |matches: Vec<BetMatch>| matches.iter().map(|m| m.bet.id).collect::<Vec<_>>();
+        let matches = search_bets(&pool, "printers", Some(&john), 20).await?;
+        assert_eq!(matches[0].snippet.matches("<b>").count(), 3);
+        assert!(matches[1].snippet.contains("<b>printer</b>"));
+        assert!(matches[0].rank > matches[1].rank);
+        assert_eq!(ids(matches), [printers.id, jam.id]);
+
+        let matches = search_bets(&pool, "printer", Some(&bob), 20).await?;
+        assert!(ids(matches).contains(&private.id));
+        let matches = search_bets(&pool, "printer -jam", None, 20).await?;
+        assert_eq!(ids(matches), [printers.id]);
+        assert!(search_bets(&pool, "toaster", None, 20).await?.is_empty());
+
+        // Descriptions are escaped, only the highlighting is markup
+        let matches = search_bets(&pool, "scanner", None, 20).await?;
+        assert_eq!(matches[0].bet.id, markup.id);
+        assert_eq!(
+            matches[0].snippet,
+            "Is the &lt;img src=x onerror=alert(1)&gt; <b>scanner</b> &amp; copier fixed?"
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn edits_after_join_need_confirmation(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John", "Jane"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        let jane = users.pop().unwrap();
+
+        let labels = vec!["Rain".into(), "Sun".into(), "Snow".into()];
+        let mut bet = create_bet(
+            &pool,
+            &bob,
+            String::from("weather"),
+            None,
+            Some(&labels),
+            &BetSettings::default(),
+        )
+        .await?;
+        let options = get_bet_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, options[0].id).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 10, options[1].id).await?;
+        bet_participants::create_bet_participant(&pool, &jane, &bet, 10, options[2].id).await?;
+
+        // Snow was picked, so it can't be dropped
+        let edit = BetEdit {
+            options: Some(vec!["Rain".into(), "Sun".into()]),
+            ..Default::default()
+        };
+        let error = edit_bet(&pool, &mut bet, edit).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::OptionPicked));
+
+        let edit = BetEdit {
+            description: Some(String::from("weather tomorrow")),
+            options: Some(vec!["Sun".into(), "Rain".into(), "Snow".into()]),
+            ..Default::default()
+        };
+        edit_bet(&pool, &mut bet, edit).await?;
+        assert_eq!(bet.version, 2);
+        let versions = get_bet_versions(&pool, &bet).await?;
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].description, "weather");
+        assert_eq!(versions[1].options, ["Sun", "Rain", "Snow"]);
+
+        // The creator agreed to their own edit
+        let bob_participant = bet_participants::get_bet_participant(&pool, &bet, &bob).await?;
+        assert_eq!(bob_participant.confirmed_version, 2);
+        let john_participant = bet_participants::confirm_bet_version(&pool, &bet, &john).await?;
+        assert_eq!(john_participant.confirmed_version, 2);
+        let jane_participant =
+            bet_participants::withdraw_bet_participant(&pool, &bet, &jane).await?;
+        assert_eq!(jane_participant.user_id, jane.id);
+
+        edit_bet(&pool, &mut bet, BetEdit::default()).await?;
+        assert_eq!(bet.version, 3);
+        close_bet(&pool, &mut bet).await?;
+        let error = bet_participants::confirm_bet_version(&pool, &bet, &john)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotActive));
+
+        // John never confirmed the last version and is pushed
+        payout_bet(&pool, &mut bet, options[1].id).await?;
+        let won: Vec<_> = bet_participants::get_bet_participants(&pool, &bet)
+            .await?
+            .into_iter()
+            .map(|participant| (participant.user_id, participant.won))
+            .collect();
+        assert_eq!(won.len(), 2);
+        assert!(won.contains(&(bob.id, Some(false))));
+        assert!(won.contains(&(john.id, None)));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn arbiter_judges_bet(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John", "Jane"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        let jane = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("judged")).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 10, yes).await?;
+
+        let error = name_arbiter(&pool, &mut bet, &bob).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidArbiter));
+        let error = name_arbiter(&pool, &mut bet, &john).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidArbiter));
+
+        name_arbiter(&pool, &mut bet, &jane).await?;
+        assert_eq!(bet.arbiter_id, Some(jane.id));
+        assert_eq!(bet.arbiter_status, Some(ArbiterStatus::Pending));
+        assert_eq!(bet.manager_id(), bob.id);
+
+        let error = bet_participants::create_bet_participant(&pool, &jane, &bet, 10, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::ArbiterCannotJoin));
+        let error = answer_arbiter_role(&pool, &mut bet, &john, ArbiterStatus::Accepted)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotArbiter));
+
+        let mut stale = bet.clone();
+        answer_arbiter_role(&pool, &mut bet, &jane, ArbiterStatus::Accepted).await?;
+        assert_eq!(bet.manager_id(), jane.id);
+        // The creator read the bet before the arbiter accepted
+        let error = close_bet(&pool, &mut stale).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::Stale));
+        let error = name_arbiter(&pool, &mut bet, &bob).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::ArbiterAccepted));
+        let error = answer_arbiter_role(&pool, &mut bet, &jane, ArbiterStatus::Declined)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotArbiter));
+
+        let mut declined = create_timeless_bet(&pool, &bob, String::from("declined")).await?;
+        let (declined_yes, _) = yes_no_options(&pool, &declined).await?;
+        name_arbiter(&pool, &mut declined, &jane).await?;
+        answer_arbiter_role(&pool, &mut declined, &jane, ArbiterStatus::Declined).await?;
+        assert_eq!(declined.manager_id(), bob.id);
+        bet_participants::create_bet_participant(&pool, &jane, &declined, 5, declined_yes).await?;
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn votes_propose_outcome_at_quorum(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John", "Jane", "Outsider"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+        let jane = users.pop().unwrap();
+        let outsider = users.pop().unwrap();
+
+        let vote = VoteResolution {
+            quorum: 2,
+            dispute_window_seconds: 3600,
+        };
+        let error = create_vote_bet(
+            &pool,
+            &bob,
+            String::from("no quorum"),
+            None,
+            None,
+            VoteResolution { quorum: 0, ..vote },
+            &BetSettings::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::InvalidVoteRules));
+
+        let mut bet = create_vote_bet(
+            &pool,
+            &bob,
+            String::from("voted"),
+            None,
+            None,
+            vote,
+            &BetSettings::default(),
+        )
+        .await?;
+        assert_eq!(bet.resolution, BetResolution::Vote);
+        assert_eq!(bet.quorum, Some(2));
+        let (yes, no) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 10, no).await?;
+        bet_participants::create_bet_participant(&pool, &jane, &bet, 10, yes).await?;
+
+        let error = vote_on_bet(&pool, &mut bet, &john, yes).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotFinished));
+        close_bet(&pool, &mut bet).await?;
+
+        let error = payout_bet(&pool, &mut bet, yes).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::AwaitingVotes));
+        let error = vote_on_bet(&pool, &mut bet, &outsider, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotParticipant));
+
+        vote_on_bet(&pool, &mut bet, &john, no).await?;
+        vote_on_bet(&pool, &mut bet, &bob, yes).await?;
+        assert_eq!(bet.proposed_option_id, None);
+        // Changing a vote counts it once
+        vote_on_bet(&pool, &mut bet, &john, yes).await?;
+        assert_eq!(bet.proposed_option_id, Some(yes));
+        assert!(bet.proposed_at.is_some());
+
+        let error = vote_on_bet(&pool, &mut bet, &jane, no).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::VotingClosed));
+
+        let now = sqlx::types::chrono::Local::now().naive_local();
+        assert!(settle_voted_bets(&pool, now).await?.is_empty());
+        let settled = settle_voted_bets(&pool, now + chrono::TimeDelta::hours(1)).await?;
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].status, BetStatus::PayedOut);
+        assert_eq!(settled[0].winning_option_id, Some(yes));
+
+        let john_score = super::super::scores::read_user_score(&pool, &john).await?;
+        assert_eq!(john_score.total_losses, 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn failed_vote_settlement_does_not_block_others(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let vote = VoteResolution {
+            quorum: 1,
+            dispute_window_seconds: 3600,
+        };
+        let mut proposed = Vec::new();
+        for description in ["broken", "fine"] {
+            let mut bet = create_vote_bet(
+                &pool,
+                &bob,
+                String::from(description),
+                None,
+                None,
+                vote,
+                &BetSettings::default(),
+            )
+            .await?;
+            let (yes, _) = yes_no_options(&pool, &bet).await?;
+            bet_participants::create_bet_participant(&pool, &john, &bet, 10, yes).await?;
+            close_bet(&pool, &mut bet).await?;
+            vote_on_bet(&pool, &mut bet, &john, yes).await?;
+            proposed.push((bet, yes));
+        }
+        let (fine, fine_yes) = proposed.pop().unwrap();
+        let (broken, _) = proposed.pop().unwrap();
+        // Point the first bet's outcome at the other bet's option so its payout fails
+        sqlx::query!(
+            "UPDATE bets SET proposed_option_id = $1 WHERE id = $2",
+            fine_yes,
+            broken.id
+        )
+        .execute(&pool)
+        .await?;
+
+        let later = sqlx::types::chrono::Local::now().naive_local() + chrono::TimeDelta::hours(1);
+        let settled = settle_voted_bets(&pool, later).await?;
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].id, fine.id);
+        assert_eq!(settled[0].status, BetStatus::PayedOut);
+        let broken = get_bet_by_id(&pool, broken.id).await?;
+        assert_eq!(broken.status, BetStatus::Finished);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn disputed_votes_are_escalated(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let vote = VoteResolution {
+            quorum: 1,
+            dispute_window_seconds: 3600,
+        };
+        let mut bet = create_vote_bet(
+            &pool,
+            &bob,
+            String::from("voted"),
+            None,
+            None,
+            vote,
+            &BetSettings::default(),
+        )
+        .await?;
+        let (yes, no) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &bob, &bet, 10, yes).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 10, no).await?;
+        close_bet(&pool, &mut bet).await?;
+
+        let error = dispute_bet(&pool, &mut bet, &john).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotDisputable));
+
+        vote_on_bet(&pool, &mut bet, &bob, yes).await?;
+        dispute_bet(&pool, &mut bet, &john).await?;
+        assert_eq!(bet.disputed_by, Some(john.id));
+        let error = dispute_bet(&pool, &mut bet, &bob).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotDisputable));
+
+        let later = sqlx::types::chrono::Local::now().naive_local() + chrono::TimeDelta::days(1);
+        assert!(settle_voted_bets(&pool, later).await?.is_empty());
+
+        payout_bet(&pool, &mut bet, no).await?;
+        assert_eq!(bet.status, BetStatus::PayedOut);
+        assert_eq!(bet.winning_option_id, Some(no));
+
+        let mut creator_bet = create_timeless_bet(&pool, &bob, String::from("creator")).await?;
+        close_bet(&pool, &mut creator_bet).await?;
+        let error = vote_on_bet(&pool, &mut creator_bet, &bob, yes)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::NotVoting));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn find_stuck_bets(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let now = sqlx::types::chrono::Local::now().naive_local();
+        let yesterday = now - chrono::TimeDelta::days(1);
+        let tommorow = now + chrono::TimeDelta::days(1);
+
+        let expired = create_timed_bet(&pool, &bob, String::from("expired"), yesterday).await?;
+        create_timed_bet(&pool, &bob, String::from("open"), tommorow).await?;
+        create_timeless_bet(&pool, &bob, String::from("timeless")).await?;
+        let mut finished = create_timeless_bet(&pool, &bob, String::from("finished")).await?;
+        close_bet(&pool, &mut finished).await?;
+
+        let stuck = get_stuck_bets(&pool, yesterday).await?;
+        assert_eq!(stuck, vec![expired.clone()]);
+
+        let stuck = get_stuck_bets(&pool, tommorow).await?;
+        assert_eq!(stuck, vec![expired, finished]);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn stale_bet_is_rejected(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let mut bet = create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        bet_participants::create_bet_participant(&pool, &john, &bet, 10, yes).await?;
+
+        let mut stale_bet = bet.clone();
+        let capped = StakeLimits {
+            max_participants: Some(5),
+            ..StakeLimits::default()
+        };
+        limit_bet(&pool, &mut bet, &capped).await?;
+        let error = limit_bet(&pool, &mut stale_bet, &StakeLimits::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::Stale));
+        assert_eq!(bet.max_participants, Some(5));
+
+        let mut stale_bet = bet.clone();
+        close_bet(&pool, &mut bet).await?;
+
+        let error = close_bet(&pool, &mut stale_bet).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::Stale));
+        assert_eq!(stale_bet.status, BetStatus::Active);
+
+        let mut stale_bet = bet.clone();
+        payout_bet(&pool, &mut bet, yes).await?;
+
+        let error = payout_bet(&pool, &mut stale_bet, yes).await.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&BetError::AlreadyPaidOut));
+        assert_eq!(stale_bet.status, BetStatus::Finished);
+
+        let john_score = super::super::scores::read_user_score(&pool, &john).await?;
+        assert_eq!(john_score.total_wins, 1);
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn run_bet_with_participants(pool: PgPool) -> AllResult<()> {
         let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
@@ -246,6 +2510,7 @@ mod tests {
 
         let mut timeless_bet =
             create_timeless_bet(&pool, &bob, String::from("description")).await?;
+        let (timeless_yes, _) = yes_no_options(&pool, &timeless_bet).await?;
         create_timeless_bet(&pool, &bob, String::from("description")).await?;
         create_timeless_bet(&pool, &bob, String::from("description")).await?;
         create_timeless_bet(&pool, &john, String::from("description")).await?;
@@ -255,28 +2520,33 @@ mod tests {
 
         let mut timed_bet =
             create_timed_bet(&pool, &bob, String::from("description"), tommorow).await?;
+        let (timed_yes, _) = yes_no_options(&pool, &timed_bet).await?;
         create_timed_bet(&pool, &bob, String::from("description"), tommorow).await?;
         create_timed_bet(&pool, &bob, String::from("description"), tommorow).await?;
         create_timed_bet(&pool, &john, String::from("description"), tommorow).await?;
 
-        let bets = get_bets_by_user(&pool, &bob).await?;
+        let bets = get_bets_by_user(&pool, &bob, Some(&bob), &BetFilter::default()).await?;
         assert_eq!(bets.len(), 6);
 
         let bob_timeless_bet =
-            bet_participants::create_bet_participant(&pool, &bob, &timeless_bet, 10, true).await?;
+            bet_participants::create_bet_participant(&pool, &bob, &timeless_bet, 10, timeless_yes)
+                .await?;
         let john_timeless_bet =
-            bet_participants::create_bet_participant(&pool, &john, &timeless_bet, 10, true).await?;
+            bet_participants::create_bet_participant(&pool, &john, &timeless_bet, 10, timeless_yes)
+                .await?;
         let bob_timed_bet =
-            bet_participants::create_bet_participant(&pool, &bob, &timed_bet, 10, true).await?;
+            bet_participants::create_bet_participant(&pool, &bob, &timed_bet, 10, timed_yes)
+                .await?;
         let john_timed_bet =
-            bet_participants::create_bet_participant(&pool, &john, &timed_bet, 10, true).await?;
+            bet_participants::create_bet_participant(&pool, &john, &timed_bet, 10, timed_yes)
+                .await?;
 
         assert_eq!(bob_timeless_bet.bet_id, timeless_bet.id);
         assert_eq!(john_timeless_bet.bet_id, timeless_bet.id);
         assert_eq!(bob_timed_bet.bet_id, timed_bet.id);
         assert_eq!(john_timed_bet.bet_id, timed_bet.id);
 
-        let user_bets = get_bets_with_user(&pool, &bob).await?;
+        let user_bets = get_bets_with_user(&pool, &bob, Some(&bob)).await?;
 
         assert_eq!(user_bets.len(), 2);
         assert!(
@@ -294,12 +2564,12 @@ mod tests {
         close_bet(&pool, &mut timeless_bet).await?;
 
         let mut payed_out_timeless = timeless_bet.clone();
-        payout_bet(&pool, &mut payed_out_timeless, true).await?;
+        payout_bet(&pool, &mut payed_out_timeless, timeless_yes).await?;
         assert_eq!(payed_out_timeless.status, BetStatus::PayedOut);
         assert_eq!(payed_out_timeless.id, timeless_bet.id);
 
         let mut payed_out_timed = timed_bet.clone();
-        payout_bet(&pool, &mut payed_out_timed, true).await?;
+        payout_bet(&pool, &mut payed_out_timed, timed_yes).await?;
         assert_eq!(payed_out_timed.status, BetStatus::PayedOut);
         assert_eq!(payed_out_timed.id, timed_bet.id);
 
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use sqlx::{PgExecutor, PgPool};
+
+use crate::models::{BetError, BetLabels, Category, MAX_TAGS, MAX_TAG_LENGTH};
+use crate::AllResult;
+
+pub async fn get_categories(connection: &PgPool) -> AllResult<Vec<Category>> {
+    let categories = sqlx::query_as!(Category, "SELECT * FROM categories ORDER BY id")
+        .fetch_all(connection)
+        .await?;
+    Ok(categories)
+}
+
+/// Fails with `BetError::UnknownCategory` if there is no category named
+/// `name`
+pub async fn get_category_by_name(
+    connection: impl PgExecutor<'_>,
+    name: &str,
+) -> AllResult<Category> {
+    let category = sqlx::query_as!(Category, "SELECT * FROM categories WHERE name = $1", name)
+        .fetch_optional(connection)
+        .await?;
+    Ok(category.ok_or(BetError::UnknownCategory)?)
+}
+
+/// Tags are trimmed and lowercased, and duplicates are dropped. Each tag is
+/// made of letters, digits, `-` and `_`.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, BetError> {
+    let mut seen = HashSet::new();
+    let mut normalized = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        let valid = (1..=MAX_TAG_LENGTH).contains(&tag.chars().count())
+            && tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(BetError::InvalidTags);
+        }
+        if seen.insert(tag.clone()) {
+            normalized.push(tag);
+        }
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err(BetError::InvalidTags);
+    }
+    Ok(normalized)
+}
+
+/// Looks up the category and normalizes the tags of `labels`, see
+/// `normalize_tags`
+pub async fn resolve_labels(
+    connection: impl PgExecutor<'_>,
+    labels: &BetLabels,
+) -> AllResult<(Option<i32>, Vec<String>)> {
+    let tags = normalize_tags(&labels.tags)?;
+    let category_id = match &labels.category {
+        Some(name) => Some(get_category_by_name(connection, name).await?.id),
+        None => None,
+    };
+    Ok((category_id, tags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_rules() {
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            normalize_tags(&tags(&[" NBA ", "finals-2030", "nba"])),
+            Ok(tags(&["nba", "finals-2030"]))
+        );
+        assert_eq!(normalize_tags(&[]), Ok(vec![]));
+        for invalid in [
+            "",
+            "  ",
+            "two words",
+            "#nba",
+            &"a".repeat(MAX_TAG_LENGTH + 1),
+        ] {
+            assert_eq!(
+                normalize_tags(&tags(&[invalid])),
+                Err(BetError::InvalidTags)
+            );
+        }
+        let too_many: Vec<_> = (0..=MAX_TAGS).map(|i| format!("tag{i}")).collect();
+        assert_eq!(normalize_tags(&too_many), Err(BetError::InvalidTags));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::models::{Friendship, FriendshipStatus, User};
 use crate::AllResult;
+use sqlx::PgExecutor;
 
 pub async fn get_friendship(
     connection: &sqlx::PgPool,
@@ -55,6 +56,28 @@ pub async fn get_accepted_friendships(
     Ok(friendships)
 }
 
+/// Whether the user with `friend_id` accepted a friend request from, or sent
+/// one that was accepted to, the user with `user_id`
+pub async fn are_friends(
+    connection: impl PgExecutor<'_>,
+    user_id: i32,
+    friend_id: i32,
+) -> AllResult<bool> {
+    let friends = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM friendships WHERE user_id = $1 AND friend_id = $2 AND status = $3
+        ) AS "friends!"
+        "#,
+        user_id,
+        friend_id,
+        FriendshipStatus::Accepted as _
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(friends)
+}
+
 pub async fn send_friend_request(
     connection: &sqlx::PgPool,
     sender: &User,
@@ -0,0 +1,275 @@
+use serde_json::Value;
+use sqlx::PgPool;
+
+use sqlx::types::chrono::NaiveDateTime;
+
+use crate::models::{IdempotencyClaim, IdempotencyKey, User, CLAIM_TIMEOUT, KEY_TTL};
+use crate::AllResult;
+
+/// Claims a new key. A key already claimed is never claimed again, even if
+/// its request didn't complete within `CLAIM_TIMEOUT`, since that request
+/// may have gone through before its server died.
+pub async fn claim_idempotency_key(
+    connection: &PgPool,
+    user: &User,
+    key: &str,
+    route: &str,
+    request_body: Value,
+) -> AllResult<IdempotencyClaim> {
+    let claimed = sqlx::query_as!(
+        IdempotencyKey,
+        r#"
+        INSERT INTO idempotency_keys (user_id, key, route, request_body)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        RETURNING *
+        "#,
+        user.id,
+        key,
+        route,
+        request_body
+    )
+    .fetch_optional(connection)
+    .await?;
+
+    if let Some(claimed) = claimed {
+        return Ok(IdempotencyClaim::Claimed(claimed));
+    }
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT request_body, response_body,
+        created_at < NOW() - make_interval(secs => $4) AS "stale!"
+        FROM idempotency_keys
+        WHERE user_id = $1 AND key = $2 AND route = $3
+        "#,
+        user.id,
+        key,
+        route,
+        CLAIM_TIMEOUT.as_secs_f64()
+    )
+    .fetch_one(connection)
+    .await?;
+    let claim = match existing.response_body {
+        _ if existing.request_body != request_body => IdempotencyClaim::Mismatch,
+        Some(response_body) => IdempotencyClaim::Completed(response_body),
+        None if existing.stale => IdempotencyClaim::Abandoned,
+        None => IdempotencyClaim::InProgress,
+    };
+    Ok(claim)
+}
+
+pub async fn complete_idempotency_key(
+    connection: &PgPool,
+    idempotency_key: &mut IdempotencyKey,
+    response_body: Value,
+) -> AllResult<()> {
+    let completed = sqlx::query_as!(
+        IdempotencyKey,
+        r#"
+        UPDATE idempotency_keys
+        SET response_body = $1, completed_at = NOW()
+        WHERE user_id = $2 AND key = $3 AND route = $4
+        RETURNING *
+        "#,
+        response_body,
+        idempotency_key.user_id,
+        idempotency_key.key,
+        idempotency_key.route
+    )
+    .fetch_one(connection)
+    .await?;
+    *idempotency_key = completed;
+    Ok(())
+}
+
+pub async fn delete_idempotency_key(
+    connection: &PgPool,
+    idempotency_key: &IdempotencyKey,
+) -> AllResult<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency_keys
+        WHERE user_id = $1 AND key = $2 AND route = $3
+        "#,
+        idempotency_key.user_id,
+        idempotency_key.key,
+        idempotency_key.route
+    )
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+pub async fn purge_expired_idempotency_keys(
+    connection: &PgPool,
+    now: NaiveDateTime,
+) -> AllResult<u64> {
+    let purged = sqlx::query!(
+        "DELETE FROM idempotency_keys WHERE created_at < $1::TIMESTAMP - make_interval(secs => $2)",
+        now,
+        KEY_TTL.as_secs_f64()
+    )
+    .execute(connection)
+    .await?;
+    Ok(purged.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::users::create_users;
+    use super::*;
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    async fn get_idempotency_key(
+        connection: &PgPool,
+        user: &User,
+        key: &str,
+        route: &str,
+    ) -> AllResult<IdempotencyKey> {
+        let idempotency_key = sqlx::query_as!(
+            IdempotencyKey,
+            r#"
+            SELECT * FROM idempotency_keys
+            WHERE user_id = $1 AND key = $2 AND route = $3
+            "#,
+            user.id,
+            key,
+            route
+        )
+        .fetch_one(connection)
+        .await?;
+        Ok(idempotency_key)
+    }
+
+    #[sqlx::test]
+    async fn claim_then_replay(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let request = json!({ "description": "bet" });
+        let claim = claim_idempotency_key(&pool, &bob, "key-1", "/bet", request.clone()).await?;
+        let IdempotencyClaim::Claimed(mut idempotency_key) = claim else {
+            panic!("expected a new claim, got {claim:?}");
+        };
+        assert_eq!(idempotency_key.user_id, bob.id);
+        assert!(idempotency_key.response_body.is_none());
+
+        let claim = claim_idempotency_key(&pool, &bob, "key-1", "/bet", request.clone()).await?;
+        assert_eq!(claim, IdempotencyClaim::InProgress);
+
+        let response = json!({ "id": 1 });
+        complete_idempotency_key(&pool, &mut idempotency_key, response.clone()).await?;
+        assert_eq!(idempotency_key.response_body, Some(response.clone()));
+        assert!(idempotency_key.completed_at.is_some());
+
+        let claim = claim_idempotency_key(&pool, &bob, "key-1", "/bet", request).await?;
+        assert_eq!(claim, IdempotencyClaim::Completed(response));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn reuse_with_different_body(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let claim =
+            claim_idempotency_key(&pool, &bob, "key-1", "/bet", json!({ "amount": 10 })).await?;
+        assert!(matches!(claim, IdempotencyClaim::Claimed(_)));
+
+        let claim =
+            claim_idempotency_key(&pool, &bob, "key-1", "/bet", json!({ "amount": 20 })).await?;
+        assert_eq!(claim, IdempotencyClaim::Mismatch);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn keys_are_scoped_by_user_and_route(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        let request = json!({ "amount": 10 });
+        for (user, route) in [(&bob, "/bet"), (&john, "/bet"), (&bob, "/bet/join")] {
+            let claim = claim_idempotency_key(&pool, user, "key-1", route, request.clone()).await?;
+            assert!(matches!(claim, IdempotencyClaim::Claimed(_)));
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn released_key_can_be_claimed_again(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let request = json!({ "amount": 10 });
+        let IdempotencyClaim::Claimed(idempotency_key) =
+            claim_idempotency_key(&pool, &bob, "key-1", "/bet", request.clone()).await?
+        else {
+            panic!("expected a new claim");
+        };
+        delete_idempotency_key(&pool, &idempotency_key).await?;
+        assert!(get_idempotency_key(&pool, &bob, "key-1", "/bet")
+            .await
+            .is_err());
+
+        let claim = claim_idempotency_key(&pool, &bob, "key-1", "/bet", request).await?;
+        assert!(matches!(claim, IdempotencyClaim::Claimed(_)));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn stale_claim_is_not_run_again(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let request = json!({ "amount": 10 });
+        let claim = claim_idempotency_key(&pool, &bob, "key-1", "/bet", request.clone()).await?;
+        assert!(matches!(claim, IdempotencyClaim::Claimed(_)));
+        let claim = claim_idempotency_key(&pool, &bob, "key-1", "/bet", request.clone()).await?;
+        assert_eq!(claim, IdempotencyClaim::InProgress);
+
+        // The server handling the first request died without completing it
+        sqlx::query!(
+            "UPDATE idempotency_keys SET created_at = NOW() - make_interval(secs => $1)",
+            CLAIM_TIMEOUT.as_secs_f64() + 1.0
+        )
+        .execute(&pool)
+        .await?;
+        let claim =
+            claim_idempotency_key(&pool, &bob, "key-1", "/bet", json!({ "amount": 20 })).await?;
+        assert_eq!(claim, IdempotencyClaim::Mismatch);
+        let claim = claim_idempotency_key(&pool, &bob, "key-1", "/bet", request).await?;
+        assert_eq!(claim, IdempotencyClaim::Abandoned);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn expired_keys_are_purged(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let request = json!({ "amount": 10 });
+        let IdempotencyClaim::Claimed(idempotency_key) =
+            claim_idempotency_key(&pool, &bob, "key-1", "/bet", request.clone()).await?
+        else {
+            panic!("expected a new claim");
+        };
+        let created_at = idempotency_key.created_at;
+        assert_eq!(purge_expired_idempotency_keys(&pool, created_at).await?, 0);
+        let expired_at = created_at + KEY_TTL + std::time::Duration::from_secs(1);
+        assert_eq!(purge_expired_idempotency_keys(&pool, expired_at).await?, 1);
+
+        // An expired key starts a new request
+        let claim = claim_idempotency_key(&pool, &bob, "key-1", "/bet", request).await?;
+        assert!(matches!(claim, IdempotencyClaim::Claimed(_)));
+
+        Ok(())
+    }
+}
@@ -1,5 +1,87 @@
+/// Runs `sqlx::query_as!` for `Bet`s with the bet's columns spliced in after
+/// `$head` and before the optional `$tail`, or `sqlx::query!` when it starts
+/// with `record:`, for rows that carry more than a bet, see `bet_from_row!`.
+/// The macros only take string literals, so the projection is kept here
+/// instead of in a constant. Columns are qualified, so the bets can be joined.
+macro_rules! query_bets {
+    (@$query:ident [$($record:tt)*] $head:literal $(, $tail:literal)?; $($arg:expr),*) => {
+        sqlx::$query!(
+            $($record)*
+            $head
+                + r#"
+                bets.id, bets.creator_id, bets.description, bets.status AS "status: BetStatus",
+                bets.stop_bets_at, bets.created_at, bets.updated_at, bets.paid_out,
+                bets.paid_out_at, bets.winning_option_id, bets.kind AS "kind: BetKind",
+                bets.line, bets.actual_value, bets.resolution AS "resolution: BetResolution",
+                bets.quorum, bets.dispute_window_seconds, bets.proposed_option_id,
+                bets.proposed_at, bets.disputed_by, bets.disputed_at, bets.arbiter_id,
+                bets.arbiter_status AS "arbiter_status: ArbiterStatus",
+                bets.visibility AS "visibility: BetVisibility", bets.version, bets.category_id,
+                bets.tags, bets.min_stake, bets.max_stake, bets.max_participants
+                "#
+                $(+ $tail)?
+            $(, $arg)*
+        )
+    };
+    (record: $head:literal $(, $tail:literal)?; $($arg:expr),* $(,)?) => {
+        query_bets!(@query [] $head $(, $tail)?; $($arg),*)
+    };
+    ($head:literal $(, $tail:literal)?; $($arg:expr),* $(,)?) => {
+        query_bets!(@query_as [crate::models::Bet,] $head $(, $tail)?; $($arg),*)
+    };
+}
+
+/// Builds a `Bet` from a row of `query_bets!(record: ...)`, leaving the
+/// row's other columns in place
+macro_rules! bet_from_row {
+    ($row:ident) => {
+        crate::models::Bet {
+            id: $row.id,
+            creator_id: $row.creator_id,
+            description: $row.description,
+            status: $row.status,
+            stop_bets_at: $row.stop_bets_at,
+            created_at: $row.created_at,
+            updated_at: $row.updated_at,
+            paid_out: $row.paid_out,
+            paid_out_at: $row.paid_out_at,
+            winning_option_id: $row.winning_option_id,
+            kind: $row.kind,
+            line: $row.line,
+            actual_value: $row.actual_value,
+            resolution: $row.resolution,
+            quorum: $row.quorum,
+            dispute_window_seconds: $row.dispute_window_seconds,
+            proposed_option_id: $row.proposed_option_id,
+            proposed_at: $row.proposed_at,
+            disputed_by: $row.disputed_by,
+            disputed_at: $row.disputed_at,
+            arbiter_id: $row.arbiter_id,
+            arbiter_status: $row.arbiter_status,
+            visibility: $row.visibility,
+            version: $row.version,
+            category_id: $row.category_id,
+            tags: $row.tags,
+            min_stake: $row.min_stake,
+            max_stake: $row.max_stake,
+            max_participants: $row.max_participants,
+        }
+    };
+}
+
+pub mod bet_attachments;
+pub mod bet_challenges;
+pub mod bet_invitations;
+pub mod bet_options;
+pub mod bet_participant_changes;
 pub mod bet_participants;
+pub mod bet_templates;
+pub mod bet_versions;
+pub mod bet_votes;
 pub mod bets;
+pub mod categories;
 pub mod friendships;
+pub mod idempotency_keys;
+pub mod parlays;
 pub mod scores;
 pub mod users;
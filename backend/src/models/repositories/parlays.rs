@@ -0,0 +1,486 @@
+use std::collections::HashSet;
+
+use sqlx::{types::chrono::NaiveDateTime, PgConnection, PgPool};
+
+use super::{
+    bet_options::get_bet_option,
+    bet_participants::{check_stake, lock_joinable_bet},
+    bets::get_bet_by_id,
+    scores::{revert_score_parlay, update_score_parlay},
+};
+use crate::models::{BetError, Parlay, ParlayLeg, ParlayPick, ParlayStatus, User, MAX_PARLAY_LEGS};
+use crate::AllResult;
+
+/// Combines picks on 2 to `MAX_PARLAY_LEGS` different bets into one parlay.
+/// Every bet has to be one `user` could join right now with `stake`, see
+/// `lock_joinable_bet`.
+pub async fn create_parlay(
+    connection: &PgPool,
+    user: &User,
+    picks: &[ParlayPick],
+    stake: i32,
+) -> AllResult<(Parlay, Vec<ParlayLeg>)> {
+    if stake <= 0 {
+        return Err(BetError::InvalidStake.into());
+    }
+    let bet_ids: HashSet<i32> = picks.iter().map(|pick| pick.bet_id).collect();
+    if !(2..=MAX_PARLAY_LEGS).contains(&picks.len()) || bet_ids.len() != picks.len() {
+        return Err(BetError::InvalidParlay.into());
+    }
+    // Bets are locked in id order so concurrent parlays can't deadlock
+    let mut picks = picks.to_vec();
+    picks.sort_by_key(|pick| pick.bet_id);
+
+    let mut transaction = connection.begin().await?;
+    let mut versions = Vec::with_capacity(picks.len());
+    for pick in &picks {
+        let bet = get_bet_by_id(&mut *transaction, pick.bet_id).await?;
+        let current = lock_joinable_bet(&mut transaction, user, &bet).await?;
+        check_stake(stake, current.min_stake, current.max_stake)?;
+        get_bet_option(&mut *transaction, &bet, pick.option_id).await?;
+        versions.push(current.version);
+    }
+
+    let parlay = sqlx::query_as!(
+        Parlay,
+        r#"
+        INSERT INTO parlays (user_id, stake)
+        VALUES ($1, $2)
+        RETURNING id, user_id, stake, status AS "status: ParlayStatus", points,
+        created_at, settled_at
+        "#,
+        user.id,
+        stake
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let mut legs = Vec::with_capacity(picks.len());
+    for (pick, version) in picks.iter().zip(versions) {
+        let leg = sqlx::query_as!(
+            ParlayLeg,
+            r#"
+            INSERT INTO parlay_legs (parlay_id, bet_id, option_id, version)
+            VALUES ($1, $2, $3, $4)
+            RETURNING parlay_id, bet_id, option_id, won, settled_at
+            "#,
+            parlay.id,
+            pick.bet_id,
+            pick.option_id,
+            version
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        legs.push(leg);
+    }
+    transaction.commit().await?;
+    Ok((parlay, legs))
+}
+
+pub async fn get_parlay_legs(connection: &PgPool, parlay: &Parlay) -> AllResult<Vec<ParlayLeg>> {
+    let legs = sqlx::query_as!(
+        ParlayLeg,
+        r#"
+        SELECT parlay_id, bet_id, option_id, won, settled_at
+        FROM parlay_legs WHERE parlay_id = $1
+        ORDER BY bet_id
+        "#,
+        parlay.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(legs)
+}
+
+/// The user's parlays with their legs, newest first
+pub async fn get_parlays_by_user(
+    connection: &PgPool,
+    user: &User,
+) -> AllResult<Vec<(Parlay, Vec<ParlayLeg>)>> {
+    let parlays = sqlx::query_as!(
+        Parlay,
+        r#"
+        SELECT id, user_id, stake, status AS "status: ParlayStatus", points,
+        created_at, settled_at
+        FROM parlays WHERE user_id = $1
+        ORDER BY id DESC
+        "#,
+        user.id
+    )
+    .fetch_all(connection)
+    .await?;
+    let legs = sqlx::query_as!(
+        ParlayLeg,
+        r#"
+        SELECT parlay_legs.parlay_id, bet_id, option_id, won, parlay_legs.settled_at
+        FROM parlay_legs
+        JOIN parlays ON parlays.id = parlay_legs.parlay_id
+        WHERE parlays.user_id = $1
+        ORDER BY bet_id
+        "#,
+        user.id
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(parlays
+        .into_iter()
+        .map(|parlay| {
+            let parlay_legs = legs
+                .iter()
+                .filter(|leg| leg.parlay_id == parlay.id)
+                .cloned()
+                .collect();
+            (parlay, parlay_legs)
+        })
+        .collect())
+}
+
+/// Settles the legs on a bet being paid out, inside the payout's
+/// transaction. Legs picked before the bet's latest edit are pushed, like
+/// participants who never confirmed it. Parlays whose last leg this was are
+/// settled and counted in their user's score, except that a pushed leg
+/// cancels the parlay like a voided one.
+pub(super) async fn settle_parlays(
+    connection: &mut PgConnection,
+    bet_id: i32,
+    version: i32,
+    winning_option_id: Option<i32>,
+    now: NaiveDateTime,
+) -> AllResult<()> {
+    // Locked so the payout of another leg waits and sees this one settled
+    let parlays = lock_parlays(&mut *connection, bet_id, &[ParlayStatus::Open]).await?;
+    for parlay in parlays {
+        sqlx::query!(
+            r#"
+            UPDATE parlay_legs
+            SET won = CASE WHEN version = $1 THEN option_id = $2 END, settled_at = $3
+            WHERE parlay_id = $4 AND bet_id = $5
+            "#,
+            version,
+            winning_option_id,
+            now,
+            parlay.id,
+            bet_id
+        )
+        .execute(&mut *connection)
+        .await?;
+        let legs = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE settled_at IS NULL) AS "open!",
+                COUNT(*) FILTER (WHERE settled_at IS NOT NULL AND won IS NULL) AS "pushed!",
+                COUNT(*) FILTER (WHERE won) AS "won!",
+                COUNT(*) FILTER (WHERE NOT won) AS "lost!"
+            FROM parlay_legs WHERE parlay_id = $1
+            "#,
+            parlay.id
+        )
+        .fetch_one(&mut *connection)
+        .await?;
+        if legs.open > 0 {
+            continue;
+        }
+        // The parlay only wins if every leg won, so a pushed leg leaves it
+        // without a result even when another leg lost
+        let (status, points) = match (legs.pushed, legs.lost) {
+            (1.., _) => (ParlayStatus::Cancelled, None),
+            (_, 1..) => (ParlayStatus::Lost, None),
+            _ => (
+                ParlayStatus::Won,
+                Some(parlay.stake * i32::try_from(legs.won)?),
+            ),
+        };
+        let parlay = set_parlay_status(&mut *connection, &parlay, status, points, now).await?;
+        update_score_parlay(&mut *connection, &parlay).await?;
+    }
+    Ok(())
+}
+
+/// Cancels every parlay with a leg on a bet being voided, inside the void's
+/// transaction, and takes settled ones back out of their user's score
+pub(super) async fn cancel_parlays(
+    connection: &mut PgConnection,
+    bet_id: i32,
+    now: NaiveDateTime,
+) -> AllResult<()> {
+    let statuses = [ParlayStatus::Open, ParlayStatus::Won, ParlayStatus::Lost];
+    let parlays = lock_parlays(&mut *connection, bet_id, &statuses).await?;
+    for parlay in parlays {
+        revert_score_parlay(&mut *connection, &parlay).await?;
+        set_parlay_status(
+            &mut *connection,
+            &parlay,
+            ParlayStatus::Cancelled,
+            None,
+            now,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Parlays with a leg on the bet, locked in id order until the transaction
+/// ends
+async fn lock_parlays(
+    connection: &mut PgConnection,
+    bet_id: i32,
+    statuses: &[ParlayStatus],
+) -> AllResult<Vec<Parlay>> {
+    let parlays = sqlx::query_as!(
+        Parlay,
+        r#"
+        SELECT id, user_id, stake, status AS "status: ParlayStatus", points,
+        created_at, settled_at
+        FROM parlays
+        WHERE id IN (SELECT parlay_id FROM parlay_legs WHERE bet_id = $1)
+        AND status = ANY($2)
+        ORDER BY id
+        FOR UPDATE
+        "#,
+        bet_id,
+        statuses as _
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(parlays)
+}
+
+async fn set_parlay_status(
+    connection: &mut PgConnection,
+    parlay: &Parlay,
+    status: ParlayStatus,
+    points: Option<i32>,
+    now: NaiveDateTime,
+) -> AllResult<Parlay> {
+    let parlay = sqlx::query_as!(
+        Parlay,
+        r#"
+        UPDATE parlays SET status = $1, points = $2, settled_at = $3
+        WHERE id = $4
+        RETURNING id, user_id, stake, status AS "status: ParlayStatus", points,
+        created_at, settled_at
+        "#,
+        status as _,
+        points,
+        now,
+        parlay.id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(parlay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        bet_options::yes_no_options,
+        bet_participants::create_bet_participant,
+        bets::{close_bet, create_timeless_bet, edit_bet, limit_bet, payout_bet, void_bet},
+        scores::{read_user_score, recompute_scores},
+        users::create_users,
+    };
+    use super::*;
+    use crate::models::{Bet, BetEdit, StakeLimits};
+
+    fn pick(bet: &Bet, option_id: i32) -> ParlayPick {
+        ParlayPick {
+            bet_id: bet.id,
+            option_id,
+        }
+    }
+
+    async fn settle(pool: &PgPool, bet: &mut Bet, option_id: i32) -> AllResult<()> {
+        close_bet(pool, bet).await?;
+        payout_bet(pool, bet, option_id).await
+    }
+
+    async fn status(pool: &PgPool, parlay: &Parlay) -> AllResult<ParlayStatus> {
+        let status = sqlx::query_scalar!(
+            r#"SELECT status AS "status: ParlayStatus" FROM parlays WHERE id = $1"#,
+            parlay.id
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(status)
+    }
+
+    #[sqlx::test]
+    async fn parlay_settles_with_its_last_leg(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "Alice"]).await?;
+        let bob = users.pop().unwrap();
+        let alice = users.pop().unwrap();
+        let mut first = create_timeless_bet(&pool, &bob, String::from("first")).await?;
+        let (first_yes, _) = yes_no_options(&pool, &first).await?;
+        let mut second = create_timeless_bet(&pool, &bob, String::from("second")).await?;
+        let (second_yes, _) = yes_no_options(&pool, &second).await?;
+        let mut third = create_timeless_bet(&pool, &bob, String::from("third")).await?;
+        let (third_yes, third_no) = yes_no_options(&pool, &third).await?;
+        let mut fourth = create_timeless_bet(&pool, &bob, String::from("fourth")).await?;
+        let (fourth_yes, _) = yes_no_options(&pool, &fourth).await?;
+
+        for (picks, stake, error) in [
+            (vec![pick(&first, first_yes)], 10, BetError::InvalidParlay),
+            (
+                vec![pick(&first, first_yes), pick(&first, first_yes)],
+                10,
+                BetError::InvalidParlay,
+            ),
+            (
+                vec![pick(&first, first_yes), pick(&second, first_yes)],
+                10,
+                BetError::UnknownOption,
+            ),
+            (
+                vec![pick(&first, first_yes), pick(&second, second_yes)],
+                0,
+                BetError::InvalidStake,
+            ),
+        ] {
+            let result = create_parlay(&pool, &alice, &picks, stake).await;
+            assert_eq!(result.unwrap_err().downcast_ref::<BetError>(), Some(&error));
+        }
+
+        let picks = [pick(&second, second_yes), pick(&first, first_yes)];
+        let (winning, legs) = create_parlay(&pool, &alice, &picks, 10).await?;
+        assert_eq!(winning.status, ParlayStatus::Open);
+        assert_eq!(
+            legs.iter().map(|leg| leg.bet_id).collect::<Vec<_>>(),
+            vec![first.id, second.id]
+        );
+        let picks = [pick(&third, third_yes), pick(&fourth, fourth_yes)];
+        let (losing, _) = create_parlay(&pool, &alice, &picks, 5).await?;
+
+        settle(&pool, &mut first, first_yes).await?;
+        assert_eq!(status(&pool, &winning).await?, ParlayStatus::Open);
+        assert_eq!(winning.legs(&pool).await?[0].won, Some(true));
+        settle(&pool, &mut second, second_yes).await?;
+        assert_eq!(status(&pool, &winning).await?, ParlayStatus::Won);
+
+        // A lost leg only settles the parlay once the other legs are paid out
+        settle(&pool, &mut third, third_no).await?;
+        assert_eq!(status(&pool, &losing).await?, ParlayStatus::Open);
+        settle(&pool, &mut fourth, fourth_yes).await?;
+        assert_eq!(status(&pool, &losing).await?, ParlayStatus::Lost);
+
+        let parlays = get_parlays_by_user(&pool, &alice).await?;
+        assert_eq!(parlays[1].0.points, Some(20));
+        assert_eq!(parlays[0].0.points, None);
+        let score = read_user_score(&pool, &alice).await?;
+        assert_eq!(
+            (score.total_wins, score.total_losses, score.points_earned),
+            (1, 1, 20)
+        );
+        recompute_scores(&pool).await?;
+        assert_eq!(read_user_score(&pool, &alice).await?, score);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn stake_has_to_fit_every_leg(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "Alice"]).await?;
+        let bob = users.pop().unwrap();
+        let alice = users.pop().unwrap();
+        let mut first = create_timeless_bet(&pool, &bob, String::from("first")).await?;
+        let (first_yes, _) = yes_no_options(&pool, &first).await?;
+        let mut second = create_timeless_bet(&pool, &bob, String::from("second")).await?;
+        let (second_yes, _) = yes_no_options(&pool, &second).await?;
+        let limits = StakeLimits {
+            min_stake: Some(5),
+            ..StakeLimits::default()
+        };
+        limit_bet(&pool, &mut first, &limits).await?;
+        let limits = StakeLimits {
+            max_stake: Some(20),
+            ..StakeLimits::default()
+        };
+        limit_bet(&pool, &mut second, &limits).await?;
+
+        let picks = [pick(&first, first_yes), pick(&second, second_yes)];
+        for (stake, error) in [(4, BetError::StakeTooLow), (21, BetError::StakeTooHigh)] {
+            let result = create_parlay(&pool, &alice, &picks, stake).await;
+            assert_eq!(result.unwrap_err().downcast_ref::<BetError>(), Some(&error));
+        }
+        assert!(get_parlays_by_user(&pool, &alice).await?.is_empty());
+        create_parlay(&pool, &alice, &picks, 20).await?;
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn voided_leg_cancels_the_parlay(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "Alice"]).await?;
+        let bob = users.pop().unwrap();
+        let alice = users.pop().unwrap();
+        let mut first = create_timeless_bet(&pool, &bob, String::from("first")).await?;
+        let (first_yes, _) = yes_no_options(&pool, &first).await?;
+        let mut second = create_timeless_bet(&pool, &bob, String::from("second")).await?;
+        let (second_yes, _) = yes_no_options(&pool, &second).await?;
+        let mut third = create_timeless_bet(&pool, &bob, String::from("third")).await?;
+        let (third_yes, _) = yes_no_options(&pool, &third).await?;
+
+        let picks = [pick(&first, first_yes), pick(&second, second_yes)];
+        let (settled, _) = create_parlay(&pool, &alice, &picks, 10).await?;
+        let picks = [pick(&second, second_yes), pick(&third, third_yes)];
+        let (open, _) = create_parlay(&pool, &alice, &picks, 10).await?;
+
+        settle(&pool, &mut first, first_yes).await?;
+        settle(&pool, &mut second, second_yes).await?;
+        assert_eq!(status(&pool, &settled).await?, ParlayStatus::Won);
+        assert_eq!(read_user_score(&pool, &alice).await?.points_earned, 20);
+
+        void_bet(&pool, &mut third, false).await?;
+        assert_eq!(status(&pool, &open).await?, ParlayStatus::Cancelled);
+        // Voiding a paid out leg takes the win back out of the score
+        void_bet(&pool, &mut first, true).await?;
+        assert_eq!(status(&pool, &settled).await?, ParlayStatus::Cancelled);
+        let score = read_user_score(&pool, &alice).await?;
+        assert_eq!(
+            (score.total_wins, score.total_losses, score.points_earned),
+            (0, 0, 0)
+        );
+
+        let picks = [pick(&first, first_yes), pick(&second, second_yes)];
+        let result = create_parlay(&pool, &alice, &picks, 10).await;
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<BetError>(),
+            Some(&BetError::NotActive)
+        );
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn pushed_leg_cancels_the_parlay(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "Alice"]).await?;
+        let bob = users.pop().unwrap();
+        let alice = users.pop().unwrap();
+        let mut first = create_timeless_bet(&pool, &bob, String::from("first")).await?;
+        let (first_yes, _) = yes_no_options(&pool, &first).await?;
+        let mut second = create_timeless_bet(&pool, &bob, String::from("second")).await?;
+        let (second_yes, _) = yes_no_options(&pool, &second).await?;
+
+        let picks = [pick(&first, first_yes), pick(&second, second_yes)];
+        let (parlay, _) = create_parlay(&pool, &alice, &picks, 10).await?;
+        // Once someone joined, the edit bumps the version the leg was picked on
+        create_bet_participant(&pool, &bob, &second, 5, second_yes).await?;
+        let edit = BetEdit {
+            description: Some(String::from("second, edited")),
+            ..BetEdit::default()
+        };
+        edit_bet(&pool, &mut second, edit).await?;
+
+        settle(&pool, &mut first, first_yes).await?;
+        settle(&pool, &mut second, second_yes).await?;
+        let won: Vec<_> = parlay
+            .legs(&pool)
+            .await?
+            .iter()
+            .map(|leg| leg.won)
+            .collect();
+        assert_eq!(won, [Some(true), None]);
+        assert_eq!(status(&pool, &parlay).await?, ParlayStatus::Cancelled);
+        let score = read_user_score(&pool, &alice).await?;
+        assert_eq!(
+            (score.total_wins, score.total_losses, score.points_earned),
+            (0, 0, 0)
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 
 use crate::{
-    models::{BetParticipant, Score, User},
+    models::{BetParticipant, CategoryScore, Parlay, ParlayStatus, Score, User},
     AllResult,
 };
 
@@ -34,15 +34,32 @@ pub async fn read_user_score(connection: &PgPool, user: &User) -> AllResult<Scor
     Ok(score)
 }
 
+pub(super) async fn read_score_by_user_id(
+    connection: impl PgExecutor<'_>,
+    user_id: i32,
+) -> AllResult<Score> {
+    let score = sqlx::query_as!(
+        Score,
+        r#"
+        SELECT user_id, total_wins, total_losses, points_earned
+        FROM scores WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(score)
+}
+
 pub(super) async fn update_score_winning_bet(
-    connection: &PgPool,
+    connection: impl PgExecutor<'_>,
     participant: &BetParticipant,
 ) -> AllResult<Score> {
     let score = sqlx::query_as!(
         Score,
         r#"
         UPDATE scores
-        SET total_wins = total_wins + 1, points_earned = $1
+        SET total_wins = total_wins + 1, points_earned = points_earned + $1
         WHERE user_id = $2
         RETURNING *
         "#,
@@ -55,7 +72,7 @@ pub(super) async fn update_score_winning_bet(
 }
 
 pub(super) async fn update_score_losing_bet(
-    connection: &PgPool,
+    connection: impl PgExecutor<'_>,
     participant: &BetParticipant,
 ) -> AllResult<Score> {
     let score = sqlx::query_as!(
@@ -73,6 +90,133 @@ pub(super) async fn update_score_losing_bet(
     Ok(score)
 }
 
+/// Undoes `update_score_winning_bet`
+pub(super) async fn revert_score_winning_bet(
+    connection: impl PgExecutor<'_>,
+    participant: &BetParticipant,
+) -> AllResult<Score> {
+    let score = sqlx::query_as!(
+        Score,
+        r#"
+        UPDATE scores
+        SET total_wins = total_wins - 1, points_earned = points_earned - $1
+        WHERE user_id = $2
+        RETURNING *
+        "#,
+        participant.bet_amount,
+        participant.user_id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(score)
+}
+
+/// Undoes `update_score_losing_bet`
+pub(super) async fn revert_score_losing_bet(
+    connection: impl PgExecutor<'_>,
+    participant: &BetParticipant,
+) -> AllResult<Score> {
+    let score = sqlx::query_as!(
+        Score,
+        r#"
+        UPDATE scores
+        SET total_losses = total_losses - 1
+        WHERE user_id = $1
+        RETURNING *
+        "#,
+        participant.user_id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(score)
+}
+
+/// Counts a won or lost parlay in its user's score
+pub(super) async fn update_score_parlay(
+    connection: impl PgExecutor<'_>,
+    parlay: &Parlay,
+) -> AllResult<Score> {
+    adjust_score_parlay(connection, parlay, 1).await
+}
+
+/// Undoes `update_score_parlay`
+pub(super) async fn revert_score_parlay(
+    connection: impl PgExecutor<'_>,
+    parlay: &Parlay,
+) -> AllResult<Score> {
+    adjust_score_parlay(connection, parlay, -1).await
+}
+
+async fn adjust_score_parlay(
+    connection: impl PgExecutor<'_>,
+    parlay: &Parlay,
+    sign: i32,
+) -> AllResult<Score> {
+    let (wins, losses) = match parlay.status {
+        ParlayStatus::Won => (sign, 0),
+        ParlayStatus::Lost => (0, sign),
+        ParlayStatus::Open | ParlayStatus::Cancelled => (0, 0),
+    };
+    let score = sqlx::query_as!(
+        Score,
+        r#"
+        UPDATE scores
+        SET total_wins = total_wins + $1, total_losses = total_losses + $2,
+        points_earned = points_earned + $3
+        WHERE user_id = $4
+        RETURNING *
+        "#,
+        wins,
+        losses,
+        wins * parlay.points.unwrap_or(0),
+        parlay.user_id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(score)
+}
+
+/// Rebuilds every score from the recorded results of paid out participants
+/// and settled parlays, leaving out bets that were voided
+pub async fn recompute_scores(connection: &PgPool) -> AllResult<Vec<Score>> {
+    let scores = sqlx::query_as!(
+        Score,
+        r#"
+        UPDATE scores
+        SET
+            total_wins = (
+                SELECT COUNT(*) FROM bet_participants JOIN bets ON bets.id = bet_id
+                WHERE user_id = scores.user_id AND bet_participants.paid_out AND won
+                AND bets.status <> 'cancelled'
+            ) + (
+                SELECT COUNT(*) FROM parlays
+                WHERE user_id = scores.user_id AND status = 'won'
+            ),
+            total_losses = (
+                SELECT COUNT(*) FROM bet_participants JOIN bets ON bets.id = bet_id
+                WHERE user_id = scores.user_id AND bet_participants.paid_out AND NOT won
+                AND bets.status <> 'cancelled'
+            ) + (
+                SELECT COUNT(*) FROM parlays
+                WHERE user_id = scores.user_id AND status = 'lost'
+            ),
+            points_earned = (
+                SELECT COALESCE(SUM(bet_amount), 0)
+                FROM bet_participants JOIN bets ON bets.id = bet_id
+                WHERE user_id = scores.user_id AND bet_participants.paid_out AND won
+                AND bets.status <> 'cancelled'
+            ) + (
+                SELECT COALESCE(SUM(points), 0) FROM parlays
+                WHERE user_id = scores.user_id AND status = 'won'
+            )
+        RETURNING user_id, total_wins, total_losses, points_earned
+        "#
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(scores)
+}
+
 pub async fn read_score_by_username(connection: &PgPool, username: &str) -> AllResult<Score> {
     let score = sqlx::query_as!(
         Score,
@@ -89,13 +233,45 @@ pub async fn read_score_by_username(connection: &PgPool, username: &str) -> AllR
     Ok(score)
 }
 
+/// Counted from the recorded results of paid out participants, like
+/// `recompute_scores`
+pub async fn read_category_scores_by_username(
+    connection: &PgPool,
+    username: &str,
+) -> AllResult<Vec<CategoryScore>> {
+    let scores = sqlx::query_as!(
+        CategoryScore,
+        r#"
+        SELECT
+            users.id AS user_id, categories.id AS category_id, categories.name AS category,
+            COUNT(*) FILTER (WHERE won)::INT AS "total_wins!",
+            COUNT(*) FILTER (WHERE NOT won)::INT AS "total_losses!",
+            COALESCE(SUM(bet_amount) FILTER (WHERE won), 0)::INT AS "points_earned!"
+        FROM users
+        JOIN bet_participants ON bet_participants.user_id = users.id
+        JOIN bets ON bets.id = bet_participants.bet_id
+        JOIN categories ON categories.id = bets.category_id
+        WHERE username = $1 AND bet_participants.paid_out AND bets.status <> 'cancelled'
+        GROUP BY users.id, categories.id
+        ORDER BY categories.id
+        "#,
+        username
+    )
+    .fetch_all(connection)
+    .await?;
+    Ok(scores)
+}
+
 #[cfg(test)]
 mod unit_tests {
     use super::*;
     use sqlx::PgPool;
 
     use super::super::{
-        bet_participants::create_bet_participant, bets::create_timeless_bet, users::create_users,
+        bet_options::yes_no_options,
+        bet_participants::{create_bet_participant, payout_participant},
+        bets::create_timeless_bet,
+        users::create_users,
     };
 
     #[sqlx::test]
@@ -138,7 +314,8 @@ mod unit_tests {
         assert_eq!(score.total_losses, 0);
 
         let bet = create_timeless_bet(&pool, &bob, "".into()).await?;
-        let bet_participant = create_bet_participant(&pool, &bob, &bet, 100, true).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        let bet_participant = create_bet_participant(&pool, &bob, &bet, 100, yes).await?;
 
         let score = update_score_winning_bet(&pool, &bet_participant).await?;
 
@@ -161,7 +338,8 @@ mod unit_tests {
         assert_eq!(score.total_losses, 0);
 
         let bet = create_timeless_bet(&pool, &bob, "".into()).await?;
-        let bet_participant = create_bet_participant(&pool, &bob, &bet, 100, true).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        let bet_participant = create_bet_participant(&pool, &bob, &bet, 100, yes).await?;
 
         let score = update_score_losing_bet(&pool, &bet_participant).await?;
 
@@ -172,6 +350,63 @@ mod unit_tests {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn wins_add_up(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob"]).await?;
+        let bob = users.pop().unwrap();
+
+        let bet = create_timeless_bet(&pool, &bob, "".into()).await?;
+        let (yes, _) = yes_no_options(&pool, &bet).await?;
+        let bet_participant = create_bet_participant(&pool, &bob, &bet, 100, yes).await?;
+
+        update_score_winning_bet(&pool, &bet_participant).await?;
+        let score = update_score_winning_bet(&pool, &bet_participant).await?;
+
+        assert_eq!(score.points_earned, 200);
+        assert_eq!(score.total_wins, 2);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn recompute_from_results(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["Bob", "John"]).await?;
+        let bob = users.pop().unwrap();
+        let john = users.pop().unwrap();
+
+        for amount in [10, 20] {
+            let bet = create_timeless_bet(&pool, &bob, "".into()).await?;
+            let (yes, no) = yes_no_options(&pool, &bet).await?;
+            let bob_bet = create_bet_participant(&pool, &bob, &bet, amount, yes).await?;
+            let john_bet = create_bet_participant(&pool, &john, &bet, amount, no).await?;
+            let mut connection = pool.acquire().await?;
+            payout_participant(&mut connection, bob_bet, Some(yes)).await?;
+            payout_participant(&mut connection, john_bet, Some(yes)).await?;
+        }
+        let open_bet = create_timeless_bet(&pool, &bob, "".into()).await?;
+        let (open_yes, _) = yes_no_options(&pool, &open_bet).await?;
+        create_bet_participant(&pool, &bob, &open_bet, 50, open_yes).await?;
+
+        sqlx::query!("UPDATE scores SET total_wins = 0, total_losses = 0, points_earned = 0")
+            .execute(&pool)
+            .await?;
+
+        let scores = recompute_scores(&pool).await?;
+        assert_eq!(scores.len(), 2);
+
+        let bob_score = read_user_score(&pool, &bob).await?;
+        assert_eq!(bob_score.total_wins, 2);
+        assert_eq!(bob_score.total_losses, 0);
+        assert_eq!(bob_score.points_earned, 30);
+
+        let john_score = read_user_score(&pool, &john).await?;
+        assert_eq!(john_score.total_wins, 0);
+        assert_eq!(john_score.total_losses, 2);
+        assert_eq!(john_score.points_earned, 0);
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn get_score_with_username(pool: PgPool) -> AllResult<()> {
         let username = "bob";
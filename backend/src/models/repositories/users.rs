@@ -51,6 +51,27 @@ pub async fn create_user(
     Ok(user)
 }
 
+pub async fn set_user_suspended(
+    connection: &sqlx::PgPool,
+    user: &User,
+    suspended: bool,
+) -> AllResult<User> {
+    let user = sqlx::query_as!(
+        User,
+        r#"
+        UPDATE users
+        SET suspended_at = CASE WHEN $1 THEN COALESCE(suspended_at, NOW()) END
+        WHERE id = $2
+        RETURNING *
+        "#,
+        suspended,
+        user.id
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(user)
+}
+
 #[cfg(test)]
 pub async fn create_users<T>(pool: &sqlx::PgPool, usernames: Vec<T>) -> AllResult<Vec<User>>
 where
@@ -59,7 +80,7 @@ where
     let mut users = Vec::with_capacity(usernames.len());
     for username in usernames {
         let user = create_user(
-            &pool,
+            pool,
             username.clone().into(),
             username.into() + "@mail.com",
             "pass123".into(),
@@ -118,4 +139,21 @@ mod unit_tests {
         assert_eq!(read_user, created_user);
         Ok(())
     }
+
+    #[sqlx::test]
+    async fn suspend_user(pool: PgPool) -> AllResult<()> {
+        let mut users = create_users(&pool, vec!["john"]).await?;
+        let john = users.pop().unwrap();
+        assert!(john.suspended_at.is_none());
+
+        let suspended = set_user_suspended(&pool, &john, true).await?;
+        assert!(suspended.suspended_at.is_some());
+
+        let suspended_again = set_user_suspended(&pool, &suspended, true).await?;
+        assert_eq!(suspended_again.suspended_at, suspended.suspended_at);
+
+        let unsuspended = set_user_suspended(&pool, &suspended, false).await?;
+        assert!(unsuspended.suspended_at.is_none());
+        Ok(())
+    }
 }
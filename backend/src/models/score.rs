@@ -13,8 +13,36 @@ pub struct Score {
     pub points_earned: i32,
 }
 
+/// A user's record on the paid out bets of one category
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CategoryScore {
+    pub user_id: i32,
+    pub category_id: i32,
+    pub category: String,
+    pub total_wins: i32,
+    pub total_losses: i32,
+    pub points_earned: i32,
+}
+
 impl Score {
     pub async fn from_username(connection: &PgPool, username: &str) -> AllResult<Score> {
         scores::read_score_by_username(connection, username).await
     }
+
+    /// Rebuilds every user's score from the results of paid out bets. Bets
+    /// paid out before results were recorded are not counted.
+    pub async fn recompute_all(connection: &PgPool) -> AllResult<Vec<Score>> {
+        scores::recompute_scores(connection).await
+    }
+}
+
+impl CategoryScore {
+    /// The user's record in every category they played a paid out bet in,
+    /// ordered by category. Bets without a category are left out.
+    pub async fn from_username(
+        connection: &PgPool,
+        username: &str,
+    ) -> AllResult<Vec<CategoryScore>> {
+        scores::read_category_scores_by_username(connection, username).await
+    }
 }
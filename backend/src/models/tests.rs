@@ -50,18 +50,52 @@ fn run_bet(pool: PgPool) -> AllResult<()> {
     let bet3 = user2.create_timeless_bet(&pool, "bet1".into()).await?;
     let bet4 = user3.create_timeless_bet(&pool, "bet1".into()).await?;
 
-    assert_eq!(user1.bets_created(&pool).await?.len(), 2);
-    assert_eq!(user2.bets_created(&pool).await?.len(), 1);
-    assert_eq!(user3.bets_created(&pool).await?.len(), 1);
-
-    assert!(user1.bets_created(&pool).await?.contains(&bet1));
-    assert!(user1.bets_created(&pool).await?.contains(&bet2));
-    assert!(user2.bets_created(&pool).await?.contains(&bet3));
-    assert!(user3.bets_created(&pool).await?.contains(&bet4));
-
-    user1.particpate_in_bet(&pool, &bet1, 10, true).await?;
-    user2.particpate_in_bet(&pool, &bet1, 20, false).await?;
-    user3.particpate_in_bet(&pool, &bet1, 30, true).await?;
+    assert_eq!(
+        user1
+            .bets_created(&pool, Some(&user1), &BetFilter::default())
+            .await?
+            .len(),
+        2
+    );
+    assert_eq!(
+        user2
+            .bets_created(&pool, Some(&user2), &BetFilter::default())
+            .await?
+            .len(),
+        1
+    );
+    assert_eq!(
+        user3
+            .bets_created(&pool, Some(&user3), &BetFilter::default())
+            .await?
+            .len(),
+        1
+    );
+
+    assert!(user1
+        .bets_created(&pool, Some(&user1), &BetFilter::default())
+        .await?
+        .contains(&bet1));
+    assert!(user1
+        .bets_created(&pool, Some(&user1), &BetFilter::default())
+        .await?
+        .contains(&bet2));
+    assert!(user2
+        .bets_created(&pool, Some(&user2), &BetFilter::default())
+        .await?
+        .contains(&bet3));
+    assert!(user3
+        .bets_created(&pool, Some(&user3), &BetFilter::default())
+        .await?
+        .contains(&bet4));
+
+    let bet1_options = bet1.options(&pool).await?;
+    assert_eq!(bet1_options.len(), 2);
+    let (yes, no) = (bet1_options[0].id, bet1_options[1].id);
+
+    user1.particpate_in_bet(&pool, &bet1, 10, yes).await?;
+    user2.particpate_in_bet(&pool, &bet1, 20, no).await?;
+    user3.particpate_in_bet(&pool, &bet1, 30, yes).await?;
 
     let bet1_participants = bet1.participants(&pool).await?;
     assert_eq!(bet1_participants.len(), 3);
@@ -72,9 +106,10 @@ fn run_bet(pool: PgPool) -> AllResult<()> {
 
     assert_eq!(bet1.status, BetStatus::Finished);
 
-    bet1.payout(&pool, true).await?;
+    bet1.payout(&pool, yes).await?;
 
     assert_eq!(bet1.status, BetStatus::PayedOut);
+    assert_eq!(bet1.winning_option_id, Some(yes));
 
     assert_eq!(user1.score(&pool).await?.points_earned, 10);
     assert_eq!(user1.score(&pool).await?.total_wins, 1);
@@ -1,10 +1,12 @@
 use super::{
     repositories::{
-        bet_participants, bets,
+        bet_challenges, bet_invitations, bet_participants, bet_templates, bets,
         friendships::{self, FriendRequestResponse},
-        scores, users,
+        parlays, scores, users,
     },
-    Bet, BetParticipant, Friendship, Score,
+    Bet, BetChallenge, BetFilter, BetInvitation, BetParticipant, BetSchedule, BetSettings,
+    BetTemplate, BetVisibility, ChallengeTerms, Friendship, InvitationStatus, Parlay, ParlayLeg,
+    ParlayPick, Score, VoteResolution,
 };
 use crate::AllResult;
 use serde::Serialize;
@@ -19,6 +21,7 @@ pub struct User {
     pub password_hash: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub suspended_at: Option<NaiveDateTime>,
 }
 
 impl User {
@@ -40,6 +43,24 @@ impl User {
         users::read_user_with_username(connection, username).await
     }
 
+    pub fn has_password(&self, password: String) -> bool {
+        self.password_hash == hash_password(password)
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_at.is_some()
+    }
+
+    pub async fn suspend(&mut self, connection: &PgPool) -> AllResult<()> {
+        *self = users::set_user_suspended(connection, self, true).await?;
+        Ok(())
+    }
+
+    pub async fn unsuspend(&mut self, connection: &PgPool) -> AllResult<()> {
+        *self = users::set_user_suspended(connection, self, false).await?;
+        Ok(())
+    }
+
     pub async fn create_default_score(&self, connection: &PgPool) -> AllResult<Score> {
         scores::create_default_score(connection, self).await
     }
@@ -94,8 +115,14 @@ impl User {
         Ok(())
     }
 
-    pub async fn bets_created(&self, connection: &PgPool) -> AllResult<Vec<Bet>> {
-        bets::get_bets_by_user(connection, self).await
+    /// Bets the user created that `viewer` can see and that match `filter`
+    pub async fn bets_created(
+        &self,
+        connection: &PgPool,
+        viewer: Option<&User>,
+        filter: &BetFilter,
+    ) -> AllResult<Vec<Bet>> {
+        bets::get_bets_by_user(connection, self, viewer, filter).await
     }
 
     pub async fn create_timeless_bet(
@@ -106,6 +133,156 @@ impl User {
         bets::create_timeless_bet(connection, self, description).await
     }
 
+    /// Creates a bet whose participants pick one of `options`, or Yes or No
+    pub async fn create_bet_with_options(
+        &self,
+        connection: &PgPool,
+        description: String,
+        stop_bets_at: Option<NaiveDateTime>,
+        options: Option<&[String]>,
+        settings: &BetSettings,
+    ) -> AllResult<Bet> {
+        bets::create_bet(
+            connection,
+            self,
+            description,
+            stop_bets_at,
+            options,
+            settings,
+        )
+        .await
+    }
+
+    /// Creates a bet where participants pick Over or Under `line`
+    pub async fn create_over_under_bet(
+        &self,
+        connection: &PgPool,
+        description: String,
+        stop_bets_at: Option<NaiveDateTime>,
+        line: f64,
+        settings: &BetSettings,
+    ) -> AllResult<Bet> {
+        bets::create_over_under_bet(connection, self, description, stop_bets_at, line, settings)
+            .await
+    }
+
+    /// Creates a bet whose outcome participants vote on, see `Bet::vote`
+    pub async fn create_vote_bet(
+        &self,
+        connection: &PgPool,
+        description: String,
+        stop_bets_at: Option<NaiveDateTime>,
+        options: Option<&[String]>,
+        vote: VoteResolution,
+        settings: &BetSettings,
+    ) -> AllResult<Bet> {
+        bets::create_vote_bet(
+            connection,
+            self,
+            description,
+            stop_bets_at,
+            options,
+            vote,
+            settings,
+        )
+        .await
+    }
+
+    /// Creates a template that makes this bet on every occurrence of
+    /// `schedule`, inviting `invitees` to each private bet it makes
+    pub async fn create_bet_template(
+        &self,
+        connection: &PgPool,
+        description: String,
+        options: Option<&[String]>,
+        visibility: BetVisibility,
+        schedule: &BetSchedule,
+        invitees: &[User],
+    ) -> AllResult<BetTemplate> {
+        bet_templates::create_bet_template(
+            connection,
+            self,
+            description,
+            options,
+            visibility,
+            schedule,
+            invitees,
+        )
+        .await
+    }
+
+    pub async fn bet_templates(&self, connection: &PgPool) -> AllResult<Vec<BetTemplate>> {
+        bet_templates::get_bet_templates_by_user(connection, self).await
+    }
+
+    /// Challenges an accepted friend to a one-on-one bet, see `BetChallenge`.
+    /// The user joins `terms.side` right away, the bet stays pending until
+    /// the friend answers.
+    pub async fn challenge(
+        &self,
+        connection: &PgPool,
+        friend: &User,
+        terms: &ChallengeTerms,
+    ) -> AllResult<(Bet, BetChallenge)> {
+        bet_challenges::create_challenge(connection, self, friend, terms).await
+    }
+
+    /// Takes the other side of a challenge at the same stake, and opens the
+    /// bet
+    pub async fn accept_challenge(
+        &self,
+        connection: &PgPool,
+        bet: &mut Bet,
+    ) -> AllResult<BetChallenge> {
+        bet_challenges::answer_challenge(connection, bet, self, true).await
+    }
+
+    /// Turns down a challenge, calling the bet off
+    pub async fn decline_challenge(
+        &self,
+        connection: &PgPool,
+        bet: &mut Bet,
+    ) -> AllResult<BetChallenge> {
+        bet_challenges::answer_challenge(connection, bet, self, false).await
+    }
+
+    /// Challenges the user sent or received, newest first
+    pub async fn challenges(&self, connection: &PgPool) -> AllResult<Vec<BetChallenge>> {
+        bet_challenges::get_challenges_by_user(connection, self).await
+    }
+
+    /// Combines picks on several open bets into one parlay, see
+    /// `Parlay`
+    pub async fn place_parlay(
+        &self,
+        connection: &PgPool,
+        picks: &[ParlayPick],
+        stake: i32,
+    ) -> AllResult<(Parlay, Vec<ParlayLeg>)> {
+        parlays::create_parlay(connection, self, picks, stake).await
+    }
+
+    /// The user's parlays with their legs, newest first
+    pub async fn parlays(&self, connection: &PgPool) -> AllResult<Vec<(Parlay, Vec<ParlayLeg>)>> {
+        parlays::get_parlays_by_user(connection, self).await
+    }
+
+    pub async fn accept_bet_invitation(
+        &self,
+        connection: &PgPool,
+        bet: &Bet,
+    ) -> AllResult<BetInvitation> {
+        bet_invitations::answer_invitation(connection, bet, self, InvitationStatus::Accepted).await
+    }
+
+    pub async fn decline_bet_invitation(
+        &self,
+        connection: &PgPool,
+        bet: &Bet,
+    ) -> AllResult<BetInvitation> {
+        bet_invitations::answer_invitation(connection, bet, self, InvitationStatus::Declined).await
+    }
+
     pub async fn create_timed_bet(
         &self,
         connection: &PgPool,
@@ -119,14 +296,59 @@ impl User {
         bet_participants::get_bet_participants_by_bet_user(connection, self).await
     }
 
+    /// Every bet the user joined that `viewer` can see, with their
+    /// participation in it
+    pub async fn bets_joined(
+        &self,
+        connection: &PgPool,
+        viewer: Option<&User>,
+    ) -> AllResult<Vec<(Bet, BetParticipant)>> {
+        bets::get_bets_with_user(connection, self, viewer).await
+    }
+
     pub async fn particpate_in_bet(
         &self,
         connection: &PgPool,
         bet: &Bet,
         amount: i32,
-        for_bet: bool,
+        option_id: i32,
+    ) -> AllResult<BetParticipant> {
+        bet_participants::create_bet_participant(connection, self, bet, amount, option_id).await
+    }
+
+    /// Agrees to the current version of an edited bet
+    pub async fn confirm_bet(&self, connection: &PgPool, bet: &Bet) -> AllResult<BetParticipant> {
+        bet_participants::confirm_bet_version(connection, bet, self).await
+    }
+
+    /// Leaves an active bet before its cutoff, or after it if the bet was
+    /// edited since the user last confirmed it
+    pub async fn withdraw_from_bet(
+        &self,
+        connection: &PgPool,
+        bet: &Bet,
+    ) -> AllResult<BetParticipant> {
+        bet_participants::withdraw_bet_participant(connection, bet, self).await
+    }
+
+    /// Moves the user's stake to another option before the bet's cutoff
+    pub async fn switch_bet_option(
+        &self,
+        connection: &PgPool,
+        bet: &Bet,
+        option_id: i32,
+    ) -> AllResult<BetParticipant> {
+        bet_participants::switch_bet_option(connection, bet, self, option_id).await
+    }
+
+    /// Changes the user's stake before the bet's cutoff
+    pub async fn adjust_bet_amount(
+        &self,
+        connection: &PgPool,
+        bet: &Bet,
+        amount: i32,
     ) -> AllResult<BetParticipant> {
-        bet_participants::create_bet_participant(connection, self, bet, amount, for_bet).await
+        bet_participants::adjust_bet_amount(connection, bet, self, amount).await
     }
 }
 
@@ -0,0 +1,42 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+
+use super::handlers::APIError;
+use crate::models::Bet;
+
+/// Rejects the request with `412 Precondition Failed` when an `If-Match`
+/// header is present and does not match the bet's current ETag.
+pub fn check_if_match(headers: &HeaderMap, bet: &Bet) -> Result<(), APIError> {
+    let Some(if_match) = headers.get(header::IF_MATCH) else {
+        return Ok(());
+    };
+    let if_match = if_match
+        .to_str()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid If-Match header"))?;
+
+    let etag = bet.etag();
+    let matches = if_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag);
+    match matches {
+        true => Ok(()),
+        false => Err(precondition_failed()),
+    }
+}
+
+pub fn with_etag(mut response: Response, bet: &Bet) -> Response {
+    if let Ok(etag) = HeaderValue::from_str(&bet.etag()) {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    response
+}
+
+pub fn precondition_failed() -> APIError {
+    (
+        StatusCode::PRECONDITION_FAILED,
+        "Bet was modified since it was read",
+    )
+}
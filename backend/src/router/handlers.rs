@@ -1,9 +1,48 @@
-use crate::models::{Bet, Score, User};
-use axum::{extract::State, Json};
-use serde::Deserialize;
+use super::{
+    etag::{check_if_match, precondition_failed, with_etag},
+    idempotency::idempotent,
+};
+use crate::models::{
+    Bet, BetAttachment, BetChallenge, BetEdit, BetError, BetFilter, BetLabels, BetOption,
+    BetSchedule, BetSettings, BetStatus, BetTemplate, BetVisibility, Category, CategoryScore,
+    ChallengeTerms, Parlay, ParlayLeg, ParlayPick, Score, StakeLimits, User, VoteResolution,
+    MAX_ATTACHMENT_BYTES, MAX_PARLAY_LEGS, MAX_TAGS, MAX_TAG_LENGTH, MIN_TEMPLATE_INTERVAL,
+};
+use crate::storage::Storage;
+use crate::telemetry;
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use std::sync::{Arc, LazyLock};
 
 type APIResult<T> = Result<Json<T>, &'static str>;
+pub type APIError = (StatusCode, &'static str);
+pub type APIResponse = Result<Response, APIError>;
+
+// Errors that name a limit are built from it once, so they can't drift apart
+static INVALID_TAGS: LazyLock<String> = LazyLock::new(|| {
+    format!("Tags are at most {MAX_TAGS} words of up to {MAX_TAG_LENGTH} letters, digits, - or _")
+});
+static ATTACHMENT_TOO_LARGE: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "Attachments are at most {} MiB",
+        MAX_ATTACHMENT_BYTES / (1024 * 1024)
+    )
+});
+static INVALID_SCHEDULE: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "Schedule needs a valid cron expression for cron recurrence only, matching at least {} minutes apart, and a positive cutoff",
+        MIN_TEMPLATE_INTERVAL.num_minutes()
+    )
+});
+static INVALID_PARLAY: LazyLock<String> =
+    LazyLock::new(|| format!("Parlays need 2 to {MAX_PARLAY_LEGS} legs, each on a different bet"));
 
 #[derive(Deserialize)]
 pub struct CreateUser {
@@ -22,10 +61,30 @@ pub async fn create_user(
 ) -> APIResult<User> {
     User::new(&pool, username, email, password)
         .await
-        .map(|user| Json(user))
+        .map(Json)
         .map_err(|_| "Unable to create user")
 }
 
+#[derive(Deserialize)]
+pub struct Login {
+    username: String,
+    password: String,
+}
+
+/// Checks the user's password, failures are counted in `LOGINS_FAILED`
+pub async fn login(
+    State(pool): State<PgPool>,
+    Json(Login { username, password }): Json<Login>,
+) -> Result<Json<User>, APIError> {
+    match User::read_from_name(&pool, &username).await {
+        Ok(user) if user.has_password(password) => Ok(Json(user)),
+        _ => {
+            metrics::counter!(telemetry::LOGINS_FAILED).increment(1);
+            Err((StatusCode::UNAUTHORIZED, "Invalid username or password"))
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Username {
     username: String,
@@ -37,7 +96,7 @@ pub async fn get_user(
 ) -> APIResult<User> {
     User::read_from_name(&pool, &username)
         .await
-        .map(|user| Json(user))
+        .map(Json)
         .map_err(|_| "Unable to get user")
 }
 
@@ -47,44 +106,1202 @@ pub async fn get_score(
 ) -> APIResult<Score> {
     Score::from_username(&pool, &username)
         .await
-        .map(|user| Json(user))
+        .map(Json)
         .map_err(|_| "Unable to get score")
 }
 
-#[derive(Deserialize)]
+pub async fn get_category_scores(
+    State(pool): State<PgPool>,
+    Json(Username { username }): Json<Username>,
+) -> APIResult<Vec<CategoryScore>> {
+    CategoryScore::from_username(&pool, &username)
+        .await
+        .map(Json)
+        .map_err(|_| "Unable to get scores")
+}
+
+pub async fn get_categories(State(pool): State<PgPool>) -> APIResult<Vec<Category>> {
+    Category::read_all(&pool)
+        .await
+        .map(Json)
+        .map_err(|_| "Unable to get categories")
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct CreateBet {
     username: String,
     description: String,
     stop_bets_at: Option<chrono::NaiveDateTime>,
+    /// Defaults to Yes and No
+    options: Option<Vec<String>>,
+    /// Makes an over/under bet on this line instead
+    line: Option<f64>,
+    /// Lets participants vote on the outcome instead of the creator
+    vote: Option<VoteResolution>,
+    #[serde(flatten)]
+    settings: BetSettings,
+}
+
+/// A bet together with the options participants can pick
+#[derive(Serialize)]
+pub struct BetWithOptions {
+    #[serde(flatten)]
+    bet: Bet,
+    options: Vec<BetOption>,
+}
+
+async fn with_options(pool: &PgPool, bet: Bet) -> Result<BetWithOptions, APIError> {
+    let options = bet.options(pool).await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unable to get bet options",
+        )
+    })?;
+    Ok(BetWithOptions { bet, options })
 }
 
 pub async fn create_bet(
     State(pool): State<PgPool>,
-    Json(CreateBet {
+    headers: HeaderMap,
+    Json(request): Json<CreateBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &request.username).await?;
+    idempotent(&pool, &headers, &user, "/bet", &request, async {
+        let description = request.description.clone();
+        let stop_bets_at = request.stop_bets_at;
+        let settings = &request.settings;
+        let bet = match (&request.options, request.line, request.vote) {
+            (Some(_), Some(_), _) => {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Over/under bets can't have options",
+                ))
+            }
+            (_, Some(_), Some(_)) => {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Over/under bets can't be resolved by vote",
+                ))
+            }
+            (None, Some(line), None) => {
+                user.create_over_under_bet(&pool, description, stop_bets_at, line, settings)
+                    .await
+            }
+            (options, None, Some(vote)) => {
+                let options = options.as_deref();
+                user.create_vote_bet(&pool, description, stop_bets_at, options, vote, settings)
+                    .await
+            }
+            (options, None, None) => {
+                let options = options.as_deref();
+                user.create_bet_with_options(&pool, description, stop_bets_at, options, settings)
+                    .await
+            }
+        };
+        let bet = bet.map_err(|error| match error.downcast_ref::<BetError>() {
+            Some(_) => bet_error(error, "Unable to create bet"),
+            None => (StatusCode::BAD_REQUEST, "Unable to create bet"),
+        })?;
+        with_options(&pool, bet).await
+    })
+    .await
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct JoinBet {
+    username: String,
+    bet_id: i32,
+    amount: i32,
+    option_id: i32,
+}
+
+pub async fn join_bet(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(request): Json<JoinBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &request.username).await?;
+    let bet = read_bet(&pool, request.bet_id).await?;
+    idempotent(&pool, &headers, &user, "/bet/join", &request, async {
+        user.particpate_in_bet(&pool, &bet, request.amount, request.option_id)
+            .await
+            .map_err(|error| bet_error(error, "Unable to join bet"))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct Viewer {
+    username: Option<String>,
+}
+
+/// Bets the viewer can't see are not found, as if they didn't exist
+pub async fn get_bet(
+    State(pool): State<PgPool>,
+    Path(bet_id): Path<i32>,
+    Query(Viewer { username }): Query<Viewer>,
+) -> APIResponse {
+    let viewer = read_viewer(&pool, username.as_deref()).await?;
+    let bet = Bet::read_visible_by_id(&pool, bet_id, viewer.as_ref())
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get bet"))?;
+    let bet = with_options(&pool, bet).await?;
+    Ok(with_etag(Json(&bet).into_response(), &bet.bet))
+}
+
+/// Every version of the bet's terms, for viewers who can see the bet
+pub async fn get_bet_versions(
+    State(pool): State<PgPool>,
+    Path(bet_id): Path<i32>,
+    Query(Viewer { username }): Query<Viewer>,
+) -> APIResponse {
+    let viewer = read_viewer(&pool, username.as_deref()).await?;
+    let bet = Bet::read_visible_by_id(&pool, bet_id, viewer.as_ref())
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get bet"))?;
+    let versions = bet.versions(&pool).await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unable to get bet versions",
+        )
+    })?;
+    Ok(Json(versions).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct Uploader {
+    username: String,
+}
+
+/// The user managing a closed bet, or a participant, attaches proof before it
+/// is paid out. The body is the file, or a link sent as `text/uri-list`.
+pub async fn attach_to_bet(
+    State(pool): State<PgPool>,
+    State(storage): State<Arc<dyn Storage>>,
+    Path(bet_id): Path<i32>,
+    Query(Uploader { username }): Query<Uploader>,
+    headers: HeaderMap,
+    content: Bytes,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let bet = read_bet(&pool, bet_id).await?;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or_default();
+    let attachment = bet
+        .attach(&pool, storage.as_ref(), &user, content_type, content)
+        .await
+        .map_err(|error| bet_error(error, "Unable to attach to bet"))?;
+    Ok(Json(attachment).into_response())
+}
+
+/// The attachments of a bet the viewer can see
+pub async fn get_attachments(
+    State(pool): State<PgPool>,
+    Path(bet_id): Path<i32>,
+    Query(Viewer { username }): Query<Viewer>,
+) -> APIResponse {
+    let viewer = read_viewer(&pool, username.as_deref()).await?;
+    let bet = Bet::read_visible_by_id(&pool, bet_id, viewer.as_ref())
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get bet"))?;
+    let attachments = bet.attachments(&pool).await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unable to get attachments",
+        )
+    })?;
+    Ok(Json(attachments).into_response())
+}
+
+/// Serves an uploaded file, or redirects to a link, if the viewer can see
+/// the bet it is attached to
+pub async fn get_attachment(
+    State(pool): State<PgPool>,
+    State(storage): State<Arc<dyn Storage>>,
+    Path(attachment_id): Path<i32>,
+    Query(Viewer { username }): Query<Viewer>,
+) -> APIResponse {
+    let viewer = read_viewer(&pool, username.as_deref()).await?;
+    let attachment = BetAttachment::read_by_id(&pool, attachment_id)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get attachment"))?;
+    Bet::read_visible_by_id(&pool, attachment.bet_id, viewer.as_ref())
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get attachment"))?;
+    if let Some(url) = &attachment.url {
+        return Ok(Redirect::temporary(url).into_response());
+    }
+    let content = attachment
+        .content(storage.as_ref())
+        .await
+        .ok()
+        .flatten()
+        .ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unable to get attachment",
+        ))?;
+    Ok(([(header::CONTENT_TYPE, attachment.content_type)], content).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct EditBet {
+    username: String,
+    bet_id: i32,
+    #[serde(flatten)]
+    edit: BetEdit,
+}
+
+/// The creator changes an active bet's description, cutoff or options
+pub async fn edit_bet(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(EditBet {
         username,
-        description,
-        stop_bets_at,
-    }): Json<CreateBet>,
-) -> APIResult<Bet> {
-    let user = User::read_from_name(&pool, &username)
+        bet_id,
+        edit,
+    }): Json<EditBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_created_bet(&pool, &user, bet_id).await?;
+    check_if_match(&headers, &bet)?;
+    bet.edit(&pool, edit)
         .await
-        .map_err(|_| "Unable to get user")?;
-    let bet = match stop_bets_at {
-        Some(time) => user.create_timed_bet(&pool, description, time).await,
-        None => user.create_timeless_bet(&pool, description).await,
-    };
-    bet.map(|bet| Json(bet)).map_err(|_| "Unable to create bet")
+        .map_err(|error| bet_error(error, "Unable to edit bet"))?;
+    let bet = with_options(&pool, bet).await?;
+    Ok(with_etag(Json(&bet).into_response(), &bet.bet))
+}
+
+#[derive(Deserialize)]
+pub struct LabelBet {
+    username: String,
+    bet_id: i32,
+    #[serde(flatten)]
+    labels: BetLabels,
+}
+
+/// The creator files an active bet under a category and replaces its tags
+pub async fn label_bet(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(LabelBet {
+        username,
+        bet_id,
+        labels,
+    }): Json<LabelBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_created_bet(&pool, &user, bet_id).await?;
+    check_if_match(&headers, &bet)?;
+    bet.label(&pool, &labels)
+        .await
+        .map_err(|error| bet_error(error, "Unable to label bet"))?;
+    Ok(with_etag(Json(&bet).into_response(), &bet))
+}
+
+#[derive(Deserialize)]
+pub struct LimitBet {
+    username: String,
+    bet_id: i32,
+    #[serde(flatten)]
+    limits: StakeLimits,
+}
+
+/// The creator replaces an active bet's stake limits and participant cap,
+/// people who already joined keep their stakes
+pub async fn limit_bet(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(LimitBet {
+        username,
+        bet_id,
+        limits,
+    }): Json<LimitBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_created_bet(&pool, &user, bet_id).await?;
+    check_if_match(&headers, &bet)?;
+    bet.limit(&pool, &limits)
+        .await
+        .map_err(|error| bet_error(error, "Unable to limit bet"))?;
+    Ok(with_etag(Json(&bet).into_response(), &bet))
+}
+
+#[derive(Deserialize)]
+pub struct AnswerEdit {
+    username: String,
+    bet_id: i32,
+}
+
+/// A participant agrees to the current version of an edited bet
+pub async fn confirm_bet(
+    State(pool): State<PgPool>,
+    Json(AnswerEdit { username, bet_id }): Json<AnswerEdit>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let bet = read_bet(&pool, bet_id).await?;
+    let participant = user
+        .confirm_bet(&pool, &bet)
+        .await
+        .map_err(|error| bet_error(error, "Unable to confirm bet"))?;
+    Ok(Json(participant).into_response())
+}
+
+/// A participant leaves an active bet before its cutoff, or after it if they
+/// didn't confirm the current version
+pub async fn withdraw_from_bet(
+    State(pool): State<PgPool>,
+    Json(AnswerEdit { username, bet_id }): Json<AnswerEdit>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let bet = read_bet(&pool, bet_id).await?;
+    let participant = user
+        .withdraw_from_bet(&pool, &bet)
+        .await
+        .map_err(|error| bet_error(error, "Unable to withdraw from bet"))?;
+    Ok(Json(participant).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SwitchOption {
+    username: String,
+    bet_id: i32,
+    option_id: i32,
+}
+
+/// A participant moves their stake to another option before the cutoff
+pub async fn switch_option(
+    State(pool): State<PgPool>,
+    Json(SwitchOption {
+        username,
+        bet_id,
+        option_id,
+    }): Json<SwitchOption>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let bet = read_bet(&pool, bet_id).await?;
+    let participant = user
+        .switch_bet_option(&pool, &bet, option_id)
+        .await
+        .map_err(|error| bet_error(error, "Unable to switch option"))?;
+    Ok(Json(participant).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct AdjustStake {
+    username: String,
+    bet_id: i32,
+    amount: i32,
+}
+
+/// A participant changes their stake before the cutoff
+pub async fn adjust_stake(
+    State(pool): State<PgPool>,
+    Json(AdjustStake {
+        username,
+        bet_id,
+        amount,
+    }): Json<AdjustStake>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let bet = read_bet(&pool, bet_id).await?;
+    let participant = user
+        .adjust_bet_amount(&pool, &bet, amount)
+        .await
+        .map_err(|error| bet_error(error, "Unable to change stake"))?;
+    Ok(Json(participant).into_response())
+}
+
+/// Every change participants made to their positions, for viewers who can
+/// see the bet
+pub async fn get_participant_changes(
+    State(pool): State<PgPool>,
+    Path(bet_id): Path<i32>,
+    Query(Viewer { username }): Query<Viewer>,
+) -> APIResponse {
+    let viewer = read_viewer(&pool, username.as_deref()).await?;
+    let bet = Bet::read_visible_by_id(&pool, bet_id, viewer.as_ref())
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get bet"))?;
+    let changes = bet.participant_changes(&pool).await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unable to get participant changes",
+        )
+    })?;
+    Ok(Json(changes).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct CloseBet {
+    username: String,
+    bet_id: i32,
+}
+
+pub async fn close_bet(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(CloseBet { username, bet_id }): Json<CloseBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_managed_bet(&pool, &user, bet_id).await?;
+    check_if_match(&headers, &bet)?;
+    if bet.status != BetStatus::Active {
+        return Err((StatusCode::CONFLICT, "Bet is not active"));
+    }
+    bet.close(&pool)
+        .await
+        .map_err(|error| bet_error(error, "Unable to close bet"))?;
+    Ok(with_etag(Json(&bet).into_response(), &bet))
+}
+
+#[derive(Deserialize)]
+pub struct VoidBet {
+    username: String,
+    bet_id: i32,
+}
+
+pub async fn void_bet(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(VoidBet { username, bet_id }): Json<VoidBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_bet(&pool, bet_id).await?;
+    // Calling off the bet doesn't pick a winner, so the creator can still do
+    // it once an arbiter took over
+    if user.id != bet.creator_id && user.id != bet.manager_id() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only the creator or the arbiter can void this bet",
+        ));
+    }
+    check_if_match(&headers, &bet)?;
+    bet.void(&pool)
+        .await
+        .map_err(|error| bet_error(error, "Unable to void bet"))?;
+    Ok(with_etag(Json(&bet).into_response(), &bet))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PayoutBet {
+    username: String,
+    bet_id: i32,
+    winning_option_id: i32,
+}
+
+pub async fn payout_bet(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(request): Json<PayoutBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &request.username).await?;
+    let mut bet = read_managed_bet(&pool, &user, request.bet_id).await?;
+    let response = idempotent(&pool, &headers, &user, "/bet/payout", &request, async {
+        check_if_match(&headers, &bet)?;
+        if bet.status != BetStatus::Finished {
+            return Err((
+                StatusCode::CONFLICT,
+                "Bet must be closed before it is paid out",
+            ));
+        }
+        bet.payout(&pool, request.winning_option_id)
+            .await
+            .map_err(|error| bet_error(error, "Unable to pay out bet"))?;
+        Ok(bet)
+    })
+    .await?;
+    let bet = read_bet(&pool, request.bet_id).await?;
+    Ok(with_etag(response, &bet))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SettleBet {
+    username: String,
+    bet_id: i32,
+    actual_value: f64,
+}
+
+pub async fn settle_bet(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(request): Json<SettleBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &request.username).await?;
+    let mut bet = read_managed_bet(&pool, &user, request.bet_id).await?;
+    let response = idempotent(&pool, &headers, &user, "/bet/settle", &request, async {
+        check_if_match(&headers, &bet)?;
+        if bet.status != BetStatus::Finished {
+            return Err((
+                StatusCode::CONFLICT,
+                "Bet must be closed before it is paid out",
+            ));
+        }
+        bet.settle_over_under(&pool, request.actual_value)
+            .await
+            .map_err(|error| bet_error(error, "Unable to settle bet"))?;
+        Ok(bet)
+    })
+    .await?;
+    let bet = read_bet(&pool, request.bet_id).await?;
+    Ok(with_etag(response, &bet))
+}
+
+#[derive(Deserialize)]
+pub struct InviteToBet {
+    username: String,
+    bet_id: i32,
+    invitee: String,
+}
+
+/// The creator invites one of their friends to a private bet
+pub async fn invite_to_bet(
+    State(pool): State<PgPool>,
+    Json(InviteToBet {
+        username,
+        bet_id,
+        invitee,
+    }): Json<InviteToBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let bet = read_created_bet(&pool, &user, bet_id).await?;
+    let invitee = read_user(&pool, &invitee).await?;
+    let invitation = bet
+        .invite(&pool, &invitee)
+        .await
+        .map_err(|error| bet_error(error, "Unable to invite user"))?;
+    Ok(Json(invitation).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct AnswerInvitation {
+    username: String,
+    bet_id: i32,
+}
+
+pub async fn accept_invitation(
+    State(pool): State<PgPool>,
+    Json(AnswerInvitation { username, bet_id }): Json<AnswerInvitation>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let bet = read_bet(&pool, bet_id).await?;
+    let invitation = user
+        .accept_bet_invitation(&pool, &bet)
+        .await
+        .map_err(|error| bet_error(error, "Unable to accept invitation"))?;
+    Ok(Json(invitation).into_response())
+}
+
+pub async fn decline_invitation(
+    State(pool): State<PgPool>,
+    Json(AnswerInvitation { username, bet_id }): Json<AnswerInvitation>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let bet = read_bet(&pool, bet_id).await?;
+    let invitation = user
+        .decline_bet_invitation(&pool, &bet)
+        .await
+        .map_err(|error| bet_error(error, "Unable to decline invitation"))?;
+    Ok(Json(invitation).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct NameArbiter {
+    username: String,
+    bet_id: i32,
+    arbiter: String,
+}
+
+/// The creator asks a user who doesn't participate to judge the bet
+pub async fn name_arbiter(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(NameArbiter {
+        username,
+        bet_id,
+        arbiter,
+    }): Json<NameArbiter>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_created_bet(&pool, &user, bet_id).await?;
+    check_if_match(&headers, &bet)?;
+    let arbiter = read_user(&pool, &arbiter).await?;
+    bet.name_arbiter(&pool, &arbiter)
+        .await
+        .map_err(|error| bet_error(error, "Unable to name arbiter"))?;
+    Ok(with_etag(Json(&bet).into_response(), &bet))
+}
+
+#[derive(Deserialize)]
+pub struct AnswerArbiterRole {
+    username: String,
+    bet_id: i32,
+}
+
+pub async fn accept_arbiter_role(
+    State(pool): State<PgPool>,
+    Json(AnswerArbiterRole { username, bet_id }): Json<AnswerArbiterRole>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_bet(&pool, bet_id).await?;
+    bet.accept_arbiter_role(&pool, &user)
+        .await
+        .map_err(|error| bet_error(error, "Unable to accept arbiter role"))?;
+    Ok(with_etag(Json(&bet).into_response(), &bet))
+}
+
+pub async fn decline_arbiter_role(
+    State(pool): State<PgPool>,
+    Json(AnswerArbiterRole { username, bet_id }): Json<AnswerArbiterRole>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_bet(&pool, bet_id).await?;
+    bet.decline_arbiter_role(&pool, &user)
+        .await
+        .map_err(|error| bet_error(error, "Unable to decline arbiter role"))?;
+    Ok(with_etag(Json(&bet).into_response(), &bet))
+}
+
+#[derive(Deserialize)]
+pub struct VoteOnBet {
+    username: String,
+    bet_id: i32,
+    option_id: i32,
+}
+
+/// Any participant can vote on a closed bet resolved by vote
+pub async fn vote_on_bet(
+    State(pool): State<PgPool>,
+    Json(VoteOnBet {
+        username,
+        bet_id,
+        option_id,
+    }): Json<VoteOnBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_bet(&pool, bet_id).await?;
+    bet.vote(&pool, &user, option_id)
+        .await
+        .map_err(|error| bet_error(error, "Unable to vote on bet"))?;
+    Ok(with_etag(Json(&bet).into_response(), &bet))
+}
+
+#[derive(Deserialize)]
+pub struct DisputeBet {
+    username: String,
+    bet_id: i32,
+}
+
+/// Any participant can dispute the outcome their votes proposed
+pub async fn dispute_bet(
+    State(pool): State<PgPool>,
+    Json(DisputeBet { username, bet_id }): Json<DisputeBet>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_bet(&pool, bet_id).await?;
+    bet.dispute(&pool, &user)
+        .await
+        .map_err(|error| bet_error(error, "Unable to dispute bet"))?;
+    Ok(with_etag(Json(&bet).into_response(), &bet))
+}
+
+#[derive(Deserialize)]
+pub struct UserBets {
+    username: String,
+    /// Who is looking, only public bets are listed without one
+    viewer: Option<String>,
+    #[serde(flatten)]
+    filter: BetFilter,
 }
 
 pub async fn get_bets(
     State(pool): State<PgPool>,
-    Json(Username { username }): Json<Username>,
+    Json(UserBets {
+        username,
+        viewer,
+        filter,
+    }): Json<UserBets>,
 ) -> APIResult<Vec<Bet>> {
     let user = User::read_from_name(&pool, &username)
         .await
         .map_err(|_| "Unable to get user")?;
-    user.bets_created(&pool)
+    let viewer = read_viewer(&pool, viewer.as_deref())
+        .await
+        .map_err(|(_, message)| message)?;
+    user.bets_created(&pool, viewer.as_ref(), &filter)
         .await
-        .map(|bet| Json(bet))
+        .map(Json)
         .map_err(|_| "Unable to get bets")
 }
+
+#[derive(Deserialize)]
+pub struct CreateTemplate {
+    username: String,
+    description: String,
+    /// Defaults to Yes and No
+    options: Option<Vec<String>>,
+    /// Defaults to public
+    #[serde(default)]
+    visibility: BetVisibility,
+    #[serde(flatten)]
+    schedule: BetSchedule,
+    /// Friends invited to every bet, for private templates
+    #[serde(default)]
+    invitees: Vec<String>,
+}
+
+/// Creates a template that makes a new bet on every occurrence of its schedule
+pub async fn create_template(
+    State(pool): State<PgPool>,
+    Json(request): Json<CreateTemplate>,
+) -> APIResponse {
+    let user = read_user(&pool, &request.username).await?;
+    let mut invitees = Vec::with_capacity(request.invitees.len());
+    for invitee in &request.invitees {
+        invitees.push(read_user(&pool, invitee).await?);
+    }
+    let template = user
+        .create_bet_template(
+            &pool,
+            request.description,
+            request.options.as_deref(),
+            request.visibility,
+            &request.schedule,
+            &invitees,
+        )
+        .await
+        .map_err(|error| bet_error(error, "Unable to create template"))?;
+    Ok(Json(template).into_response())
+}
+
+pub async fn get_templates(
+    State(pool): State<PgPool>,
+    Json(Username { username }): Json<Username>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let templates = user
+        .bet_templates(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Unable to get templates"))?;
+    Ok(Json(templates).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct StopTemplate {
+    username: String,
+    template_id: i32,
+}
+
+/// The creator stops a template from making more bets
+pub async fn stop_template(
+    State(pool): State<PgPool>,
+    Json(StopTemplate {
+        username,
+        template_id,
+    }): Json<StopTemplate>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut template = BetTemplate::read_by_id(&pool, template_id)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get template"))?;
+    if template.creator_id != user.id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only the creator can stop this template",
+        ));
+    }
+    template
+        .stop(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Unable to stop template"))?;
+    Ok(Json(template).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct CreateChallenge {
+    username: String,
+    friend: String,
+    #[serde(flatten)]
+    terms: ChallengeTerms,
+}
+
+/// A challenge together with its bet and the bet's options
+#[derive(Serialize)]
+pub struct ChallengeWithBet {
+    #[serde(flatten)]
+    challenge: BetChallenge,
+    bet: BetWithOptions,
+}
+
+/// A user challenges one of their friends to a one-on-one bet
+pub async fn create_challenge(
+    State(pool): State<PgPool>,
+    Json(CreateChallenge {
+        username,
+        friend,
+        terms,
+    }): Json<CreateChallenge>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let friend = read_user(&pool, &friend).await?;
+    let (bet, challenge) = user
+        .challenge(&pool, &friend, &terms)
+        .await
+        .map_err(|error| bet_error(error, "Unable to create challenge"))?;
+    let bet = with_options(&pool, bet).await?;
+    Ok(Json(ChallengeWithBet { challenge, bet }).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct AnswerChallenge {
+    username: String,
+    bet_id: i32,
+}
+
+pub async fn accept_challenge(
+    State(pool): State<PgPool>,
+    Json(AnswerChallenge { username, bet_id }): Json<AnswerChallenge>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_bet(&pool, bet_id).await?;
+    let challenge = user
+        .accept_challenge(&pool, &mut bet)
+        .await
+        .map_err(|error| bet_error(error, "Unable to accept challenge"))?;
+    let bet = with_options(&pool, bet).await?;
+    Ok(Json(ChallengeWithBet { challenge, bet }).into_response())
+}
+
+pub async fn decline_challenge(
+    State(pool): State<PgPool>,
+    Json(AnswerChallenge { username, bet_id }): Json<AnswerChallenge>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let mut bet = read_bet(&pool, bet_id).await?;
+    let challenge = user
+        .decline_challenge(&pool, &mut bet)
+        .await
+        .map_err(|error| bet_error(error, "Unable to decline challenge"))?;
+    let bet = with_options(&pool, bet).await?;
+    Ok(Json(ChallengeWithBet { challenge, bet }).into_response())
+}
+
+pub async fn get_challenges(
+    State(pool): State<PgPool>,
+    Json(Username { username }): Json<Username>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let challenges = user.challenges(&pool).await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unable to get challenges",
+        )
+    })?;
+    Ok(Json(challenges).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct CreateParlay {
+    username: String,
+    stake: i32,
+    legs: Vec<ParlayPick>,
+}
+
+#[derive(Serialize)]
+pub struct ParlayWithLegs {
+    #[serde(flatten)]
+    parlay: Parlay,
+    legs: Vec<ParlayLeg>,
+}
+
+/// A user combines picks on several open bets into one parlay
+pub async fn create_parlay(
+    State(pool): State<PgPool>,
+    Json(CreateParlay {
+        username,
+        stake,
+        legs,
+    }): Json<CreateParlay>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    for leg in &legs {
+        read_bet(&pool, leg.bet_id).await?;
+    }
+    let (parlay, legs) = user
+        .place_parlay(&pool, &legs, stake)
+        .await
+        .map_err(|error| bet_error(error, "Unable to create parlay"))?;
+    Ok(Json(ParlayWithLegs { parlay, legs }).into_response())
+}
+
+pub async fn get_parlays(
+    State(pool): State<PgPool>,
+    Json(Username { username }): Json<Username>,
+) -> APIResponse {
+    let user = read_user(&pool, &username).await?;
+    let parlays = user
+        .parlays(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Unable to get parlays"))?
+        .into_iter()
+        .map(|(parlay, legs)| ParlayWithLegs { parlay, legs })
+        .collect::<Vec<_>>();
+    Ok(Json(parlays).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct Discover {
+    /// Defaults to active bets
+    status: Option<BetStatus>,
+    username: Option<String>,
+    #[serde(flatten)]
+    filter: BetFilter,
+}
+
+/// Lists the bets the viewer can see, only public bets without a viewer
+pub async fn discover_bets(
+    State(pool): State<PgPool>,
+    Query(Discover {
+        status,
+        username,
+        filter,
+    }): Query<Discover>,
+) -> APIResponse {
+    let viewer = read_viewer(&pool, username.as_deref()).await?;
+    let status = status.unwrap_or(BetStatus::Active);
+    let bets = Bet::read_all_by_status(&pool, &status, viewer.as_ref(), &filter)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Unable to get bets"))?;
+    Ok(Json(bets).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct Search {
+    q: String,
+    username: Option<String>,
+    /// Defaults to 20, at most 100
+    limit: Option<i64>,
+}
+
+/// Searches the descriptions of the bets the viewer can see
+pub async fn search_bets(
+    State(pool): State<PgPool>,
+    Query(Search { q, username, limit }): Query<Search>,
+) -> APIResponse {
+    if q.trim().is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "Search query is empty"));
+    }
+    let viewer = read_viewer(&pool, username.as_deref()).await?;
+    let limit = limit.unwrap_or(20).clamp(1, 100);
+    let matches = Bet::search(&pool, &q, viewer.as_ref(), limit)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Unable to search bets"))?;
+    Ok(Json(matches).into_response())
+}
+
+/// Reads the user a bet listing or lookup is made for, anonymous viewers only
+/// see public bets
+async fn read_viewer(pool: &PgPool, username: Option<&str>) -> Result<Option<User>, APIError> {
+    match username {
+        Some(username) => User::read_from_name(pool, username)
+            .await
+            .map(Some)
+            .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get user")),
+        None => Ok(None),
+    }
+}
+
+/// Reads the user making the request, suspended users can't act
+async fn read_user(pool: &PgPool, username: &str) -> Result<User, APIError> {
+    let user = User::read_from_name(pool, username)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get user"))?;
+    if user.is_suspended() {
+        return Err((StatusCode::FORBIDDEN, "User is suspended"));
+    }
+    Ok(user)
+}
+
+async fn read_bet(pool: &PgPool, bet_id: i32) -> Result<Bet, APIError> {
+    Bet::read_by_id(pool, bet_id)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Unable to get bet"))
+}
+
+async fn read_created_bet(pool: &PgPool, user: &User, bet_id: i32) -> Result<Bet, APIError> {
+    let bet = read_bet(pool, bet_id).await?;
+    if bet.creator_id != user.id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only the creator can manage this bet",
+        ));
+    }
+    Ok(bet)
+}
+
+/// Reads a bet `user` closes and pays out, see `Bet::manager_id`
+async fn read_managed_bet(pool: &PgPool, user: &User, bet_id: i32) -> Result<Bet, APIError> {
+    let bet = read_bet(pool, bet_id).await?;
+    if bet.manager_id() != user.id {
+        let message = if bet.manager_id() == bet.creator_id {
+            "Only the creator can manage this bet"
+        } else {
+            "Only the arbiter can manage this bet"
+        };
+        return Err((StatusCode::FORBIDDEN, message));
+    }
+    Ok(bet)
+}
+
+fn bet_error(error: Box<dyn std::error::Error>, message: &'static str) -> APIError {
+    match error.downcast_ref::<BetError>() {
+        Some(BetError::Stale) => precondition_failed(),
+        Some(BetError::NotActive) => (StatusCode::CONFLICT, "Bet is not accepting participants"),
+        Some(BetError::CutoffPassed) => {
+            (StatusCode::CONFLICT, "Bet stopped accepting participants")
+        }
+        Some(BetError::NotFinished) => (
+            StatusCode::CONFLICT,
+            "Bet must be closed before it is paid out",
+        ),
+        Some(BetError::AlreadyPaidOut) => (StatusCode::CONFLICT, "Bet was already paid out"),
+        Some(BetError::Cancelled) => (StatusCode::CONFLICT, "Bet was cancelled"),
+        Some(BetError::InvalidOptions) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Bet needs at least two distinct options",
+        ),
+        Some(BetError::UnknownOption) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Option does not belong to this bet",
+        ),
+        Some(BetError::InvalidLine) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Line must be a finite number",
+        ),
+        Some(BetError::WrongKind) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Over/under bets are settled with an actual value, other bets with a winning option",
+        ),
+        Some(BetError::InvalidStake) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Bet amount must be positive",
+        ),
+        Some(BetError::InvalidVoteRules) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Quorum must be positive and the dispute window can't be negative",
+        ),
+        Some(BetError::NotVoting) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Bet is not resolved by vote",
+        ),
+        Some(BetError::AwaitingVotes) => (
+            StatusCode::CONFLICT,
+            "Bet is resolved by vote and can't be paid out by hand unless disputed",
+        ),
+        Some(BetError::NotParticipant) => (
+            StatusCode::FORBIDDEN,
+            "User didn't join this bet",
+        ),
+        Some(BetError::VotingClosed) => (StatusCode::CONFLICT, "Votes already proposed an outcome"),
+        Some(BetError::NotDisputable) => (
+            StatusCode::CONFLICT,
+            "Bet has no outcome that can still be disputed",
+        ),
+        Some(BetError::InvalidArbiter) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Arbiter can't be the creator or a participant",
+        ),
+        Some(BetError::NotArbiter) => (
+            StatusCode::FORBIDDEN,
+            "User wasn't asked to arbitrate this bet",
+        ),
+        Some(BetError::ArbiterAccepted) => (
+            StatusCode::CONFLICT,
+            "Arbiter already accepted and can't be replaced",
+        ),
+        Some(BetError::ArbiterCannotJoin) => {
+            (StatusCode::FORBIDDEN, "The bet's arbiter can't join it")
+        }
+        Some(BetError::NotPrivate) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Only private bets take invitations",
+        ),
+        Some(BetError::NotFriend) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Only the creator's friends can be invited",
+        ),
+        Some(BetError::AlreadyInvited) => (StatusCode::CONFLICT, "User was already invited"),
+        Some(BetError::NotInvited) => (
+            StatusCode::FORBIDDEN,
+            "Only invited users can join this bet",
+        ),
+        Some(BetError::FriendsOnly) => (
+            StatusCode::FORBIDDEN,
+            "Only the creator's friends can join this bet",
+        ),
+        Some(BetError::UnknownCategory) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, "Category does not exist")
+        }
+        Some(BetError::InvalidTags) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            INVALID_TAGS.as_str(),
+        ),
+        Some(BetError::InvalidChallenge) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Challenges need exactly two options, and an expiry in the future and before the cutoff",
+        ),
+        Some(BetError::NotChallenged) => (
+            StatusCode::FORBIDDEN,
+            "User wasn't challenged with this bet",
+        ),
+        Some(BetError::ChallengeClosed) => (
+            StatusCode::CONFLICT,
+            "Challenge was already answered, expired or called off",
+        ),
+        Some(BetError::InvalidLimits) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Stake limits and participant caps must be positive, and the minimum stake can't exceed the maximum",
+        ),
+        Some(BetError::StakeTooLow) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Bet amount is below the bet's minimum stake",
+        ),
+        Some(BetError::StakeTooHigh) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Bet amount is above the bet's maximum stake",
+        ),
+        Some(BetError::BetFull) => (
+            StatusCode::CONFLICT,
+            "Bet has reached its maximum number of participants",
+        ),
+        Some(BetError::InvalidTransition) => {
+            (StatusCode::CONFLICT, "Bet status can't change this way")
+        }
+        Some(BetError::AttachmentTooLarge) => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            ATTACHMENT_TOO_LARGE.as_str(),
+        ),
+        Some(BetError::UnsupportedContentType) => (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Attachments are PNG, JPEG, GIF or WebP images matching their Content-Type, or text/uri-list links",
+        ),
+        Some(BetError::InvalidLink) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Links are a single http or https URL",
+        ),
+        Some(BetError::InvalidParlay) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            INVALID_PARLAY.as_str(),
+        ),
+        Some(BetError::InvalidSchedule) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            INVALID_SCHEDULE.as_str(),
+        ),
+        Some(BetError::OptionPicked) => (
+            StatusCode::CONFLICT,
+            "Options participants picked can't be removed",
+        ),
+        None => (StatusCode::INTERNAL_SERVER_ERROR, message),
+    }
+}
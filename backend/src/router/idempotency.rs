@@ -0,0 +1,119 @@
+use std::future::Future;
+
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use super::handlers::{APIError, APIResponse};
+use crate::models::{IdempotencyClaim, IdempotencyKey, User};
+use crate::telemetry;
+
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+/// Runs `action` at most once per (user, `Idempotency-Key`, route).
+///
+/// The first successful response is stored and replayed for every retry with
+/// the same key and request body. Requests without the header are run as is.
+///
+/// `action` has to reject requests before it writes anything, so a client
+/// error releases the key and the client can fix the request and retry. A
+/// server error may come after the action's changes were committed, so the
+/// key stays claimed and retries are turned away rather than run again, until
+/// the key expires after `KEY_TTL`.
+pub async fn idempotent<T, F>(
+    pool: &PgPool,
+    headers: &HeaderMap,
+    user: &User,
+    route: &str,
+    request: &impl Serialize,
+    action: F,
+) -> APIResponse
+where
+    T: Serialize,
+    F: Future<Output = Result<T, APIError>>,
+{
+    let Some(key) = headers.get(IDEMPOTENCY_KEY) else {
+        return action.await.map(|body| Json(body).into_response());
+    };
+    let key = key
+        .to_str()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid Idempotency-Key"))?;
+    let request_body = serde_json::to_value(request)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Unable to read request"))?;
+
+    let claim = IdempotencyKey::claim(pool, user, key, route, request_body)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Unable to claim Idempotency-Key",
+            )
+        })?;
+
+    let mut idempotency_key = match claim {
+        IdempotencyClaim::Claimed(idempotency_key) => idempotency_key,
+        IdempotencyClaim::Completed(response_body) => return Ok(replay(response_body)),
+        IdempotencyClaim::InProgress => {
+            return Err((
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key is still in progress",
+            ))
+        }
+        IdempotencyClaim::Abandoned => {
+            return Err((
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key didn't finish and may have gone through",
+            ))
+        }
+        IdempotencyClaim::Mismatch => {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key was already used with a different request",
+            ))
+        }
+    };
+
+    let body = match action.await {
+        Ok(body) => body,
+        Err(error) if error.0.is_client_error() => {
+            if let Err(release_error) = idempotency_key.release(pool).await {
+                // Retries with this key are turned away until it expires
+                telemetry::report_failure(
+                    "idempotency",
+                    format_args!("unable to release key: {release_error}"),
+                );
+            }
+            return Err(error);
+        }
+        Err(error) => return Err(error),
+    };
+
+    let response_body = serde_json::to_value(body).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unable to write response",
+        )
+    })?;
+    idempotency_key
+        .complete(pool, response_body.clone())
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Unable to store response",
+            )
+        })?;
+    Ok(Json(response_body).into_response())
+}
+
+fn replay(response_body: serde_json::Value) -> Response {
+    let mut response = Json(response_body).into_response();
+    response
+        .headers_mut()
+        .insert("Idempotent-Replayed", HeaderValue::from_static("true"));
+    response
+}
@@ -1,15 +1,96 @@
+mod etag;
 mod handlers;
+mod idempotency;
 
-use axum::routing::{get, post};
-use handlers::{create_bet, create_user, get_bets, get_score, get_user};
+use std::sync::Arc;
+
+use axum::{
+    extract::{DefaultBodyLimit, FromRef},
+    middleware,
+    routing::{get, post},
+};
+use handlers::{
+    accept_arbiter_role, accept_challenge, accept_invitation, adjust_stake, attach_to_bet,
+    close_bet, confirm_bet, create_bet, create_challenge, create_parlay, create_template,
+    create_user, decline_arbiter_role, decline_challenge, decline_invitation, discover_bets,
+    dispute_bet, edit_bet, get_attachment, get_attachments, get_bet, get_bet_versions, get_bets,
+    get_categories, get_category_scores, get_challenges, get_parlays, get_participant_changes,
+    get_score, get_templates, get_user, invite_to_bet, join_bet, label_bet, limit_bet, login,
+    name_arbiter, payout_bet, search_bets, settle_bet, stop_template, switch_option, void_bet,
+    vote_on_bet, withdraw_from_bet,
+};
+use metrics_exporter_prometheus::PrometheusHandle;
 use sqlx::PgPool;
 
-pub fn create_router(pool: PgPool) -> axum::Router {
+use crate::{models::MAX_ATTACHMENT_BYTES, storage::Storage, telemetry};
+
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pool: PgPool,
+    metrics: PrometheusHandle,
+    storage: Arc<dyn Storage>,
+}
+
+pub fn create_router(
+    pool: PgPool,
+    metrics: PrometheusHandle,
+    storage: Arc<dyn Storage>,
+) -> axum::Router {
     axum::Router::new()
         .route("/user", post(create_user))
         .route("/user", get(get_user))
+        .route("/user/login", post(login))
         .route("/user/score", get(get_score))
+        .route("/user/score/categories", get(get_category_scores))
         .route("/user/bets", get(get_bets))
+        .route("/user/templates", get(get_templates))
+        .route("/user/challenges", get(get_challenges))
+        .route("/user/parlays", get(get_parlays))
         .route("/bet", post(create_bet))
-        .with_state(pool)
+        .route("/bet/{id}", get(get_bet))
+        .route("/bet/{id}/versions", get(get_bet_versions))
+        .route("/bet/{id}/changes", get(get_participant_changes))
+        .route(
+            "/bet/{id}/attachments",
+            get(get_attachments)
+                .post(attach_to_bet)
+                .layer(DefaultBodyLimit::max(MAX_ATTACHMENT_BYTES)),
+        )
+        .route("/attachment/{id}", get(get_attachment))
+        .route("/bets", get(discover_bets))
+        .route("/bets/search", get(search_bets))
+        .route("/categories", get(get_categories))
+        .route("/bet/join", post(join_bet))
+        .route("/bet/edit", post(edit_bet))
+        .route("/bet/labels", post(label_bet))
+        .route("/bet/limits", post(limit_bet))
+        .route("/bet/confirm", post(confirm_bet))
+        .route("/bet/withdraw", post(withdraw_from_bet))
+        .route("/bet/switch", post(switch_option))
+        .route("/bet/stake", post(adjust_stake))
+        .route("/bet/close", post(close_bet))
+        .route("/bet/payout", post(payout_bet))
+        .route("/bet/settle", post(settle_bet))
+        .route("/bet/void", post(void_bet))
+        .route("/bet/vote", post(vote_on_bet))
+        .route("/bet/dispute", post(dispute_bet))
+        .route("/bet/invite", post(invite_to_bet))
+        .route("/bet/invitation/accept", post(accept_invitation))
+        .route("/bet/invitation/decline", post(decline_invitation))
+        .route("/bet/arbiter", post(name_arbiter))
+        .route("/bet/arbiter/accept", post(accept_arbiter_role))
+        .route("/bet/arbiter/decline", post(decline_arbiter_role))
+        .route("/challenge", post(create_challenge))
+        .route("/challenge/accept", post(accept_challenge))
+        .route("/challenge/decline", post(decline_challenge))
+        .route("/parlay", post(create_parlay))
+        .route("/template", post(create_template))
+        .route("/template/stop", post(stop_template))
+        .route("/metrics", get(telemetry::render))
+        .route_layer(middleware::from_fn(telemetry::track_requests))
+        .with_state(AppState {
+            pool,
+            metrics,
+            storage,
+        })
 }
@@ -0,0 +1,319 @@
+use std::{sync::Arc, time::Duration};
+
+use sqlx::{types::chrono::NaiveDateTime, PgPool};
+use tokio::task::JoinHandle;
+
+use crate::{models::IdempotencyKey, telemetry, AllResult, Bet, BetChallenge, BetTemplate};
+
+/// Source of the current time for background jobs, so tests can move time
+/// forward without waiting
+pub trait Clock: Send + Sync {
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// The local wall clock, the same time source used when bets are created
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        sqlx::types::chrono::Local::now().naive_local()
+    }
+}
+
+/// The bets a scheduler pass created or changed
+#[derive(Debug, Default)]
+pub struct Pass {
+    pub created: Vec<Bet>,
+    /// Challenges nobody answered in time, their bets were called off
+    pub expired: Vec<BetChallenge>,
+    pub closed: Vec<Bet>,
+    pub settled: Vec<Bet>,
+    /// How many expired idempotency keys were deleted
+    pub purged_keys: u64,
+}
+
+/// Runs one scheduler pass: creates the bets of every due template, expires
+/// unanswered challenges, closes every active bet whose cutoff has passed,
+/// pays out every voted bet whose dispute window has passed, then deletes
+/// expired idempotency keys. Template occurrences missed while the scheduler
+/// was down are all created on the next pass.
+///
+/// Each step runs even if an earlier one failed, a failed step is logged and
+/// retried on the next pass.
+pub async fn run_once(connection: &PgPool, clock: &dyn Clock) -> Pass {
+    let now = clock.now();
+    let created = logged(
+        "create template bets",
+        BetTemplate::create_due_bets(connection, now).await,
+    );
+    let expired = logged(
+        "expire challenges",
+        BetChallenge::expire_due(connection, now).await,
+    );
+    let closed = logged("close bets", Bet::close_expired(connection, now).await);
+    let settled = logged("settle bets", Bet::settle_voted(connection, now).await);
+    let purged_keys = logged(
+        "purge idempotency keys",
+        IdempotencyKey::purge_expired(connection, now).await,
+    );
+    Pass {
+        created,
+        expired,
+        closed,
+        settled,
+        purged_keys,
+    }
+}
+
+/// The step's result, or nothing after logging why it failed
+fn logged<T: Default>(step: &str, result: AllResult<T>) -> T {
+    result.unwrap_or_else(|error| {
+        telemetry::report_failure("scheduler", format_args!("unable to {step}: {error}"));
+        T::default()
+    })
+}
+
+/// Runs a scheduler pass every `interval` until the server stops
+pub fn spawn(connection: PgPool, clock: Arc<dyn Clock>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            run_once(&connection, clock.as_ref()).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        BetFilter, BetSchedule, BetSettings, BetStatus, BetVisibility, ChallengeStatus,
+        ChallengeTerms, Recurrence, User, VoteResolution,
+    };
+    use chrono::TimeDelta;
+    use std::sync::Mutex;
+
+    struct TestClock(Mutex<NaiveDateTime>);
+
+    impl TestClock {
+        fn at(now: NaiveDateTime) -> Self {
+            TestClock(Mutex::new(now))
+        }
+
+        fn advance(&self, by: TimeDelta) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> NaiveDateTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    async fn create_bob(pool: &PgPool) -> AllResult<User> {
+        User::new(pool, "bob".into(), "bob@mail.com".into(), "pass123".into()).await
+    }
+
+    #[sqlx::test]
+    async fn closes_bets_once_cutoff_passes(pool: PgPool) -> AllResult<()> {
+        let bob = create_bob(&pool).await?;
+        let start = SystemClock.now();
+        let clock = TestClock::at(start);
+
+        let in_one_hour = bob
+            .create_timed_bet(&pool, "one hour".into(), start + TimeDelta::hours(1))
+            .await?;
+        let in_two_hours = bob
+            .create_timed_bet(&pool, "two hours".into(), start + TimeDelta::hours(2))
+            .await?;
+        let timeless = bob.create_timeless_bet(&pool, "timeless".into()).await?;
+
+        assert!(run_once(&pool, &clock).await.closed.is_empty());
+
+        clock.advance(TimeDelta::minutes(90));
+        let closed = run_once(&pool, &clock).await.closed;
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].id, in_one_hour.id);
+        assert_eq!(closed[0].status, BetStatus::Finished);
+        assert_eq!(
+            Bet::read_by_id(&pool, in_one_hour.id).await?.status,
+            BetStatus::Finished
+        );
+
+        clock.advance(TimeDelta::days(1));
+        let closed = run_once(&pool, &clock).await.closed;
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].id, in_two_hours.id);
+
+        assert!(run_once(&pool, &clock).await.closed.is_empty());
+        assert_eq!(
+            Bet::read_by_id(&pool, timeless.id).await?.status,
+            BetStatus::Active
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn concurrent_passes_close_each_bet_once(pool: PgPool) -> AllResult<()> {
+        let bob = create_bob(&pool).await?;
+        let now = SystemClock.now();
+        for _ in 0..20 {
+            bob.create_timed_bet(&pool, "expired".into(), now - TimeDelta::minutes(1))
+                .await?;
+        }
+
+        let clock = Arc::new(TestClock::at(now));
+        let replicas: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                let clock = clock.clone();
+                tokio::spawn(async move { run_once(&pool, clock.as_ref()).await.closed.len() })
+            })
+            .collect();
+
+        let mut total_closed = 0;
+        for replica in replicas {
+            total_closed += replica.await?;
+        }
+        assert_eq!(total_closed, 20);
+        assert_eq!(
+            Bet::read_all_by_status(&pool, &BetStatus::Finished, None, &BetFilter::default())
+                .await?
+                .len(),
+            20
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn settles_voted_bets_once_dispute_window_passes(pool: PgPool) -> AllResult<()> {
+        let bob = create_bob(&pool).await?;
+        let alice = User::new(
+            &pool,
+            "alice".into(),
+            "alice@mail.com".into(),
+            "pass123".into(),
+        )
+        .await?;
+        let clock = TestClock::at(SystemClock.now());
+
+        let vote = VoteResolution {
+            quorum: 1,
+            dispute_window_seconds: 3600,
+        };
+        let mut bet = bob
+            .create_vote_bet(
+                &pool,
+                "voted".into(),
+                None,
+                None,
+                vote,
+                &BetSettings::default(),
+            )
+            .await?;
+        let options = bet.options(&pool).await?;
+        alice
+            .particpate_in_bet(&pool, &bet, 10, options[0].id)
+            .await?;
+        bet.close(&pool).await?;
+        bet.vote(&pool, &alice, options[0].id).await?;
+
+        assert!(run_once(&pool, &clock).await.settled.is_empty());
+
+        clock.advance(TimeDelta::hours(2));
+        let settled = run_once(&pool, &clock).await.settled;
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].status, BetStatus::PayedOut);
+        assert_eq!(settled[0].winning_option_id, Some(options[0].id));
+
+        assert!(run_once(&pool, &clock).await.settled.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn catches_up_on_missed_template_runs(pool: PgPool) -> AllResult<()> {
+        let bob = create_bob(&pool).await?;
+        let start = SystemClock.now();
+        let clock = TestClock::at(start);
+
+        let schedule = BetSchedule {
+            recurrence: Recurrence::Daily,
+            cron: None,
+            starts_at: start + TimeDelta::hours(1),
+            cutoff_seconds: Some(30 * 60),
+        };
+        bob.create_bet_template(
+            &pool,
+            "Who wins Sunday's match?".into(),
+            None,
+            BetVisibility::Public,
+            &schedule,
+            &[],
+        )
+        .await?;
+        assert!(run_once(&pool, &clock).await.created.is_empty());
+
+        // Down for over two days, a bet is created for each of the three
+        // missed occurrences and the two whose cutoff passed are closed right
+        // away
+        clock.advance(TimeDelta::days(2) + TimeDelta::minutes(80));
+        let pass = run_once(&pool, &clock).await;
+        assert_eq!(pass.created.len(), 3);
+        let closed: Vec<_> = pass.closed.iter().map(|bet| bet.id).collect();
+        assert_eq!(closed, [pass.created[0].id, pass.created[1].id]);
+        assert_eq!(
+            Bet::read_by_id(&pool, pass.created[2].id).await?.status,
+            BetStatus::Active
+        );
+
+        clock.advance(TimeDelta::days(1));
+        assert_eq!(run_once(&pool, &clock).await.created.len(), 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn expires_unanswered_challenges(pool: PgPool) -> AllResult<()> {
+        let bob = create_bob(&pool).await?;
+        let john = User::new(
+            &pool,
+            "john".into(),
+            "john@mail.com".into(),
+            "pass123".into(),
+        )
+        .await?;
+        bob.send_friend_request(&pool, &john).await?;
+        john.accept_friend_request(&pool, &bob).await?;
+        let start = SystemClock.now();
+        let clock = TestClock::at(start);
+
+        let terms = ChallengeTerms {
+            description: "I bet you the printer jams today".into(),
+            options: None,
+            side: "Yes".into(),
+            amount: 20,
+            expires_at: Some(start + TimeDelta::hours(1)),
+            stop_bets_at: None,
+        };
+        let (bet, _) = bob.challenge(&pool, &john, &terms).await?;
+        assert!(run_once(&pool, &clock).await.expired.is_empty());
+
+        clock.advance(TimeDelta::hours(2));
+        let expired = run_once(&pool, &clock).await.expired;
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].status, ChallengeStatus::Expired);
+        assert_eq!(
+            Bet::read_by_id(&pool, bet.id).await?.status,
+            BetStatus::Cancelled
+        );
+        assert!(run_once(&pool, &clock).await.expired.is_empty());
+
+        Ok(())
+    }
+}
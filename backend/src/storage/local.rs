@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::fs;
+
+use super::Storage;
+use crate::AllResult;
+
+/// Keeps every key as a file under `root`
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> AllResult<PathBuf> {
+        // Keys are made by the server, this only guards against mistakes
+        if key.split('/').any(|part| part.is_empty() || part == "..") {
+            return Err(format!("invalid storage key {key:?}").into());
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    /// Writes to a temporary file first, so readers never see half a file
+    async fn put(&self, key: &str, content: Bytes) -> AllResult<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let partial = path.with_extension("partial");
+        fs::write(&partial, &content).await?;
+        fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> AllResult<Bytes> {
+        let path = self.path(key)?;
+        Ok(fs::read(path).await?.into())
+    }
+
+    async fn delete(&self, key: &str) -> AllResult<()> {
+        let path = self.path(key)?;
+        fs::remove_file(path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn put_get_delete() -> AllResult<()> {
+        let root = std::env::temp_dir().join(format!("bwf-storage-{}", std::process::id()));
+        let storage = LocalStorage::new(&root);
+
+        storage
+            .put("bets/1/2", Bytes::from_static(b"proof"))
+            .await?;
+        assert_eq!(storage.get("bets/1/2").await?, Bytes::from_static(b"proof"));
+        storage
+            .put("bets/1/2", Bytes::from_static(b"better"))
+            .await?;
+        assert_eq!(
+            storage.get("bets/1/2").await?,
+            Bytes::from_static(b"better")
+        );
+        storage.delete("bets/1/2").await?;
+        assert!(storage.get("bets/1/2").await.is_err());
+        assert!(storage.put("../escape", Bytes::new()).await.is_err());
+
+        fs::remove_dir_all(root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+//! Where attachment contents are kept, see `BetAttachment`. The database only
+//! stores each attachment's key.
+
+mod local;
+mod s3;
+
+use std::{env, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use crate::AllResult;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+/// A flat store of blobs addressed by keys like `bets/1/2`
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Stores `content` under `key`, replacing whatever was there
+    async fn put(&self, key: &str, content: Bytes) -> AllResult<()>;
+    async fn get(&self, key: &str) -> AllResult<Bytes>;
+    async fn delete(&self, key: &str) -> AllResult<()>;
+}
+
+/// Which storage backend the server uses
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageConfig {
+    /// Files under a local directory
+    Local(PathBuf),
+    /// An S3-compatible bucket, configured with the usual `AWS_` variables
+    S3,
+}
+
+impl StorageConfig {
+    /// Reads `ATTACHMENT_STORAGE`, `local` by default or `s3`. Local storage
+    /// keeps files under `ATTACHMENT_DIR`, `attachments` by default.
+    pub fn from_env() -> AllResult<Self> {
+        match env::var("ATTACHMENT_STORAGE").as_deref() {
+            Ok("local") | Err(_) => {
+                let dir = env::var("ATTACHMENT_DIR").unwrap_or_else(|_| "attachments".into());
+                Ok(StorageConfig::Local(dir.into()))
+            }
+            Ok("s3") => Ok(StorageConfig::S3),
+            Ok(other) => Err(format!("unknown ATTACHMENT_STORAGE {other:?}").into()),
+        }
+    }
+
+    pub fn open(&self) -> AllResult<Arc<dyn Storage>> {
+        Ok(match self {
+            StorageConfig::Local(dir) => Arc::new(LocalStorage::new(dir)),
+            StorageConfig::S3 => Arc::new(S3Storage::from_env()?),
+        })
+    }
+}
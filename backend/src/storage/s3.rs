@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use object_store::{
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path,
+    ObjectStore,
+};
+
+use super::Storage;
+use crate::AllResult;
+
+/// Keeps every key as an object in an S3-compatible bucket
+pub struct S3Storage {
+    bucket: AmazonS3,
+}
+
+impl S3Storage {
+    /// Reads the bucket, region and credentials from `AWS_BUCKET`,
+    /// `AWS_REGION`, `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`. Other
+    /// S3-compatible stores like MinIO also need `AWS_ENDPOINT`, and
+    /// `AWS_ALLOW_HTTP=true` for plain HTTP endpoints.
+    pub fn from_env() -> AllResult<Self> {
+        let bucket = AmazonS3Builder::from_env().build()?;
+        Ok(S3Storage { bucket })
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, content: Bytes) -> AllResult<()> {
+        let path = Path::parse(key)?;
+        self.bucket.put(&path, content.into()).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> AllResult<Bytes> {
+        let path = Path::parse(key)?;
+        Ok(self.bucket.get(&path).await?.bytes().await?)
+    }
+
+    async fn delete(&self, key: &str) -> AllResult<()> {
+        let path = Path::parse(key)?;
+        self.bucket.delete(&path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run with `cargo test -- --ignored` against a bucket configured like
+    /// `S3Storage::from_env` expects, for example a local MinIO
+    #[tokio::test]
+    #[ignore = "needs an S3-compatible bucket"]
+    async fn put_get_delete() -> AllResult<()> {
+        let storage = S3Storage::from_env()?;
+
+        storage
+            .put("bets/1/2", Bytes::from_static(b"proof"))
+            .await?;
+        assert_eq!(storage.get("bets/1/2").await?, Bytes::from_static(b"proof"));
+        storage.delete("bets/1/2").await?;
+        assert!(storage.get("bets/1/2").await.is_err());
+
+        Ok(())
+    }
+}
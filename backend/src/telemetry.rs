@@ -0,0 +1,196 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    filter::{EnvFilter, Targets},
+    layer::{Context, SubscriberExt},
+    util::SubscriberInitExt,
+    Layer,
+};
+
+use crate::{models::BetError, AllResult};
+
+pub const HTTP_REQUESTS: &str = "http_requests_total";
+pub const HTTP_REQUEST_DURATION: &str = "http_request_duration_seconds";
+
+pub const DB_POOL_CONNECTIONS: &str = "db_pool_connections";
+pub const DB_POOL_IDLE: &str = "db_pool_idle_connections";
+pub const DB_POOL_IN_USE: &str = "db_pool_in_use_connections";
+pub const DB_POOL_ACQUIRE_WAIT: &str = "db_pool_acquire_wait_seconds";
+
+pub const BETS_CREATED: &str = "bets_created_total";
+pub const BET_PARTICIPANTS_JOINED: &str = "bet_participants_joined_total";
+pub const BETS_CLOSED: &str = "bets_closed_total";
+pub const BET_PAYOUTS: &str = "bet_payouts_total";
+pub const BET_PAYOUTS_FAILED: &str = "bet_payouts_failed_total";
+pub const BETS_VOIDED: &str = "bets_voided_total";
+pub const LOGINS_FAILED: &str = "logins_failed_total";
+pub const FAILURES: &str = "failures_total";
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Where sqlx logs how long every `PgPool` acquire waited, see
+/// `pool_options`
+const ACQUIRE_TARGET: &str = "sqlx::pool::acquire";
+
+/// Installs the global logger, which writes to stderr at the level set by
+/// `RUST_LOG`, `info` by default. It also turns the acquire times sqlx logs
+/// into `DB_POOL_ACQUIRE_WAIT`, whatever the level.
+pub fn install_logger() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let acquires = Targets::new().with_target(ACQUIRE_TARGET, LevelFilter::TRACE);
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(filter),
+        )
+        .with(AcquireWait.with_filter(acquires))
+        .init();
+}
+
+/// Pool options that log the time taken by every acquire, so the logger
+/// installed by `install_logger` can record it
+pub fn pool_options() -> PgPoolOptions {
+    PgPoolOptions::new().acquire_time_level(log::LevelFilter::Trace)
+}
+
+/// Records the `aquired_after_secs` of sqlx's acquire events
+struct AcquireWait;
+
+impl<S: Subscriber> Layer<S> for AcquireWait {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let mut acquired_after = AcquiredAfter(None);
+        event.record(&mut acquired_after);
+        if let Some(seconds) = acquired_after.0 {
+            histogram!(DB_POOL_ACQUIRE_WAIT).record(seconds);
+        }
+    }
+}
+
+struct AcquiredAfter(Option<f64>);
+
+impl Visit for AcquiredAfter {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        // sqlx spells it this way
+        if field.name() == "aquired_after_secs" {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+}
+
+/// Installs the global Prometheus recorder. Must be called once, before the
+/// router starts serving requests.
+pub fn install_recorder() -> AllResult<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full(HTTP_REQUEST_DURATION.into()), LATENCY_BUCKETS)?
+        .set_buckets_for_metric(Matcher::Full(DB_POOL_ACQUIRE_WAIT.into()), LATENCY_BUCKETS)?
+        .install_recorder()?;
+
+    describe_counter!(HTTP_REQUESTS, "HTTP requests handled, by route");
+    describe_histogram!(
+        HTTP_REQUEST_DURATION,
+        metrics::Unit::Seconds,
+        "HTTP request latency, by route"
+    );
+    describe_gauge!(DB_POOL_CONNECTIONS, "Open database connections");
+    describe_gauge!(DB_POOL_IDLE, "Idle database connections");
+    describe_gauge!(DB_POOL_IN_USE, "Database connections checked out");
+    describe_histogram!(
+        DB_POOL_ACQUIRE_WAIT,
+        metrics::Unit::Seconds,
+        "Time taken to check out a database connection"
+    );
+    describe_counter!(BETS_CREATED, "Bets created");
+    describe_counter!(BET_PARTICIPANTS_JOINED, "Users that joined a bet");
+    describe_counter!(BETS_CLOSED, "Bets closed to new participants");
+    describe_counter!(BET_PAYOUTS, "Bets paid out");
+    describe_counter!(
+        BET_PAYOUTS_FAILED,
+        "Bet payouts that failed with a server or database error"
+    );
+    describe_counter!(BETS_VOIDED, "Bets called off");
+    describe_counter!(
+        LOGINS_FAILED,
+        "Logins with an unknown user or a wrong password"
+    );
+    describe_counter!(
+        FAILURES,
+        "Failures with no request to report them to, by task"
+    );
+
+    Ok(handle)
+}
+
+/// Records a request count and latency for every matched route.
+pub(crate) async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_default();
+    let method = request.method().to_string();
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    counter!(HTTP_REQUESTS, &labels).increment(1);
+    histogram!(HTTP_REQUEST_DURATION, &labels).record(start.elapsed().as_secs_f64());
+
+    response
+}
+
+pub(crate) async fn render(
+    State(pool): State<PgPool>,
+    State(handle): State<PrometheusHandle>,
+) -> String {
+    record_pool_stats(&pool);
+    handle.run_upkeep();
+    handle.render()
+}
+
+fn record_pool_stats(pool: &PgPool) {
+    let size = pool.size();
+    let idle = pool.num_idle() as u32;
+    gauge!(DB_POOL_CONNECTIONS).set(size);
+    gauge!(DB_POOL_IDLE).set(idle);
+    gauge!(DB_POOL_IN_USE).set(size.saturating_sub(idle));
+}
+
+/// Counts a payout, or its failure unless the bet couldn't be paid out as
+/// asked, like a stale bet or an unknown option
+pub(crate) fn record_payout<T>(result: &AllResult<T>) {
+    match result {
+        Ok(_) => counter!(BET_PAYOUTS).increment(1),
+        Err(error) if error.is::<BetError>() => {}
+        Err(_) => counter!(BET_PAYOUTS_FAILED).increment(1),
+    }
+}
+
+/// Logs a failure that no request reports back, like a scheduler step or a
+/// cleanup after an error, and counts it by `task`
+pub(crate) fn report_failure(task: &'static str, message: std::fmt::Arguments) {
+    tracing::error!(task, "{message}");
+    counter!(FAILURES, "task" => task).increment(1);
+}
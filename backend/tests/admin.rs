@@ -0,0 +1,248 @@
+use std::process::Command;
+
+use bet_with_friends::{AllResult, Bet, BetSettings, BetStatus, User};
+use serde_json::Value;
+use sqlx::PgPool;
+
+/// Runs `bwf-admin` against the test database and parses its JSON output
+fn admin(pool: &PgPool, args: &[&str]) -> AllResult<Value> {
+    let output = Command::new(env!("CARGO_BIN_EXE_bwf-admin"))
+        .env("DATABASE_URL", database_url(pool)?)
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+fn database_url(pool: &PgPool) -> AllResult<String> {
+    let base_url = dotenvy::var("DATABASE_URL")?;
+    let (server, _) = base_url.rsplit_once('/').ok_or("invalid DATABASE_URL")?;
+    let database = pool
+        .connect_options()
+        .get_database()
+        .ok_or("test pool has no database")?
+        .to_owned();
+    Ok(format!("{server}/{database}"))
+}
+
+async fn create_user(pool: &PgPool, username: &str) -> AllResult<User> {
+    User::new(
+        pool,
+        username.into(),
+        format!("{username}@mail.com"),
+        "pass123".into(),
+    )
+    .await
+}
+
+#[sqlx::test]
+async fn create_and_suspend_user(pool: PgPool) -> AllResult<()> {
+    let created = admin(
+        &pool,
+        &[
+            "create-user",
+            "--username",
+            "bob",
+            "--email",
+            "bob@mail.com",
+            "--password",
+            "bobpass",
+        ],
+    )?;
+    assert_eq!(created["username"], "bob");
+    assert!(created["suspended_at"].is_null());
+
+    let bob = User::read_from_name(&pool, "bob").await?;
+    assert_eq!(created["id"], bob.id);
+    assert_eq!(bob.score(&pool).await?.total_wins, 0);
+
+    let suspended = admin(&pool, &["suspend-user", "--username", "bob"])?;
+    assert!(suspended["suspended_at"].is_string());
+    assert!(User::read_from_name(&pool, "bob").await?.is_suspended());
+
+    admin(&pool, &["unsuspend-user", "--username", "bob"])?;
+    assert!(!User::read_from_name(&pool, "bob").await?.is_suspended());
+
+    assert!(admin(&pool, &["suspend-user", "--username", "nobody"]).is_err());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn list_stuck_bets(pool: PgPool) -> AllResult<()> {
+    let bob = create_user(&pool, "bob").await?;
+
+    let now = sqlx::types::chrono::Local::now().naive_local();
+    let expired = bob
+        .create_timed_bet(&pool, "expired".into(), now - chrono::TimeDelta::hours(1))
+        .await?;
+    bob.create_timed_bet(&pool, "open".into(), now + chrono::TimeDelta::hours(1))
+        .await?;
+    let mut finished = bob.create_timeless_bet(&pool, "finished".into()).await?;
+    finished.close(&pool).await?;
+
+    let stuck = admin(&pool, &["stuck-bets"])?;
+    let ids: Vec<_> = stuck
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bet| &bet["id"])
+        .collect();
+    assert_eq!(ids, vec![expired.id]);
+
+    let stuck = admin(&pool, &["stuck-bets", "--older-than-hours", "0"])?;
+    let ids: Vec<_> = stuck
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bet| &bet["id"])
+        .collect();
+    assert_eq!(ids, vec![expired.id, finished.id]);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn close_and_settle_bets(pool: PgPool) -> AllResult<()> {
+    let bob = create_user(&pool, "bob").await?;
+    let john = create_user(&pool, "john").await?;
+
+    let closing = bob.create_timeless_bet(&pool, "closing".into()).await?;
+    let closed = admin(&pool, &["close-bet", "--bet-id", &closing.id.to_string()])?;
+    assert_eq!(closed["status"], "Finished");
+    assert!(admin(&pool, &["close-bet", "--bet-id", &closing.id.to_string()]).is_err());
+
+    let settling = bob.create_timeless_bet(&pool, "settling".into()).await?;
+    let options = settling.options(&pool).await?;
+    let (yes, no) = (options[0].id, options[1].id);
+    bob.particpate_in_bet(&pool, &settling, 10, yes).await?;
+    john.particpate_in_bet(&pool, &settling, 20, no).await?;
+
+    let bet_id = settling.id.to_string();
+    let settled = admin(
+        &pool,
+        &[
+            "settle-bet",
+            "--bet-id",
+            &bet_id,
+            "--winning-option-id",
+            &no.to_string(),
+        ],
+    )?;
+    assert_eq!(settled["status"], "PayedOut");
+    assert_eq!(settled["winning_option_id"], no);
+    assert_eq!(
+        Bet::read_by_id(&pool, settling.id).await?.status,
+        BetStatus::PayedOut
+    );
+    assert_eq!(john.score(&pool).await?.points_earned, 20);
+    assert_eq!(bob.score(&pool).await?.total_losses, 1);
+
+    assert!(admin(
+        &pool,
+        &[
+            "settle-bet",
+            "--bet-id",
+            &bet_id,
+            "--winning-option-id",
+            &yes.to_string()
+        ]
+    )
+    .is_err());
+    assert_eq!(john.score(&pool).await?.total_wins, 1);
+
+    let over_under = bob
+        .create_over_under_bet(&pool, "late".into(), None, 4.5, &BetSettings::default())
+        .await?;
+    let over = over_under.options(&pool).await?[0].id;
+    john.particpate_in_bet(&pool, &over_under, 5, over).await?;
+    let bet_id = over_under.id.to_string();
+    assert!(admin(&pool, &["settle-bet", "--bet-id", &bet_id]).is_err());
+    let settled = admin(
+        &pool,
+        &["settle-bet", "--bet-id", &bet_id, "--actual-value", "4.5"],
+    )?;
+    assert_eq!(settled["status"], "PayedOut");
+    assert!(settled["winning_option_id"].is_null());
+    assert_eq!(john.score(&pool).await?.points_earned, 20);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn void_paid_out_bet(pool: PgPool) -> AllResult<()> {
+    let bob = create_user(&pool, "bob").await?;
+
+    let mut bet = bob.create_timeless_bet(&pool, "bet".into()).await?;
+    let yes = bet.options(&pool).await?[0].id;
+    bob.particpate_in_bet(&pool, &bet, 10, yes).await?;
+    bet.close(&pool).await?;
+    bet.payout(&pool, yes).await?;
+    assert_eq!(bob.score(&pool).await?.points_earned, 10);
+
+    let voided = admin(&pool, &["void-bet", "--bet-id", &bet.id.to_string()])?;
+    assert_eq!(voided["status"], "Cancelled");
+    assert_eq!(bob.score(&pool).await?.points_earned, 0);
+    assert_eq!(bob.score(&pool).await?.total_wins, 0);
+
+    assert!(admin(&pool, &["void-bet", "--bet-id", &bet.id.to_string()]).is_err());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn recompute_scores(pool: PgPool) -> AllResult<()> {
+    let bob = create_user(&pool, "bob").await?;
+    let john = create_user(&pool, "john").await?;
+
+    let mut bet = bob.create_timeless_bet(&pool, "bet".into()).await?;
+    let options = bet.options(&pool).await?;
+    let (yes, no) = (options[0].id, options[1].id);
+    bob.particpate_in_bet(&pool, &bet, 10, yes).await?;
+    john.particpate_in_bet(&pool, &bet, 20, no).await?;
+    bet.close(&pool).await?;
+    bet.payout(&pool, yes).await?;
+
+    sqlx::query!("UPDATE scores SET total_wins = 5, total_losses = 5, points_earned = 500")
+        .execute(&pool)
+        .await?;
+
+    let scores = admin(&pool, &["recompute-scores"])?;
+    assert_eq!(scores.as_array().unwrap().len(), 2);
+
+    let bob_score = bob.score(&pool).await?;
+    assert_eq!(bob_score.total_wins, 1);
+    assert_eq!(bob_score.total_losses, 0);
+    assert_eq!(bob_score.points_earned, 10);
+
+    let john_score = john.score(&pool).await?;
+    assert_eq!(john_score.total_wins, 0);
+    assert_eq!(john_score.total_losses, 1);
+    assert_eq!(john_score.points_earned, 0);
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = false)]
+async fn run_and_revert_migrations(pool: PgPool) -> AllResult<()> {
+    let run = admin(&pool, &["migrate", "run"])?;
+    let applied = run["applied"].as_array().unwrap().clone();
+    let latest = applied.last().unwrap().as_i64().unwrap();
+    create_user(&pool, "bob").await?;
+
+    let reverted = admin(&pool, &["migrate", "revert"])?;
+    assert_eq!(reverted["reverted"], latest);
+    let is_applied: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM _sqlx_migrations WHERE version = $1)")
+            .bind(latest)
+            .fetch_one(&pool)
+            .await?;
+    assert!(!is_applied);
+
+    let run = admin(&pool, &["migrate", "run"])?;
+    assert_eq!(run["applied"].as_array().unwrap(), &applied);
+
+    Ok(())
+}
@@ -0,0 +1,1389 @@
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use bet_with_friends::{
+    create_router,
+    storage::{LocalStorage, Storage},
+    telemetry, AllResult, Bet, BetFilter, User,
+};
+use http_body_util::BodyExt;
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::sync::{Arc, OnceLock};
+use tower::ServiceExt;
+
+fn router(pool: PgPool) -> Router {
+    let storage = LocalStorage::new(std::env::temp_dir().join("bwf-attachments"));
+    router_with_storage(pool, Arc::new(storage))
+}
+
+fn router_with_storage(pool: PgPool, storage: Arc<dyn Storage>) -> Router {
+    // The logger and recorder are global, so every test shares the ones
+    // installed here
+    static METRICS: OnceLock<PrometheusHandle> = OnceLock::new();
+    let metrics = METRICS.get_or_init(|| {
+        telemetry::install_logger();
+        telemetry::install_recorder().unwrap()
+    });
+    create_router(pool, metrics.clone(), storage)
+}
+
+async fn send(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    body: Value,
+) -> AllResult<(StatusCode, Value)> {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))?;
+    let response = router.clone().oneshot(request).await?;
+    let status = response.status();
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok((status, serde_json::from_slice(&body).unwrap_or(Value::Null)))
+}
+
+#[sqlx::test]
+async fn create_user_and_bet(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+
+    let (status, user) = send(
+        &router,
+        Method::POST,
+        "/user",
+        json!({ "username": "bob", "email": "bob@mail.com", "password": "bobpass" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(user["username"], "bob");
+    assert!(user.get("password_hash").is_none());
+
+    let (status, bet) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "test bet" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bet["status"], "Active");
+
+    let bob = User::read_from_name(&pool, "bob").await?;
+    assert_eq!(
+        bob.bets_created(&pool, Some(&bob), &BetFilter::default())
+            .await?
+            .len(),
+        1
+    );
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "nobody", "description": "test bet" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn bet_with_options(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+
+    let (status, bet) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "chili", "options": ["Alice", "Bob", "Carol"] }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    let labels: Vec<_> = bet["options"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|option| option["label"].clone())
+        .collect();
+    assert_eq!(labels, vec!["Alice", "Bob", "Carol"]);
+    let carol = bet["options"][2]["id"].clone();
+
+    let (status, read_bet) = send(
+        &router,
+        Method::GET,
+        &format!("/bet/{}", bet["id"]),
+        json!({}),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(read_bet["options"], bet["options"]);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "bob", "bet_id": bet["id"], "amount": 10, "option_id": carol }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, yes_no) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "yes or no" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(yes_no["options"][0]["label"], "Yes");
+    assert_eq!(yes_no["options"][1]["label"], "No");
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "bob", "bet_id": yes_no["id"], "amount": 10, "option_id": carol }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "lonely", "options": ["Alice"] }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn over_under_bet(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    let bob = User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "late", "line": 4.5, "options": ["A", "B"] }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, bet) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "How late is the 8:15?", "line": 4.5 }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bet["kind"], "OverUnder");
+    assert_eq!(bet["line"], 4.5);
+    assert_eq!(bet["options"][0]["label"], "Over");
+    let under = bet["options"][1]["id"].clone();
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "bob", "bet_id": bet["id"], "amount": 10, "option_id": under }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/close",
+        json!({ "username": "bob", "bet_id": bet["id"] }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/payout",
+        json!({ "username": "bob", "bet_id": bet["id"], "winning_option_id": under }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, settled) = send(
+        &router,
+        Method::POST,
+        "/bet/settle",
+        json!({ "username": "bob", "bet_id": bet["id"], "actual_value": 3 }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(settled["status"], "PayedOut");
+    assert_eq!(settled["actual_value"], 3.0);
+    assert_eq!(settled["winning_option_id"], under);
+    assert_eq!(bob.score(&pool).await?.points_earned, 10);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn creator_voids_bet(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    let bob = User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    User::new(
+        &pool,
+        "john".into(),
+        "john@mail.com".into(),
+        "johnpass".into(),
+    )
+    .await?;
+    let mut bet = bob.create_timeless_bet(&pool, "bet".into()).await?;
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/void",
+        json!({ "username": "john", "bet_id": bet.id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, voided) = send(
+        &router,
+        Method::POST,
+        "/bet/void",
+        json!({ "username": "bob", "bet_id": bet.id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(voided["status"], "Cancelled");
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/void",
+        json!({ "username": "bob", "bet_id": bet.id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    bet = bob.create_timeless_bet(&pool, "paid out".into()).await?;
+    bet.close(&pool).await?;
+    let yes = bet.options(&pool).await?[0].id;
+    bet.payout(&pool, yes).await?;
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/void",
+        json!({ "username": "bob", "bet_id": bet.id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn participants_vote_and_dispute(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    let bob = User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    let john = User::new(
+        &pool,
+        "john".into(),
+        "john@mail.com".into(),
+        "johnpass".into(),
+    )
+    .await?;
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "bet", "line": 1.5, "vote": { "quorum": 1, "dispute_window_seconds": 60 } }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, created) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "bet", "vote": { "quorum": 1, "dispute_window_seconds": 60 } }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["resolution"], "Vote");
+    let mut bet = Bet::read_by_id(&pool, created["id"].as_i64().unwrap() as i32).await?;
+    let yes = created["options"][0]["id"].as_i64().unwrap();
+    let no = created["options"][1]["id"].as_i64().unwrap();
+    bob.particpate_in_bet(&pool, &bet, 10, yes as i32).await?;
+    john.particpate_in_bet(&pool, &bet, 10, no as i32).await?;
+    bet.close(&pool).await?;
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/payout",
+        json!({ "username": "bob", "bet_id": bet.id, "winning_option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, voted) = send(
+        &router,
+        Method::POST,
+        "/bet/vote",
+        json!({ "username": "bob", "bet_id": bet.id, "option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(voted["proposed_option_id"], yes);
+
+    let (status, disputed) = send(
+        &router,
+        Method::POST,
+        "/bet/dispute",
+        json!({ "username": "john", "bet_id": bet.id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(disputed["disputed_by"], john.id);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/payout",
+        json!({ "username": "bob", "bet_id": bet.id, "winning_option_id": no }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn arbiter_manages_bet(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    let bob = User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    User::new(
+        &pool,
+        "jane".into(),
+        "jane@mail.com".into(),
+        "janepass".into(),
+    )
+    .await?;
+    let bet = bob.create_timeless_bet(&pool, "bet".into()).await?;
+    let yes = bet.options(&pool).await?[0].id;
+
+    let (status, named) = send(
+        &router,
+        Method::POST,
+        "/bet/arbiter",
+        json!({ "username": "bob", "bet_id": bet.id, "arbiter": "jane" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(named["arbiter_status"], "Pending");
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "jane", "bet_id": bet.id, "amount": 10, "option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, accepted) = send(
+        &router,
+        Method::POST,
+        "/bet/arbiter/accept",
+        json!({ "username": "jane", "bet_id": bet.id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(accepted["arbiter_status"], "Accepted");
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/close",
+        json!({ "username": "bob", "bet_id": bet.id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/close",
+        json!({ "username": "jane", "bet_id": bet.id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, paid_out) = send(
+        &router,
+        Method::POST,
+        "/bet/payout",
+        json!({ "username": "jane", "bet_id": bet.id, "winning_option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(paid_out["status"], "PayedOut");
+
+    // The creator can still call off a bet the arbiter manages
+    let other = bob.create_timeless_bet(&pool, "other".into()).await?;
+    for (path, username) in [("/bet/arbiter", "bob"), ("/bet/arbiter/accept", "jane")] {
+        let (status, _) = send(
+            &router,
+            Method::POST,
+            path,
+            json!({ "username": username, "bet_id": other.id, "arbiter": "jane" }),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, voided) = send(
+        &router,
+        Method::POST,
+        "/bet/void",
+        json!({ "username": "bob", "bet_id": other.id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(voided["status"], "Cancelled");
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn private_bet_invitations(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    let bob = User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    let john = User::new(
+        &pool,
+        "john".into(),
+        "john@mail.com".into(),
+        "johnpass".into(),
+    )
+    .await?;
+    bob.send_friend_request(&pool, &john).await?;
+    john.accept_friend_request(&pool, &bob).await?;
+
+    let (status, created) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "private", "visibility": "Private" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["visibility"], "Private");
+    let bet_id = created["id"].as_i64().unwrap();
+    let yes = created["options"][0]["id"].as_i64().unwrap();
+
+    let (status, _) = send(&router, Method::GET, &format!("/bet/{bet_id}"), json!({})).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let john_url = format!("/bet/{bet_id}?username=john");
+    let (status, _) = send(&router, Method::GET, &john_url, json!({})).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, invitation) = send(
+        &router,
+        Method::POST,
+        "/bet/invite",
+        json!({ "username": "bob", "bet_id": bet_id, "invitee": "john" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(invitation["status"], "Invited");
+    let (status, _) = send(&router, Method::GET, &john_url, json!({})).await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "john", "bet_id": bet_id, "amount": 10, "option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/invitation/accept",
+        json!({ "username": "john", "bet_id": bet_id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "john", "bet_id": bet_id, "amount": 10, "option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn discover_friends_bets(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    let bob = User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    let john = User::new(
+        &pool,
+        "john".into(),
+        "john@mail.com".into(),
+        "johnpass".into(),
+    )
+    .await?;
+    User::new(
+        &pool,
+        "jane".into(),
+        "jane@mail.com".into(),
+        "janepass".into(),
+    )
+    .await?;
+    bob.send_friend_request(&pool, &john).await?;
+    john.accept_friend_request(&pool, &bob).await?;
+
+    let (_, public) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "public" }),
+    )
+    .await?;
+    assert_eq!(public["visibility"], "Public");
+    let ids = |bets: Value| -> Vec<i64> {
+        bets.as_array()
+            .unwrap()
+            .iter()
+            .map(|bet| bet["id"].as_i64().unwrap())
+            .collect()
+    };
+    let public_id = public["id"].as_i64().unwrap();
+    let (_, friends) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "friends", "visibility": "Friends" }),
+    )
+    .await?;
+    let friends_id = friends["id"].as_i64().unwrap();
+
+    let (status, bets) = send(&router, Method::GET, "/bets?username=john", json!({})).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(bets), [public_id, friends_id]);
+    let (_, bets) = send(&router, Method::GET, "/bets?username=jane", json!({})).await?;
+    assert_eq!(ids(bets), [public_id]);
+    let (_, bets) = send(&router, Method::GET, "/bets?status=Finished", json!({})).await?;
+    assert!(ids(bets).is_empty());
+
+    let (_, bets) = send(
+        &router,
+        Method::GET,
+        "/user/bets",
+        json!({ "username": "bob", "viewer": "jane" }),
+    )
+    .await?;
+    assert_eq!(ids(bets), [public_id]);
+
+    let yes = friends["options"][0]["id"].as_i64().unwrap();
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "jane", "bet_id": friends["id"], "amount": 10, "option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn edit_bet_after_join(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    User::new(
+        &pool,
+        "john".into(),
+        "john@mail.com".into(),
+        "johnpass".into(),
+    )
+    .await?;
+
+    let (_, bet) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "Will it rian?" }),
+    )
+    .await?;
+    let bet_id = bet["id"].as_i64().unwrap();
+    let (status, edited) = send(
+        &router,
+        Method::POST,
+        "/bet/edit",
+        json!({ "username": "bob", "bet_id": bet_id, "description": "Will it rain?" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(edited["description"], "Will it rain?");
+    assert_eq!(edited["version"], 1);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/edit",
+        json!({ "username": "john", "bet_id": bet_id, "description": "mine now" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let yes = bet["options"][0]["id"].as_i64().unwrap();
+    send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "john", "bet_id": bet_id, "amount": 10, "option_id": yes }),
+    )
+    .await?;
+    let (status, edited) = send(
+        &router,
+        Method::POST,
+        "/bet/edit",
+        json!({ "username": "bob", "bet_id": bet_id, "options": ["Rain", "Dry"] }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(edited["version"], 2);
+    assert_eq!(edited["options"][0]["label"], "Rain");
+
+    let (status, versions) = send(
+        &router,
+        Method::GET,
+        &format!("/bet/{bet_id}/versions"),
+        json!({}),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(versions[0]["options"], json!(["Yes", "No"]));
+    assert_eq!(versions[1]["options"], json!(["Rain", "Dry"]));
+
+    let (status, participant) = send(
+        &router,
+        Method::POST,
+        "/bet/confirm",
+        json!({ "username": "john", "bet_id": bet_id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(participant["confirmed_version"], 2);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn change_position(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+
+    let (_, bet) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "description" }),
+    )
+    .await?;
+    let bet_id = bet["id"].as_i64().unwrap();
+    let yes = bet["options"][0]["id"].as_i64().unwrap();
+    let no = bet["options"][1]["id"].as_i64().unwrap();
+    send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "bob", "bet_id": bet_id, "amount": 10, "option_id": yes }),
+    )
+    .await?;
+
+    let (status, participant) = send(
+        &router,
+        Method::POST,
+        "/bet/switch",
+        json!({ "username": "bob", "bet_id": bet_id, "option_id": no }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(participant["option_id"], no);
+    let (status, participant) = send(
+        &router,
+        Method::POST,
+        "/bet/stake",
+        json!({ "username": "bob", "bet_id": bet_id, "amount": 20 }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(participant["bet_amount"], 20);
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/withdraw",
+        json!({ "username": "bob", "bet_id": bet_id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/stake",
+        json!({ "username": "bob", "bet_id": bet_id, "amount": 30 }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, changes) = send(
+        &router,
+        Method::GET,
+        &format!("/bet/{bet_id}/changes"),
+        json!({}),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    let actions: Vec<_> = changes
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| change["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, ["Joined", "Switched", "Restaked", "Withdrew"]);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn recurring_template(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    User::new(
+        &pool,
+        "john".into(),
+        "john@mail.com".into(),
+        "johnpass".into(),
+    )
+    .await?;
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/template",
+        json!({
+            "username": "bob",
+            "description": "Who wins Sunday's match?",
+            "recurrence": "Cron",
+            "cron": "every sunday",
+            "starts_at": "2030-01-01T00:00:00",
+            "cutoff_seconds": 3600
+        }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, template) = send(
+        &router,
+        Method::POST,
+        "/template",
+        json!({
+            "username": "bob",
+            "description": "Who wins Sunday's match?",
+            "options": ["Home", "Away"],
+            "recurrence": "Cron",
+            "cron": "0 0 18 * * Sun",
+            "starts_at": "2030-01-01T00:00:00",
+            "cutoff_seconds": 3600
+        }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    // The first Sunday of 2030
+    assert_eq!(template["next_run_at"], "2030-01-06T18:00:00");
+    let template_id = template["id"].as_i64().unwrap();
+
+    let (status, templates) = send(
+        &router,
+        Method::GET,
+        "/user/templates",
+        json!({ "username": "bob" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(templates[0]["id"], template_id);
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/template/stop",
+        json!({ "username": "john", "template_id": template_id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, template) = send(
+        &router,
+        Method::POST,
+        "/template/stop",
+        json!({ "username": "bob", "template_id": template_id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(template["next_run_at"].is_null());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn categories_and_tags(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+
+    let (status, categories) = send(&router, Method::GET, "/categories", json!({})).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(categories
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|category| category["name"] == "Sports"));
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "finals", "category": "Knitting" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (_, bets) = send(
+        &router,
+        Method::GET,
+        "/user/bets",
+        json!({ "username": "bob" }),
+    )
+    .await?;
+    assert!(bets.as_array().unwrap().is_empty());
+
+    let (status, finals) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({
+            "username": "bob",
+            "description": "finals",
+            "category": "Sports",
+            "tags": ["NBA", "finals"]
+        }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(finals["tags"], json!(["nba", "finals"]));
+    let finals_id = finals["id"].as_i64().unwrap();
+    let (_, election) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "election" }),
+    )
+    .await?;
+    let election_id = election["id"].as_i64().unwrap();
+
+    let (status, election) = send(
+        &router,
+        Method::POST,
+        "/bet/labels",
+        json!({ "username": "bob", "bet_id": election_id, "category": "Politics", "tags": ["2030"] }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(election["category_id"].is_i64());
+
+    let ids = |bets: Value| -> Vec<i64> {
+        bets.as_array()
+            .unwrap()
+            .iter()
+            .map(|bet| bet["id"].as_i64().unwrap())
+            .collect()
+    };
+    let (_, bets) = send(&router, Method::GET, "/bets?category=Sports", json!({})).await?;
+    assert_eq!(ids(bets), [finals_id]);
+    let (_, bets) = send(&router, Method::GET, "/bets?tag=2030", json!({})).await?;
+    assert_eq!(ids(bets), [election_id]);
+    let (_, bets) = send(
+        &router,
+        Method::GET,
+        "/user/bets",
+        json!({ "username": "bob", "category": "Politics" }),
+    )
+    .await?;
+    assert_eq!(ids(bets), [election_id]);
+
+    let (status, scores) = send(
+        &router,
+        Method::GET,
+        "/user/score/categories",
+        json!({ "username": "bob" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(scores.as_array().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn search_bets(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    for (description, visibility) in [
+        ("Will the printer jam today?", "Public"),
+        ("Who broke the printer?", "Private"),
+    ] {
+        send(
+            &router,
+            Method::POST,
+            "/bet",
+            json!({ "username": "bob", "description": description, "visibility": visibility }),
+        )
+        .await?;
+    }
+
+    let (status, matches) = send(&router, Method::GET, "/bets/search?q=printer", json!({})).await?;
+    assert_eq!(status, StatusCode::OK);
+    let matches = matches.as_array().unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0]["description"], "Will the printer jam today?");
+    assert_eq!(matches[0]["snippet"], "Will the <b>printer</b> jam today?");
+
+    let (_, matches) = send(
+        &router,
+        Method::GET,
+        "/bets/search?q=printer&username=bob",
+        json!({}),
+    )
+    .await?;
+    assert_eq!(matches.as_array().unwrap().len(), 2);
+
+    let (status, _) = send(&router, Method::GET, "/bets/search?q=%20", json!({})).await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn challenge_friend(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    let bob = User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    let john = User::new(
+        &pool,
+        "john".into(),
+        "john@mail.com".into(),
+        "johnpass".into(),
+    )
+    .await?;
+    bob.send_friend_request(&pool, &john).await?;
+    john.accept_friend_request(&pool, &bob).await?;
+
+    let (status, challenge) = send(
+        &router,
+        Method::POST,
+        "/challenge",
+        json!({
+            "username": "bob",
+            "friend": "john",
+            "description": "I bet you 20 points the printer jams today",
+            "side": "Yes",
+            "amount": 20
+        }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(challenge["status"], "Pending");
+    assert_eq!(challenge["bet"]["status"], "Pending");
+    let bet_id = challenge["bet_id"].as_i64().unwrap();
+    let no = challenge["bet"]["options"][1]["id"].clone();
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/challenge/accept",
+        json!({ "username": "bob", "bet_id": bet_id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, challenge) = send(
+        &router,
+        Method::POST,
+        "/challenge/accept",
+        json!({ "username": "john", "bet_id": bet_id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(challenge["status"], "Accepted");
+    assert_eq!(challenge["bet"]["status"], "Active");
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/challenge/decline",
+        json!({ "username": "john", "bet_id": bet_id }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let john_side = &john.bets_joined(&pool, Some(&john)).await?[0].1;
+    assert_eq!(json!(john_side.option_id), no);
+    assert_eq!(john_side.bet_amount, 20);
+
+    let (status, challenges) = send(
+        &router,
+        Method::GET,
+        "/user/challenges",
+        json!({ "username": "john" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(challenges[0]["bet_id"], bet_id);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn stake_limits(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    User::new(
+        &pool,
+        "john".into(),
+        "john@mail.com".into(),
+        "johnpass".into(),
+    )
+    .await?;
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "finals", "min_stake": 50, "max_stake": 5 }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, bet) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({
+            "username": "bob",
+            "description": "finals",
+            "min_stake": 5,
+            "max_stake": 50,
+            "max_participants": 1
+        }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bet["max_participants"], 1);
+    let yes = bet["options"][0]["id"].clone();
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "bob", "bet_id": bet["id"], "amount": 4, "option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "bob", "bet_id": bet["id"], "amount": 10, "option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "john", "bet_id": bet["id"], "amount": 10, "option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, bet) = send(
+        &router,
+        Method::POST,
+        "/bet/limits",
+        json!({ "username": "bob", "bet_id": bet["id"], "max_participants": 2 }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bet["min_stake"], json!(null));
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/bet/join",
+        json!({ "username": "john", "bet_id": bet["id"], "amount": 100, "option_id": yes }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn attach_evidence(pool: PgPool) -> AllResult<()> {
+    let root = std::env::temp_dir().join(format!("bwf-router-evidence-{}", std::process::id()));
+    let router = router_with_storage(pool.clone(), Arc::new(LocalStorage::new(&root)));
+    User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    let (_, bet) = send(
+        &router,
+        Method::POST,
+        "/bet",
+        json!({ "username": "bob", "description": "rain" }),
+    )
+    .await?;
+    let bet_id = bet["id"].as_i64().unwrap();
+    send(
+        &router,
+        Method::POST,
+        "/bet/close",
+        json!({ "username": "bob", "bet_id": bet_id }),
+    )
+    .await?;
+
+    let upload = |content_type: &str, body: &'static [u8]| {
+        Request::builder()
+            .method(Method::POST)
+            .uri(format!("/bet/{bet_id}/attachments?username=bob"))
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+    };
+    let png = b"\x89PNG\r\n\x1a\n rest of the image";
+    let response = router.clone().oneshot(upload("image/png", png)?).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await?.to_bytes();
+    let photo: Value = serde_json::from_slice(&body)?;
+    let response = router.clone().oneshot(upload("image/gif", png)?).await?;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let link = b"https://example.com/radar";
+    let response = router
+        .clone()
+        .oneshot(upload("text/uri-list", link)?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (status, attachments) = send(
+        &router,
+        Method::GET,
+        &format!("/bet/{bet_id}/attachments"),
+        json!({}),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(attachments.as_array().unwrap().len(), 2);
+    assert_eq!(attachments[1]["url"], "https://example.com/radar");
+
+    let download = |id: &Value| {
+        Request::builder()
+            .uri(format!("/attachment/{id}"))
+            .body(Body::empty())
+    };
+    let response = router.clone().oneshot(download(&photo["id"])?).await?;
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+    let body = response.into_body().collect().await?.to_bytes();
+    assert_eq!(&body[..], png);
+    let response = router
+        .clone()
+        .oneshot(download(&attachments[1]["id"])?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "https://example.com/radar"
+    );
+
+    tokio::fs::remove_dir_all(root).await?;
+    Ok(())
+}
+
+#[sqlx::test]
+async fn parlays(pool: PgPool) -> AllResult<()> {
+    let router = router(pool.clone());
+    User::new(&pool, "bob".into(), "bob@mail.com".into(), "bobpass".into()).await?;
+    User::new(
+        &pool,
+        "alice".into(),
+        "alice@mail.com".into(),
+        "alicepass".into(),
+    )
+    .await?;
+
+    let mut legs = Vec::new();
+    for description in ["semifinal", "final"] {
+        let (status, bet) = send(
+            &router,
+            Method::POST,
+            "/bet",
+            json!({ "username": "bob", "description": description }),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+        legs.push(json!({ "bet_id": bet["id"], "option_id": bet["options"][0]["id"] }));
+    }
+
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/parlay",
+        json!({ "username": "alice", "stake": 10, "legs": [legs[0]] }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/parlay",
+        json!({
+            "username": "alice",
+            "stake": 10,
+            "legs": [legs[0], { "bet_id": 999, "option_id": 1 }]
+        }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, parlay) = send(
+        &router,
+        Method::POST,
+        "/parlay",
+        json!({ "username": "alice", "stake": 10, "legs": legs }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parlay["status"], "Open");
+    assert_eq!(parlay["legs"].as_array().unwrap().len(), 2);
+
+    for leg in &legs {
+        let (status, _) = send(
+            &router,
+            Method::POST,
+            "/bet/close",
+            json!({ "username": "bob", "bet_id": leg["bet_id"] }),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(
+            &router,
+            Method::POST,
+            "/bet/payout",
+            json!({
+                "username": "bob",
+                "bet_id": leg["bet_id"],
+                "winning_option_id": leg["option_id"]
+            }),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, parlays) = send(
+        &router,
+        Method::GET,
+        "/user/parlays",
+        json!({ "username": "alice" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parlays[0]["id"], parlay["id"]);
+    assert_eq!(parlays[0]["status"], "Won");
+    assert_eq!(parlays[0]["points"], 20);
+
+    let (_, score) = send(
+        &router,
+        Method::GET,
+        "/user/score",
+        json!({ "username": "alice" }),
+    )
+    .await?;
+    assert_eq!(score["points_earned"], 20);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn metrics_count_requests_by_route(pool: PgPool) -> AllResult<()> {
+    let pool = telemetry::pool_options()
+        .connect_with((*pool.connect_options()).clone())
+        .await?;
+    let router = router(pool);
+    send(
+        &router,
+        Method::POST,
+        "/user",
+        json!({ "username": "bob", "email": "bob@mail.com", "password": "bobpass" }),
+    )
+    .await?;
+    let (status, _) = send(
+        &router,
+        Method::POST,
+        "/user/login",
+        json!({ "username": "bob", "password": "wrong" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, user) = send(
+        &router,
+        Method::POST,
+        "/user/login",
+        json!({ "username": "bob", "password": "bobpass" }),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(user["username"], "bob");
+    let (status, _) = send(&router, Method::GET, "/bet/999", json!({})).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let request = Request::builder().uri("/metrics").body(Body::empty())?;
+    let response = router.clone().oneshot(request).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await?.to_bytes();
+    let metrics = String::from_utf8(body.to_vec())?;
+    let requests: Vec<_> = metrics
+        .lines()
+        .filter(|line| line.starts_with(telemetry::HTTP_REQUESTS))
+        .collect();
+    assert!(requests
+        .iter()
+        .any(|line| line.contains(r#"method="POST""#) && line.contains(r#"route="/user""#)));
+    assert!(requests
+        .iter()
+        .any(|line| line.contains(r#"route="/bet/{id}""#) && line.contains(r#"status="404""#)));
+    assert!(metrics.contains(telemetry::DB_POOL_CONNECTIONS));
+    assert!(metrics.contains(&format!("{}_count", telemetry::DB_POOL_ACQUIRE_WAIT)));
+    assert!(metrics
+        .lines()
+        .any(|line| line.starts_with(telemetry::LOGINS_FAILED) && !line.ends_with(" 0")));
+    Ok(())
+}